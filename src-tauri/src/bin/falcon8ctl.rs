@@ -0,0 +1,617 @@
+//! Command-line access to Falcon-8 keypads, for scripting and troubleshooting without the GUI.
+//!
+//! Set `FALCON8_SIMULATE` to run against simulated keypads, like the GUI. Nicknames given in the
+//! GUI work with `--device`, and the two share the host-side actions of the profiles they write.
+//!
+//! Exit codes: 0 on success, 1 when `diff` finds differences, 2 for bad arguments or files, 3 when
+//! no keypad matches and 4 when talking to the keypad fails.
+
+use std::{
+    path::PathBuf,
+    process::ExitCode,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand};
+use falcon8touch::{
+    falcon8::{
+        hotplug::{HotplugEvent, HotplugWatcher},
+        protocol::{Direction, KeyBinding, LightingMode, KEY_COUNT},
+        upgrade_profile_file, DeviceSelector, Falcon8Error, MigrationReport, Result,
+    },
+    state::{app_config_dir, AppState, ProfileChanged},
+};
+use serde::Serialize;
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Keypad to use: an index, serial number, port path or nickname.
+    #[arg(short, long, global = true, default_value = "0")]
+    device: DeviceSelector,
+    /// Print JSON instead of text.
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List connected keypads.
+    List,
+    /// Show a keypad's USB details and firmware version.
+    Info,
+    /// Print a profile in the profile file format.
+    Get {
+        /// Profile slot to print, the active one by default.
+        #[arg(long)]
+        slot: Option<u8>,
+    },
+    /// Bind a key of the active profile, e.g. `set-key 1 Ctrl+Shift+F13`.
+    SetKey {
+        /// Key to bind, 1 to 8.
+        #[arg(value_parser = clap::value_parser!(u8).range(1..=KEY_COUNT as i64))]
+        key: u8,
+        /// What the key sends: a key combination, `Media.<name>`, `System.<name>`,
+        /// `Mouse.<button>`, `Macro.<slot>` or `None`.
+        binding: KeyBinding,
+    },
+    /// Show the active profile's lighting, or change the settings given.
+    Lighting {
+        /// off, static, breathing, wave or reactive.
+        #[arg(long)]
+        mode: Option<LightingMode>,
+        #[arg(long)]
+        speed: Option<u8>,
+        /// left_to_right or right_to_left.
+        #[arg(long)]
+        direction: Option<Direction>,
+        #[arg(long)]
+        brightness: Option<u8>,
+    },
+    /// Copy profiles between files and the keypad, or switch profiles.
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Save the keypad's whole configuration memory to a file.
+    Dump { file: PathBuf },
+    /// Write a file saved by `dump` back to the keypad.
+    Restore { file: PathBuf },
+    /// Print keypads coming and going and profile switches until interrupted.
+    Monitor,
+    /// Print key presses on the keypad until interrupted.
+    Keys,
+    /// Show how a profile on the keypad differs from a profile file. Exits with 1 if they differ.
+    Diff {
+        file: PathBuf,
+        /// Profile slot to compare, the active one by default.
+        #[arg(long)]
+        slot: Option<u8>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProfileCommand {
+    /// Write a profile file to the keypad. Files from older versions are upgraded as they're
+    /// read, without changing them; see `profile upgrade`.
+    Load {
+        file: PathBuf,
+        /// Profile slot to write, the active one by default.
+        #[arg(long)]
+        slot: Option<u8>,
+    },
+    /// Save a profile from the keypad to a file.
+    Save {
+        file: PathBuf,
+        /// Profile slot to save, the active one by default.
+        #[arg(long)]
+        slot: Option<u8>,
+    },
+    /// Switch the keypad to another profile slot.
+    Activate { slot: u8 },
+    /// Upgrade a profile file from an older version in place, keeping a backup of the original.
+    Upgrade { file: PathBuf },
+}
+
+/// Exit code for commands that ran but found a problem, like differences.
+const EXIT_DIFFERENT: u8 = 1;
+/// Exit code for invalid input, e.g. an unreadable profile file. clap uses it for bad arguments.
+const EXIT_INVALID: u8 = 2;
+/// Exit code when no keypad is connected or none matches `--device`.
+const EXIT_NO_DEVICE: u8 = 3;
+/// Exit code when the keypad can't be opened or stops responding.
+const EXIT_DEVICE: u8 = 4;
+
+/// How often `monitor` checks for profile switches, matching the GUI.
+const PROFILE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+fn exit_code(error: &Falcon8Error) -> u8 {
+    use Falcon8Error::*;
+
+    match error {
+        NotFound | Disconnected | NoMatchingDevice(_) | AmbiguousDevice { .. } => EXIT_NO_DEVICE,
+        PermissionDenied
+        | Busy
+        | Timeout
+        | Descriptor(_)
+        | Usb(_)
+        | ChecksumMismatch { .. }
+        | Protocol(_)
+        | ShortTransfer { .. }
+        | UnsupportedFirmware { .. }
+        | MemoryReadMismatch { .. }
+        | MemoryReadLength { .. }
+        | RestoreMismatch { .. } => EXIT_DEVICE,
+        _ => EXIT_INVALID,
+    }
+}
+
+/// What `--json` prints for commands that change something, saying what they changed.
+#[derive(Serialize)]
+struct Done {
+    ok: bool,
+    /// 1 to 8, like the `set-key` argument.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    binding: Option<KeyBinding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slot: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+}
+
+impl Default for Done {
+    fn default() -> Self {
+        Self {
+            ok: true,
+            key: None,
+            binding: None,
+            slot: None,
+            path: None,
+        }
+    }
+}
+
+fn print_json(value: &impl Serialize) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(value).expect("command output serializes")
+    );
+}
+
+/// Prints `heading`, then what upgrading a profile file changed.
+fn print_migration(heading: &str, migration: &MigrationReport) {
+    println!("{heading}");
+    for change in &migration.changes {
+        println!("  {change}");
+    }
+    if let Some(backup) = &migration.backup {
+        println!("  original kept as {}", backup.display());
+    }
+}
+
+fn hex(color: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", color[0], color[1], color[2])
+}
+
+fn run(cli: &Cli, state: &AppState) -> Result<ExitCode> {
+    let device = &cli.device;
+    let slot_or_active = |slot: Option<u8>| match slot {
+        Some(slot) => Ok(slot),
+        None => state.device(device)?.active_profile(),
+    };
+
+    match &cli.command {
+        Command::List => {
+            let devices = state.list_devices()?;
+            if cli.json {
+                print_json(&devices);
+                return Ok(ExitCode::SUCCESS);
+            }
+            if devices.is_empty() {
+                println!("no keypads connected");
+            }
+            for summary in &devices {
+                let identity = &summary.identity;
+                let mut line = format!(
+                    "{}: {} at {}",
+                    summary.index,
+                    summary.product.as_deref().unwrap_or("Falcon-8"),
+                    identity.port_path
+                );
+                if let Some(serial_number) = &identity.serial_number {
+                    line += &format!(", serial {serial_number}");
+                }
+                if let Some(nickname) = &identity.nickname {
+                    line += &format!(" ({nickname})");
+                }
+                println!("{line}");
+            }
+        }
+        Command::Info => {
+            let info = state.get_device_info(device)?;
+            if cli.json {
+                print_json(&info);
+            } else {
+                println!("{info}");
+            }
+        }
+        Command::Get { slot } => {
+            let profile = state.read_profile(device, slot_or_active(*slot)?)?;
+            if cli.json {
+                print_json(&profile);
+            } else {
+                print!("{}", profile.to_toml());
+            }
+        }
+        Command::SetKey { key, binding } => {
+            state.set_key_binding(device, usize::from(*key) - 1, *binding)?;
+            if cli.json {
+                print_json(&Done {
+                    key: Some(*key),
+                    binding: Some(*binding),
+                    ..Done::default()
+                });
+            } else {
+                println!("key{key} set to {binding}");
+            }
+        }
+        Command::Lighting {
+            mode,
+            speed,
+            direction,
+            brightness,
+        } => {
+            if mode.is_some() || speed.is_some() || direction.is_some() || brightness.is_some() {
+                let mut settings = state.lighting(device)?.settings;
+                settings.mode = mode.unwrap_or(settings.mode);
+                settings.speed = speed.unwrap_or(settings.speed);
+                settings.direction = direction.unwrap_or(settings.direction);
+                settings.brightness = brightness.unwrap_or(settings.brightness);
+                state.set_lighting(device, settings)?;
+            }
+
+            let lighting = state.lighting(device)?;
+            if cli.json {
+                print_json(&lighting);
+            } else {
+                let settings = &lighting.settings;
+                println!("Profile: {}", lighting.slot);
+                println!("Mode: {}", settings.mode);
+                println!("Speed: {}", settings.speed);
+                println!("Direction: {}", settings.direction);
+                println!("Brightness: {}", settings.brightness);
+                let colors: Vec<String> = lighting.colors.into_iter().map(hex).collect();
+                println!("Colours: {}", colors.join(" "));
+            }
+        }
+        Command::Profile(ProfileCommand::Load { file, slot }) => {
+            let slot = slot_or_active(*slot)?;
+            let imported = state.import_profile(device, slot, file)?;
+            if cli.json {
+                print_json(&imported);
+            } else {
+                if let Some(migration) = &imported.migration {
+                    let heading = format!(
+                        "read {} as schema version {}, upgraded to {}:",
+                        file.display(),
+                        migration.from_version,
+                        migration.to_version
+                    );
+                    print_migration(&heading, migration);
+                    println!(
+                        "  the file is unchanged, `profile upgrade {}` updates it",
+                        file.display()
+                    );
+                }
+                println!("loaded {} into profile {slot}", file.display());
+            }
+        }
+        Command::Profile(ProfileCommand::Save { file, slot }) => {
+            let slot = slot_or_active(*slot)?;
+            state.export_profile(device, slot, file)?;
+            if cli.json {
+                print_json(&Done {
+                    slot: Some(slot),
+                    path: Some(file.clone()),
+                    ..Done::default()
+                });
+            } else {
+                println!("saved profile {slot} to {}", file.display());
+            }
+        }
+        Command::Profile(ProfileCommand::Activate { slot }) => {
+            state.set_active_profile(device, *slot)?;
+            if cli.json {
+                print_json(&Done {
+                    slot: Some(*slot),
+                    ..Done::default()
+                });
+            } else {
+                println!("switched to profile {slot}");
+            }
+        }
+        Command::Profile(ProfileCommand::Upgrade { file }) => {
+            let migration = upgrade_profile_file(file)?;
+            if cli.json {
+                print_json(&migration);
+            } else {
+                match &migration {
+                    Some(migration) => {
+                        let heading = format!(
+                            "upgraded {} from schema version {} to {}:",
+                            file.display(),
+                            migration.from_version,
+                            migration.to_version
+                        );
+                        print_migration(&heading, migration);
+                    }
+                    None => println!("{} is already up to date", file.display()),
+                }
+            }
+        }
+        Command::Dump { file } => {
+            state.dump_device(device, file)?;
+            if cli.json {
+                print_json(&Done {
+                    path: Some(file.clone()),
+                    ..Done::default()
+                });
+            } else {
+                println!("saved configuration memory to {}", file.display());
+            }
+        }
+        Command::Restore { file } => {
+            state.restore_device(device, file)?;
+            if cli.json {
+                print_json(&Done {
+                    path: Some(file.clone()),
+                    ..Done::default()
+                });
+            } else {
+                println!("restored configuration memory from {}", file.display());
+            }
+        }
+        Command::Monitor => monitor(state, cli.json),
+        Command::Keys => {
+            for key_event in state.watch_keys(device)? {
+                let event = &key_event.event;
+                if cli.json {
+                    println!(
+                        "{}",
+                        serde_json::to_string(&key_event).expect("events serialize")
+                    );
+                } else {
+                    let action = if event.pressed { "pressed" } else { "released" };
+                    println!("key{} {action}", event.key_index + 1);
+                }
+            }
+            // The reader only stops on its own when it fails.
+            state.unwatch_keys(device)?;
+        }
+        Command::Diff { file, slot } => {
+            let diff = state.diff_profile(device, *slot, file)?;
+            if cli.json {
+                print_json(&diff);
+            } else {
+                print!("{diff}");
+            }
+            if !diff.is_empty() {
+                return Ok(ExitCode::from(EXIT_DIFFERENT));
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Something `monitor` reports, printed as one line, or one JSON object per line with `--json`.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum MonitorEvent {
+    Hotplug(HotplugEvent),
+    Profile {
+        kind: &'static str,
+        #[serde(flatten)]
+        change: ProfileChanged,
+    },
+}
+
+impl MonitorEvent {
+    fn print(&self, json: bool) {
+        if json {
+            println!("{}", serde_json::to_string(self).expect("events serialize"));
+            return;
+        }
+        match self {
+            Self::Hotplug(event) => {
+                let location = event.location();
+                let verb = match event {
+                    HotplugEvent::DeviceAdded(_) => "connected",
+                    HotplugEvent::DeviceRemoved(_) => "disconnected",
+                };
+                println!(
+                    "keypad {verb} at {} (bus {}, address {})",
+                    location.port_path, location.bus_number, location.address
+                );
+            }
+            Self::Profile { change, .. } => {
+                println!("{} switched to profile {}", change.device, change.slot);
+            }
+        }
+    }
+}
+
+/// Prints events until the process is killed. Without hotplug support it still reports profile
+/// switches on the keypads that were connected at the start.
+fn monitor(state: &AppState, json: bool) -> ! {
+    let (sender, events) = mpsc::channel();
+    let watcher = rusb::Context::new()
+        .map_err(Falcon8Error::from)
+        .and_then(|context| {
+            HotplugWatcher::start(context, move |event| {
+                let _ = sender.send(event);
+            })
+        });
+    let _watcher = watcher
+        .map_err(|e| eprintln!("falcon8ctl: not watching for keypads coming and going: {e}"))
+        .ok();
+
+    state.poll_profiles();
+    loop {
+        match events.recv_timeout(PROFILE_POLL_INTERVAL) {
+            Ok(event) => {
+                if let Err(e) = state.refresh() {
+                    eprintln!("falcon8ctl: {e}");
+                }
+                MonitorEvent::Hotplug(event).print(json);
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(PROFILE_POLL_INTERVAL),
+        }
+        for change in state.poll_profiles() {
+            MonitorEvent::Profile {
+                kind: "profile_changed",
+                change,
+            }
+            .print(json);
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let state = AppState::from_env();
+    if let Some(dir) = app_config_dir() {
+        let nicknames = dir.join("nicknames.json");
+        if nicknames.exists() {
+            if let Err(e) = state.load_nicknames(nicknames) {
+                eprintln!("falcon8ctl: ignoring nicknames: {e}");
+            }
+        }
+        if let Err(e) = state.load_host_actions(dir.join("actions.json")) {
+            eprintln!("falcon8ctl: ignoring host-side actions: {e}");
+        }
+    }
+
+    match state.refresh().and_then(|()| run(&cli, &state)) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("falcon8ctl: {e}");
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+    use falcon8touch::falcon8::protocol::{usage, Modifiers};
+
+    use super::*;
+
+    fn parse(args: &[&str]) -> std::result::Result<Cli, clap::Error> {
+        Cli::try_parse_from([&["falcon8ctl"], args].concat())
+    }
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn test_parse() {
+        let cli = parse(&["--device", "SIM00001", "set-key", "2", "Ctrl+A"]).unwrap();
+        assert_eq!(cli.device, "SIM00001".parse().unwrap());
+        assert!(matches!(
+            cli.command,
+            Command::SetKey { key: 2, binding }
+                if binding == KeyBinding::combo(Modifiers::LEFT_CTRL, usage::keyboard::A)
+        ));
+
+        let cli = parse(&["lighting", "--mode", "wave", "--json"]).unwrap();
+        assert!(cli.json);
+        assert!(matches!(
+            cli.command,
+            Command::Lighting {
+                mode: Some(LightingMode::Wave),
+                speed: None,
+                ..
+            }
+        ));
+
+        for bad in [
+            &["set-key", "0", "A"][..],
+            &["set-key", "9", "A"],
+            &["set-key", "1", "Ctrl+Nope"],
+            &["lighting", "--mode", "disco"],
+            &["profile", "activate"],
+        ] {
+            let error = parse(bad).err().expect("should be rejected");
+            assert_eq!(error.exit_code(), i32::from(EXIT_INVALID), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let state = AppState::simulated(2);
+        let run = |args: &[&str]| run(&parse(args).unwrap(), &state);
+
+        assert_eq!(
+            run(&["-d", "1", "set-key", "8", "Media.Mute"]),
+            Ok(ExitCode::SUCCESS)
+        );
+        assert_eq!(
+            state
+                .read_profile(&DeviceSelector::Index(1), 0)
+                .unwrap()
+                .keys[7],
+            KeyBinding::Consumer {
+                usage: usage::consumer::MUTE
+            }
+        );
+
+        assert_eq!(
+            run(&["lighting", "--brightness", "7"]),
+            Ok(ExitCode::SUCCESS)
+        );
+        assert_eq!(
+            state
+                .lighting(&DeviceSelector::Index(0))
+                .unwrap()
+                .settings
+                .brightness,
+            7
+        );
+
+        let error = run(&["-d", "5", "info"]).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_NO_DEVICE);
+        let error = run(&["profile", "activate", "9"]).unwrap_err();
+        assert_eq!(exit_code(&error), EXIT_INVALID);
+    }
+
+    #[test]
+    fn test_done_json() {
+        let done = Done {
+            slot: Some(2),
+            path: Some(PathBuf::from("editing.toml")),
+            ..Done::default()
+        };
+        assert_eq!(
+            serde_json::to_value(done).unwrap(),
+            serde_json::json!({"ok": true, "slot": 2, "path": "editing.toml"})
+        );
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(exit_code(&Falcon8Error::NotFound), EXIT_NO_DEVICE);
+        assert_eq!(exit_code(&Falcon8Error::Timeout), EXIT_DEVICE);
+        assert_eq!(
+            exit_code(&Falcon8Error::InvalidDump("truncated")),
+            EXIT_INVALID
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use std::{path::PathBuf, thread};
+
+use falcon8touch::{
+    falcon8::{
+        actions::SystemHost,
+        animation::EffectConfig,
+        protocol::{Direction, KeyBinding, LightingMode, Macro, Rgb, KEY_COUNT},
+        upgrade_profile_file, Config, DeviceInfo, DeviceSelector, Falcon8Error, LightingSettings,
+        LightingState, MigrationReport, Profile, ProfileDiff, ProfileSlot,
+    },
+    state::{AppState, DeviceSummary, ImportedProfile},
+};
+use tauri::{AppHandle, Manager, State};
+
+type Result<T> = std::result::Result<T, Falcon8Error>;
+
+#[tauri::command]
+pub fn list_devices(state: State<'_, AppState>) -> Result<Vec<DeviceSummary>> {
+    state.list_devices()
+}
+
+#[tauri::command]
+pub fn get_device_info(state: State<'_, AppState>, device: DeviceSelector) -> Result<DeviceInfo> {
+    state.get_device_info(&device)
+}
+
+#[tauri::command]
+pub fn read_config(state: State<'_, AppState>, device: DeviceSelector) -> Result<Config> {
+    state.read_config(&device)
+}
+
+#[tauri::command]
+pub fn write_config(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    config: Config,
+) -> Result<()> {
+    state.write_config(&device, &config)
+}
+
+#[tauri::command]
+pub fn set_key_binding(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    key: usize,
+    binding: KeyBinding,
+) -> Result<()> {
+    state.set_key_binding(&device, key, binding)
+}
+
+#[tauri::command]
+pub fn set_key_map(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    keys: [KeyBinding; KEY_COUNT],
+) -> Result<()> {
+    state.set_key_map(&device, &keys)
+}
+
+#[tauri::command]
+pub fn parse_binding(text: String) -> Result<KeyBinding> {
+    Ok(text.parse()?)
+}
+
+#[tauri::command]
+pub fn format_binding(binding: KeyBinding) -> String {
+    binding.to_string()
+}
+
+#[tauri::command]
+pub fn list_profiles(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+) -> Result<Vec<ProfileSlot>> {
+    state.profiles(&device)
+}
+
+#[tauri::command]
+pub fn read_profile(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: u8,
+) -> Result<Profile> {
+    state.read_profile(&device, slot)
+}
+
+#[tauri::command]
+pub fn write_profile(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: u8,
+    profile: Profile,
+) -> Result<()> {
+    state.write_profile(&device, slot, &profile)
+}
+
+#[tauri::command]
+pub fn import_profile(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: u8,
+    path: PathBuf,
+) -> Result<ImportedProfile> {
+    state.import_profile(&device, slot, &path)
+}
+
+#[tauri::command]
+pub fn upgrade_profile(path: PathBuf) -> Result<Option<MigrationReport>> {
+    upgrade_profile_file(path)
+}
+
+#[tauri::command]
+pub fn export_profile(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: u8,
+    path: PathBuf,
+) -> Result<()> {
+    state.export_profile(&device, slot, &path)
+}
+
+#[tauri::command]
+pub fn diff_profile(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: Option<u8>,
+    path: PathBuf,
+) -> Result<ProfileDiff> {
+    state.diff_profile(&device, slot, &path)
+}
+
+#[tauri::command]
+pub fn dump_device(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    path: PathBuf,
+) -> Result<()> {
+    state.dump_device(&device, &path)
+}
+
+#[tauri::command]
+pub fn restore_device(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    path: PathBuf,
+) -> Result<()> {
+    state.restore_device(&device, &path)
+}
+
+#[tauri::command]
+pub fn set_active_profile(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: u8,
+) -> Result<()> {
+    state.set_active_profile(&device, slot)
+}
+
+#[tauri::command]
+pub fn watch_profiles(state: State<'_, AppState>) {
+    state.watch_profiles();
+}
+
+#[tauri::command]
+pub fn unwatch_profiles(state: State<'_, AppState>) {
+    state.unwatch_profiles();
+}
+
+#[tauri::command]
+pub fn upload_macro(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: usize,
+    definition: Macro,
+) -> Result<()> {
+    state.upload_macro(&device, slot, &definition)
+}
+
+#[tauri::command]
+pub fn download_macro(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    slot: usize,
+) -> Result<Option<Macro>> {
+    state.download_macro(&device, slot)
+}
+
+#[tauri::command]
+pub fn set_lighting(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    lighting: LightingSettings,
+) -> Result<()> {
+    state.set_lighting(&device, lighting)
+}
+
+#[tauri::command]
+pub fn set_nickname(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    nickname: Option<String>,
+) -> Result<()> {
+    state.set_nickname(&device, nickname.as_deref())
+}
+
+#[tauri::command]
+pub fn get_lighting(state: State<'_, AppState>, device: DeviceSelector) -> Result<LightingState> {
+    state.lighting(&device)
+}
+
+#[tauri::command]
+pub fn set_effect(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    mode: LightingMode,
+    speed: u8,
+    direction: Direction,
+) -> Result<()> {
+    state.set_effect(&device, mode, speed, direction)
+}
+
+#[tauri::command]
+pub fn set_brightness(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    brightness: u8,
+) -> Result<()> {
+    state.set_brightness(&device, brightness)
+}
+
+#[tauri::command]
+pub fn set_key_color(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    key: usize,
+    color: Rgb,
+) -> Result<()> {
+    state.set_key_color(&device, key, color)
+}
+
+#[tauri::command]
+pub fn set_key_colors(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    colors: [Rgb; KEY_COUNT],
+) -> Result<()> {
+    state.set_key_colors(&device, &colors)
+}
+
+#[tauri::command]
+pub fn start_animation(
+    state: State<'_, AppState>,
+    device: DeviceSelector,
+    effect: EffectConfig,
+    fps: u32,
+) -> Result<()> {
+    state.start_animation(&device, effect, fps)
+}
+
+#[tauri::command]
+pub fn stop_animation(state: State<'_, AppState>, device: DeviceSelector) -> Result<()> {
+    state.stop_animation(&device)
+}
+
+/// Reads key presses from `device` until it's unplugged or unwatched, running their host-side
+/// actions and forwarding them to the frontend.
+pub fn watch_device_keys(app: AppHandle, device: &DeviceSelector) -> Result<()> {
+    let events = app.state::<AppState>().watch_keys(device)?;
+    thread::spawn(move || {
+        let state = app.state::<AppState>();
+        state.run_key_events(events, &mut SystemHost::default(), |event| {
+            if let Err(e) = app.emit_all("key-event", event) {
+                eprintln!("Failed to emit key-event: {e}");
+            }
+        });
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn watch_keys(app: AppHandle, device: DeviceSelector) -> Result<()> {
+    watch_device_keys(app, &device)
+}
+
+#[tauri::command]
+pub fn unwatch_keys(state: State<'_, AppState>, device: DeviceSelector) -> Result<()> {
+    state.unwatch_keys(&device)
+}
@@ -0,0 +1,419 @@
+//! Things a key can do on the computer rather than on the keypad, like running a command.
+//!
+//! The keypad doesn't know about these. The host reads its key presses (see `key_events`) and
+//! runs the actions configured in the profile for the key: one when it goes down, one when it
+//! comes up, and one when it's held for a while.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    key_events::KeyEvent,
+    protocol::{KeyBinding, Keystroke, Layout, KEY_COUNT},
+    virtual_keyboard::{self, VirtualKeyboard},
+    Falcon8Error, Result,
+};
+
+/// How long a key has to be held for its long-press action, unless configured otherwise.
+pub const DEFAULT_HOLD_MS: u32 = 500;
+
+/// Something the computer does when a key is pressed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    /// Runs a command line with the system shell, without waiting for it to finish.
+    Shell { command: String },
+    /// Opens a URL, or a file, with its default application.
+    Url { url: String },
+    /// Types text as if on a keyboard, for a computer set to `layout`. With `unicode_input`,
+    /// characters the layout doesn't have are typed by code point, see `Layout::unicode_input`.
+    Text {
+        text: String,
+        #[serde(default)]
+        layout: Layout,
+        #[serde(default)]
+        unicode_input: bool,
+    },
+    /// Presses and releases a key combination, e.g. Ctrl+Shift+M.
+    Combo { combo: KeyBinding },
+    /// Switches the keypad to another profile slot.
+    Profile { slot: u8 },
+}
+
+/// The host-side actions of one key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyActions {
+    pub press: Option<Action>,
+    /// Skipped when the key was held long enough for `long_press` instead.
+    pub release: Option<Action>,
+    pub long_press: Option<Action>,
+    /// How long the key has to be held for `long_press`, in milliseconds.
+    pub hold_ms: u32,
+}
+
+impl Default for KeyActions {
+    fn default() -> Self {
+        Self {
+            press: None,
+            release: None,
+            long_press: None,
+            hold_ms: DEFAULT_HOLD_MS,
+        }
+    }
+}
+
+impl KeyActions {
+    pub fn is_empty(&self) -> bool {
+        self.press.is_none() && self.release.is_none() && self.long_press.is_none()
+    }
+}
+
+/// The host-side actions of every profile written to a keypad, which the keypad can't store
+/// itself. Keyed by [`Falcon8::persistent_key`](super::Falcon8::persistent_key), then by profile
+/// slot, and optionally backed by a JSON file that is rewritten on every change.
+#[derive(Debug, Default)]
+pub struct HostActions {
+    path: Option<PathBuf>,
+    slots: BTreeMap<String, BTreeMap<u8, [KeyActions; KEY_COUNT]>>,
+}
+
+impl HostActions {
+    /// Loads the actions saved at `path`, or none if it doesn't exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let slots = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| Falcon8Error::file(&path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(Falcon8Error::file(&path, e)),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            slots,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn get(&self, key: &str, slot: u8) -> Option<&[KeyActions; KEY_COUNT]> {
+        self.slots.get(key)?.get(&slot)
+    }
+
+    /// Sets the actions of profile `slot` on the keypad with persistent key `key`, forgetting
+    /// them if no key has any.
+    pub fn set(&mut self, key: &str, slot: u8, actions: &[KeyActions; KEY_COUNT]) -> Result<()> {
+        if actions.iter().all(KeyActions::is_empty) {
+            if let Some(slots) = self.slots.get_mut(key) {
+                slots.remove(&slot);
+                if slots.is_empty() {
+                    self.slots.remove(key);
+                }
+            }
+        } else {
+            self.slots
+                .entry(key.to_string())
+                .or_default()
+                .insert(slot, actions.clone());
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| Falcon8Error::file(path, e))?;
+        }
+        let json = serde_json::to_string_pretty(&self.slots).expect("actions serialize");
+        fs::write(path, json).map_err(|e| Falcon8Error::file(path, e))
+    }
+}
+
+/// A key that is down.
+#[derive(Debug)]
+struct Held {
+    /// When it went down, in milliseconds since the Unix epoch.
+    since: u64,
+    actions: KeyActions,
+    long_pressed: bool,
+}
+
+impl Held {
+    /// When the long-press action is due, if there is one still to run.
+    fn deadline(&self) -> Option<u64> {
+        (self.actions.long_press.is_some() && !self.long_pressed)
+            .then(|| self.since + u64::from(self.actions.hold_ms))
+    }
+}
+
+/// Works out which actions key events trigger. Time comes from the events' timestamps and the
+/// `now` passed to `expire`, so this doesn't need a clock of its own.
+#[derive(Debug, Default)]
+pub struct ActionEngine {
+    held: [Option<Held>; KEY_COUNT],
+}
+
+impl ActionEngine {
+    /// The action `event` triggers. `lookup` gives the key's actions when it goes down; they
+    /// apply until it comes up again, even if the profile changes in between.
+    pub fn key_event(
+        &mut self,
+        event: &KeyEvent,
+        lookup: impl FnOnce() -> KeyActions,
+    ) -> Option<Action> {
+        let held = self.held.get_mut(event.key_index)?;
+        if event.pressed {
+            let actions = lookup();
+            let press = actions.press.clone();
+            *held = Some(Held {
+                since: event.timestamp,
+                actions,
+                long_pressed: false,
+            });
+            return press;
+        }
+
+        let held = held.take()?;
+        match held.deadline() {
+            // Released before `expire` got to it, but held long enough all the same.
+            Some(deadline) if deadline <= event.timestamp => held.actions.long_press,
+            _ if held.long_pressed => None,
+            _ => held.actions.release,
+        }
+    }
+
+    /// When the next long-press action is due, in milliseconds since the Unix epoch.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.held.iter().flatten().filter_map(Held::deadline).min()
+    }
+
+    /// Long-press actions of keys that have been held long enough by `now`.
+    pub fn expire(&mut self, now: u64) -> Vec<Action> {
+        let mut due = Vec::new();
+        for held in self.held.iter_mut().flatten() {
+            if held.deadline().is_some_and(|deadline| deadline <= now) {
+                held.long_pressed = true;
+                due.extend(held.actions.long_press.clone());
+            }
+        }
+        due
+    }
+}
+
+/// What running actions needs from the computer. Switching profiles is up to the caller, who
+/// knows which keypad the key belongs to.
+pub trait ActionHost {
+    fn run_command(&mut self, command: &str) -> Result<()>;
+
+    fn open_url(&mut self, url: &str) -> Result<()>;
+
+    /// Types text already turned into keystrokes for the computer's layout.
+    fn type_keystrokes(&mut self, keystrokes: &[Keystroke]) -> Result<()>;
+
+    fn send_combo(&mut self, combo: KeyBinding) -> Result<()>;
+}
+
+/// Runs actions on this machine.
+#[derive(Default)]
+pub struct SystemHost {
+    /// Made the first time something is typed, so it's only needed by those typing.
+    keyboard: Option<Box<dyn VirtualKeyboard>>,
+}
+
+impl SystemHost {
+    /// Types and sends combos on `keyboard` instead of the system's virtual keyboard.
+    pub fn with_keyboard(keyboard: Box<dyn VirtualKeyboard>) -> Self {
+        Self {
+            keyboard: Some(keyboard),
+        }
+    }
+
+    fn keyboard(&mut self) -> Result<&mut dyn VirtualKeyboard> {
+        if self.keyboard.is_none() {
+            self.keyboard = Some(virtual_keyboard::open()?);
+        }
+        Ok(self.keyboard.as_deref_mut().unwrap())
+    }
+}
+
+/// Starts `command` without waiting for it. A thread waits instead, so it doesn't linger as a
+/// zombie once it exits.
+fn spawn(mut command: Command, description: &str) -> Result<()> {
+    let mut child =
+        command
+            .stdin(Stdio::null())
+            .spawn()
+            .map_err(|e| Falcon8Error::CommandFailed {
+                command: description.to_string(),
+                message: e.to_string(),
+            })?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+impl ActionHost for SystemHost {
+    fn run_command(&mut self, command: &str) -> Result<()> {
+        let mut shell = if cfg!(windows) {
+            let mut shell = Command::new("cmd");
+            shell.arg("/C");
+            shell
+        } else {
+            let mut shell = Command::new("sh");
+            shell.arg("-c");
+            shell
+        };
+        shell.arg(command);
+        spawn(shell, command)
+    }
+
+    fn open_url(&mut self, url: &str) -> Result<()> {
+        let mut opener = if cfg!(windows) {
+            let mut opener = Command::new("cmd");
+            opener.args(["/C", "start", ""]);
+            opener
+        } else if cfg!(target_os = "macos") {
+            Command::new("open")
+        } else {
+            Command::new("xdg-open")
+        };
+        opener.arg(url);
+        spawn(opener, url)
+    }
+
+    fn type_keystrokes(&mut self, keystrokes: &[Keystroke]) -> Result<()> {
+        self.keyboard()?.type_keystrokes(keystrokes)
+    }
+
+    fn send_combo(&mut self, combo: KeyBinding) -> Result<()> {
+        self.keyboard()?.tap(combo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shell(command: &str) -> Option<Action> {
+        Some(Action::Shell {
+            command: command.to_string(),
+        })
+    }
+
+    fn event(key_index: usize, pressed: bool, timestamp: u64) -> KeyEvent {
+        KeyEvent {
+            key_index,
+            pressed,
+            timestamp,
+        }
+    }
+
+    fn actions() -> KeyActions {
+        KeyActions {
+            press: shell("press"),
+            release: shell("release"),
+            ..KeyActions::default()
+        }
+    }
+
+    fn tap_or_hold() -> KeyActions {
+        KeyActions {
+            press: None,
+            release: shell("tap"),
+            long_press: shell("hold"),
+            hold_ms: 300,
+        }
+    }
+
+    #[test]
+    fn test_press_and_release() {
+        let mut engine = ActionEngine::default();
+        assert_eq!(
+            engine.key_event(&event(0, true, 0), actions),
+            shell("press")
+        );
+        assert_eq!(engine.next_deadline(), None);
+        assert_eq!(
+            engine.key_event(&event(0, false, 2000), || unreachable!()),
+            shell("release")
+        );
+
+        // A release without a press, e.g. a key already down when reading started.
+        assert_eq!(
+            engine.key_event(&event(1, false, 0), || unreachable!()),
+            None
+        );
+        assert_eq!(engine.key_event(&event(9, true, 0), actions), None);
+    }
+
+    #[test]
+    fn test_long_press() {
+        let mut engine = ActionEngine::default();
+
+        // Tapped.
+        assert_eq!(engine.key_event(&event(2, true, 1000), tap_or_hold), None);
+        assert_eq!(engine.next_deadline(), Some(1300));
+        assert_eq!(engine.expire(1299), []);
+        assert_eq!(
+            engine.key_event(&event(2, false, 1299), || unreachable!()),
+            shell("tap")
+        );
+        assert_eq!(engine.next_deadline(), None);
+
+        // Held.
+        engine.key_event(&event(2, true, 2000), tap_or_hold);
+        assert_eq!(engine.expire(2300), [shell("hold").unwrap()]);
+        assert_eq!(engine.next_deadline(), None);
+        assert_eq!(engine.expire(5000), []);
+        assert_eq!(
+            engine.key_event(&event(2, false, 5000), || unreachable!()),
+            None
+        );
+
+        // Held, but released before the deadline was looked at.
+        engine.key_event(&event(2, true, 6000), tap_or_hold);
+        assert_eq!(
+            engine.key_event(&event(2, false, 6400), || unreachable!()),
+            shell("hold")
+        );
+    }
+
+    #[test]
+    fn test_actions_stay_until_release() {
+        let mut engine = ActionEngine::default();
+        engine.key_event(&event(0, true, 0), actions);
+        engine.key_event(&event(1, true, 0), tap_or_hold);
+        assert_eq!(
+            engine.key_event(&event(0, false, 10), || unreachable!()),
+            shell("release")
+        );
+        assert_eq!(engine.expire(300), [shell("hold").unwrap()]);
+    }
+
+    #[test]
+    fn test_json() {
+        let json = serde_json::to_value(tap_or_hold()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "press": null,
+                "release": {"type": "shell", "command": "tap"},
+                "long_press": {"type": "shell", "command": "hold"},
+                "hold_ms": 300,
+            })
+        );
+        let actions: KeyActions = serde_json::from_str("{}").unwrap();
+        assert_eq!(actions, KeyActions::default());
+    }
+}
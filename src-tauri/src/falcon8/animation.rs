@@ -0,0 +1,371 @@
+//! Lighting animations computed on the host and streamed to the LEDs frame by frame.
+//!
+//! Frames go out as volatile `LedColorsReport`s, so nothing is written to flash and the profile's
+//! saved colours come back once the animation stops. Only the keys that changed since the
+//! previous frame are sent.
+
+use std::{
+    sync::{
+        mpsc::{self, RecvTimeoutError, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    protocol::{LedColorsReport, Rgb, KEY_COUNT, LED_FLAG_VOLATILE},
+    transport::Transport,
+    Falcon8, Falcon8Error, Result,
+};
+
+pub const MAX_FPS: u32 = 60;
+
+/// Something that draws frames.
+pub trait Effect: Send {
+    /// Draws the frame `elapsed` after the animation started into `leds`, which still holds the
+    /// previous frame.
+    fn frame(&mut self, elapsed: Duration, leds: &mut [Rgb; KEY_COUNT]);
+}
+
+impl<F: FnMut(Duration, &mut [Rgb; KEY_COUNT]) + Send> Effect for F {
+    fn frame(&mut self, elapsed: Duration, leds: &mut [Rgb; KEY_COUNT]) {
+        self(elapsed, leds)
+    }
+}
+
+/// Fully saturated colour at `hue`, a fraction of the colour wheel.
+fn hue_to_rgb(hue: f32) -> Rgb {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    let (r, g, b) = match h as u8 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    [r, g, b].map(|c| (c * 255.0).round() as u8)
+}
+
+/// A rainbow scrolling across the keys once per `period`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rainbow {
+    pub period: Duration,
+}
+
+impl Effect for Rainbow {
+    fn frame(&mut self, elapsed: Duration, leds: &mut [Rgb; KEY_COUNT]) {
+        let phase = elapsed.as_secs_f32() / self.period.as_secs_f32().max(f32::EPSILON);
+        for (key, led) in leds.iter_mut().enumerate() {
+            *led = hue_to_rgb(phase + key as f32 / KEY_COUNT as f32);
+        }
+    }
+}
+
+/// One lit key running along the row, moving a key every `step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chase {
+    pub color: Rgb,
+    pub background: Rgb,
+    pub step: Duration,
+}
+
+impl Effect for Chase {
+    fn frame(&mut self, elapsed: Duration, leds: &mut [Rgb; KEY_COUNT]) {
+        let lit = (elapsed.as_millis() / self.step.as_millis().max(1)) as usize % KEY_COUNT;
+        for (key, led) in leds.iter_mut().enumerate() {
+            *led = if key == lit {
+                self.color
+            } else {
+                self.background
+            };
+        }
+    }
+}
+
+/// The built-in effects, in a form the frontend can pick from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "snake_case")]
+pub enum EffectConfig {
+    Rainbow {
+        period_ms: u64,
+    },
+    Chase {
+        color: Rgb,
+        background: Rgb,
+        step_ms: u64,
+    },
+}
+
+impl EffectConfig {
+    pub fn into_effect(self) -> Box<dyn Effect> {
+        match self {
+            Self::Rainbow { period_ms } => Box::new(Rainbow {
+                period: Duration::from_millis(period_ms),
+            }),
+            Self::Chase {
+                color,
+                background,
+                step_ms,
+            } => Box::new(Chase {
+                color,
+                background,
+                step: Duration::from_millis(step_ms),
+            }),
+        }
+    }
+}
+
+/// Keeps frames on a fixed schedule.
+#[derive(Debug)]
+struct FramePacer {
+    start: Instant,
+    interval: Duration,
+    next: Instant,
+}
+
+impl FramePacer {
+    fn new(start: Instant, interval: Duration) -> Self {
+        Self {
+            start,
+            interval,
+            next: start,
+        }
+    }
+
+    /// How long to wait until the next frame is due, and the animation time to draw it at.
+    ///
+    /// Deadlines advance by exactly one interval so slow frames don't make the rate drift. If
+    /// the stream has fallen a whole frame behind, the missed frames are dropped instead of
+    /// being sent in a burst.
+    fn next_frame(&mut self, now: Instant) -> (Duration, Duration) {
+        if now >= self.next + self.interval {
+            self.next = now;
+        }
+        let due = self.next;
+        self.next += self.interval;
+        (due.saturating_duration_since(now), due - self.start)
+    }
+}
+
+impl<T: Transport> Falcon8<T> {
+    /// Shows `colors` on the keys in `mask` without saving them.
+    pub fn show_colors(&self, mask: u8, colors: &[Rgb; KEY_COUNT]) -> Result<()> {
+        self.write_report(&LedColorsReport {
+            // Volatile colours go to whatever profile is displayed.
+            slot: 0,
+            flags: LED_FLAG_VOLATILE,
+            mask,
+            colors: *colors,
+        })
+    }
+
+    /// Puts the active profile's saved colours back after `show_colors`.
+    pub fn restore_colors(&self) -> Result<()> {
+        let saved = self.lighting()?.colors;
+        self.show_colors(0xFF, &saved)
+    }
+}
+
+/// Bit set for every key whose colour differs between the two frames.
+fn changed_mask(previous: &[Rgb; KEY_COUNT], next: &[Rgb; KEY_COUNT]) -> u8 {
+    previous
+        .iter()
+        .zip(next)
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .fold(0, |mask, (key, _)| mask | 1 << key)
+}
+
+/// An effect playing on a keypad from a background thread. Stops when dropped.
+///
+/// The thread keeps the configuration interface claimed while it runs, and each frame is a
+/// single report, so other commands to the same keypad go through between frames.
+#[derive(Debug)]
+pub struct Animation {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl Animation {
+    pub fn start<T: Transport + 'static>(
+        falcon: Arc<Falcon8<T>>,
+        mut effect: Box<dyn Effect>,
+        fps: u32,
+    ) -> Result<Self> {
+        if !(1..=MAX_FPS).contains(&fps) {
+            return Err(Falcon8Error::InvalidFrameRate(fps));
+        }
+        let interval = Duration::from_secs(1) / fps;
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread = thread::spawn(move || {
+            let _guard = falcon.claim()?;
+            let mut pacer = FramePacer::new(Instant::now(), interval);
+            let mut shown = None;
+            let mut leds = falcon.lighting()?.colors;
+
+            loop {
+                let (wait, elapsed) = pacer.next_frame(Instant::now());
+                match stopped.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    _ => break,
+                }
+
+                effect.frame(elapsed, &mut leds);
+                let mask = shown.map_or(0xFF, |shown| changed_mask(&shown, &leds));
+                if mask != 0 {
+                    falcon.show_colors(mask, &leds)?;
+                }
+                shown = Some(leds);
+            }
+
+            falcon.restore_colors()
+        });
+
+        Ok(Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// Whether the animation has ended by itself, i.e. failed.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Stops the animation and restores the saved colours, returning the error that ended it
+    /// early, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<()> {
+        drop(self.stop.take());
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Animation {
+    fn drop(&mut self) {
+        if let Err(e) = self.join() {
+            eprintln!("Animation failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::{transport::SimulatedFalcon8, CONFIG_INTERFACE};
+
+    #[test]
+    fn test_frame_pacing() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(10);
+        let mut pacer = FramePacer::new(start, interval);
+
+        assert_eq!(pacer.next_frame(start), (Duration::ZERO, Duration::ZERO));
+        // A frame that took 4ms leaves 6ms until the next one.
+        let now = start + Duration::from_millis(4);
+        assert_eq!(pacer.next_frame(now), (Duration::from_millis(6), interval));
+        // Running a little late shortens the wait instead of shifting the schedule.
+        let now = start + Duration::from_millis(23);
+        assert_eq!(pacer.next_frame(now), (Duration::ZERO, interval * 2));
+        // Falling a whole frame behind drops the missed frames.
+        let now = start + Duration::from_millis(55);
+        assert_eq!(
+            pacer.next_frame(now),
+            (Duration::ZERO, Duration::from_millis(55))
+        );
+        let now = start + Duration::from_millis(58);
+        assert_eq!(
+            pacer.next_frame(now),
+            (Duration::from_millis(7), Duration::from_millis(65))
+        );
+    }
+
+    #[test]
+    fn test_changed_mask() {
+        let previous = [[0; 3]; KEY_COUNT];
+        let mut next = previous;
+        assert_eq!(changed_mask(&previous, &next), 0);
+        next[0] = [1, 0, 0];
+        next[7] = [0, 0, 1];
+        assert_eq!(changed_mask(&previous, &next), 0b1000_0001);
+    }
+
+    #[test]
+    fn test_effects() {
+        let mut leds = [[0; 3]; KEY_COUNT];
+        let mut rainbow = Rainbow {
+            period: Duration::from_secs(1),
+        };
+        rainbow.frame(Duration::ZERO, &mut leds);
+        assert_eq!(leds[0], [0xFF, 0, 0]);
+        rainbow.frame(Duration::from_millis(500), &mut leds);
+        assert_eq!(leds[0], [0, 0xFF, 0xFF]);
+
+        let mut chase = Chase {
+            color: [0xFF, 0, 0],
+            background: [0; 3],
+            step: Duration::from_millis(100),
+        };
+        chase.frame(Duration::from_millis(250), &mut leds);
+        assert_eq!(leds[2], [0xFF, 0, 0]);
+        assert_eq!(leds.iter().filter(|&&led| led == [0; 3]).count(), 7);
+    }
+
+    #[test]
+    fn test_streaming() {
+        let falcon = Arc::new(Falcon8::with_transport(SimulatedFalcon8::new()));
+        let saved = falcon.transport.led_colors(0);
+
+        // Lights one key per frame, so every frame after the first changes two keys.
+        let mut frame = 0;
+        let effect = move |_: Duration, leds: &mut [Rgb; KEY_COUNT]| {
+            *leds = [[0; 3]; KEY_COUNT];
+            leds[frame % KEY_COUNT] = [0, 0, 0xFF];
+            frame += 1;
+        };
+        let animation = Animation::start(falcon.clone(), Box::new(effect), MAX_FPS).unwrap();
+
+        thread::sleep(Duration::from_millis(100));
+        // Other commands still get through while frames stream.
+        assert!(falcon.key_map().is_ok());
+        assert!(falcon.transport.is_claimed(CONFIG_INTERFACE));
+        assert!(!animation.is_finished());
+        animation.stop().unwrap();
+
+        let masks = falcon.transport.take_volatile_masks();
+        let (restore, frames) = masks.split_last().unwrap();
+        assert!(frames.len() >= 3, "{masks:?}");
+        assert_eq!(frames[0], 0xFF);
+        assert!(frames[1..].iter().all(|mask| mask.count_ones() == 2));
+        assert_eq!(*restore, 0xFF);
+
+        assert_eq!(falcon.transport.displayed_colors(), saved);
+        assert_eq!(falcon.transport.led_colors(0), saved);
+        assert!(!falcon.transport.is_claimed(CONFIG_INTERFACE));
+    }
+
+    #[test]
+    fn test_invalid_frame_rate() {
+        let falcon = Arc::new(Falcon8::with_transport(SimulatedFalcon8::new()));
+        let effect = Rainbow {
+            period: Duration::from_secs(1),
+        };
+        assert_eq!(
+            Animation::start(falcon, Box::new(effect), 0).err(),
+            Some(Falcon8Error::InvalidFrameRate(0))
+        );
+    }
+}
@@ -0,0 +1,199 @@
+//! In-memory model of a profile's configuration, assembled from the individual feature reports.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    protocol::{
+        KeyBinding, KeyMapReport, LedColorsReport, LightingReport, ProfileStateReport, Rgb,
+        KEY_COUNT,
+    },
+    transport::Transport,
+    Falcon8, Falcon8Error, LightingSettings, Result,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    pub slot: u8,
+    pub keys: [KeyBinding; KEY_COUNT],
+    pub lighting: LightingSettings,
+    pub colors: [Rgb; KEY_COUNT],
+}
+
+impl<T: Transport> Falcon8<T> {
+    /// Points slot-addressed reads at the active profile and returns its slot.
+    pub(super) fn select_active_slot(&self) -> Result<u8> {
+        let state: ProfileStateReport = self.read_report()?;
+        if state.edit_slot != state.active_slot {
+            self.write_report(&ProfileStateReport {
+                edit_slot: state.active_slot,
+                ..state
+            })?;
+        }
+        Ok(state.active_slot)
+    }
+
+    /// Points slot-addressed reads at profile `slot`.
+    pub(super) fn select_slot(&self, slot: u8) -> Result<()> {
+        let state: ProfileStateReport = self.read_report()?;
+        if slot >= state.slot_count {
+            return Err(Falcon8Error::InvalidProfileSlot(slot));
+        }
+        if state.edit_slot != slot {
+            self.write_report(&ProfileStateReport {
+                edit_slot: slot,
+                ..state
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Reads the configuration of the active profile.
+    pub fn read_config(&self) -> Result<Config> {
+        let _guard = self.transaction()?;
+        let slot = self.select_active_slot()?;
+        self.read_selected_config(slot)
+    }
+
+    /// Reads the configuration of the profile `slot`, which has to be selected already.
+    pub(super) fn read_selected_config(&self, slot: u8) -> Result<Config> {
+        let keys: KeyMapReport = self.read_report()?;
+        let lighting: LightingReport = self.read_report()?;
+        let colors: LedColorsReport = self.read_report()?;
+
+        Ok(Config {
+            slot,
+            keys: keys.keys()?,
+            lighting: LightingSettings::from_report(&lighting),
+            colors: colors.colors,
+        })
+    }
+
+    /// Writes `config` to the profile slot it names.
+    pub fn write_config(&self, config: &Config) -> Result<()> {
+        let _guard = self.transaction()?;
+
+        self.write_report(&KeyMapReport::from_keys(config.slot, &config.keys))?;
+        self.write_report(&config.lighting.to_report(config.slot))?;
+        self.write_report(&LedColorsReport {
+            slot: config.slot,
+            flags: 0,
+            mask: 0xFF,
+            colors: config.colors,
+        })
+    }
+
+    /// Reads what each key of the active profile sends.
+    pub fn key_map(&self) -> Result<[KeyBinding; KEY_COUNT]> {
+        let _guard = self.transaction()?;
+        self.select_active_slot()?;
+
+        let keys: KeyMapReport = self.read_report()?;
+        Ok(keys.keys()?)
+    }
+
+    /// Replaces all eight bindings of the active profile. They are stored on the keypad, so they
+    /// keep working without any host software running.
+    pub fn set_key_map(&self, keys: &[KeyBinding; KEY_COUNT]) -> Result<()> {
+        let _guard = self.transaction()?;
+        let slot = self.select_active_slot()?;
+
+        self.write_report(&KeyMapReport::from_keys(slot, keys))
+    }
+
+    pub fn set_key_binding(&self, key: usize, binding: KeyBinding) -> Result<()> {
+        if key >= KEY_COUNT {
+            return Err(Falcon8Error::InvalidKey(key));
+        }
+
+        let _guard = self.transaction()?;
+        self.select_active_slot()?;
+
+        let mut keys: KeyMapReport = self.read_report()?;
+        keys.bindings[key] = binding.encode();
+        self.write_report(&keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::{
+        protocol::{usage, DecodeError, Modifiers, MouseButtons, KEY_MAP_OFFSET},
+        transport::SimulatedFalcon8,
+    };
+
+    #[test]
+    fn test_config_round_trip() {
+        let falcon = Falcon8::with_transport(SimulatedFalcon8::new());
+        falcon.transport.press_profile_button();
+
+        let mut config = falcon.read_config().unwrap();
+        assert_eq!(config.slot, 1);
+        config.keys[0] = KeyBinding::combo(Modifiers::LEFT_CTRL, usage::keyboard::A + 2);
+        config.lighting.brightness = 0x40;
+        config.colors[7] = [0xFF, 0x00, 0x00];
+        falcon.write_config(&config).unwrap();
+
+        assert_eq!(falcon.read_config().unwrap(), config);
+        assert_eq!(falcon.transport.key_map(0)[0], [0x01, 0x00, 0x68, 0x00]);
+    }
+
+    #[test]
+    fn test_set_key_binding() {
+        let falcon = Falcon8::with_transport(SimulatedFalcon8::new());
+        falcon
+            .set_key_binding(5, KeyBinding::key(usage::keyboard::A))
+            .unwrap();
+        assert_eq!(falcon.transport.key_map(0)[5], [0x01, 0x00, 0x04, 0x00]);
+
+        assert_eq!(
+            falcon.set_key_binding(KEY_COUNT, KeyBinding::Disabled),
+            Err(Falcon8Error::InvalidKey(KEY_COUNT))
+        );
+    }
+
+    #[test]
+    fn test_key_map() {
+        let falcon = Falcon8::with_transport(SimulatedFalcon8::new());
+        let keys = falcon.key_map().unwrap();
+        assert_eq!(keys[3], KeyBinding::key(usage::keyboard::F13 + 3));
+
+        let keys = [
+            KeyBinding::Disabled,
+            KeyBinding::combo(
+                Modifiers::LEFT_CTRL | Modifiers::LEFT_SHIFT,
+                usage::keyboard::Z,
+            ),
+            KeyBinding::Consumer {
+                usage: usage::consumer::VOLUME_UP,
+            },
+            KeyBinding::Consumer {
+                usage: usage::consumer::VOLUME_DOWN,
+            },
+            KeyBinding::System {
+                usage: usage::system::SLEEP,
+            },
+            KeyBinding::Mouse {
+                buttons: MouseButtons::BACK,
+            },
+            KeyBinding::key(usage::keyboard::F24),
+            KeyBinding::combo(Modifiers::RIGHT_ALT, 0),
+        ];
+        falcon.set_key_map(&keys).unwrap();
+        assert_eq!(falcon.key_map().unwrap(), keys);
+        assert_eq!(falcon.transport.key_map(0)[2], [0x02, 0xE9, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_invalid_binding_on_device() {
+        let falcon = Falcon8::with_transport(SimulatedFalcon8::new());
+        falcon.transport.poke_memory(KEY_MAP_OFFSET, &[0x7F]);
+        assert!(matches!(
+            falcon.key_map(),
+            Err(Falcon8Error::Protocol(DecodeError::InvalidField {
+                value: 0x7F,
+                ..
+            }))
+        ));
+    }
+}
@@ -1,2 +1,9 @@
+use std::time::Duration;
+
 pub const VID: u16 = 0x195D;
 pub const PID: u16 = 0x6009;
+
+/// The vendor HID interface carrying configuration feature reports.
+pub const CONFIG_INTERFACE: u8 = 2;
+
+pub const TIMEOUT: Duration = Duration::from_secs(1);
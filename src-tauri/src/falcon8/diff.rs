@@ -0,0 +1,279 @@
+//! What differs between two profiles, e.g. the profile file a keypad should be running and what
+//! it actually holds.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use super::{
+    profile_file::{hex_color, key_name, macro_steps},
+    protocol::{KeyBinding, Macro, Rgb, KEY_COUNT},
+    LightingSettings, Profile,
+};
+
+/// One way two profiles differ. `key` is 0-based like the commands' key indices, and `name`
+/// is how profile files and the text output refer to it, from `key1` to `key8`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Difference {
+    Key {
+        key: usize,
+        name: String,
+        expected: KeyBinding,
+        actual: KeyBinding,
+    },
+    Lighting {
+        expected: LightingSettings,
+        actual: LightingSettings,
+    },
+    Color {
+        key: usize,
+        name: String,
+        expected: Rgb,
+        actual: Rgb,
+    },
+    /// `None` when only one side has the macro.
+    Macro {
+        slot: u8,
+        expected: Option<Macro>,
+        actual: Option<Macro>,
+    },
+}
+
+/// The differences between an expected and an actual profile, in key, lighting, colour and
+/// macro order. Displays as one line per difference.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ProfileDiff {
+    pub differences: Vec<Difference>,
+}
+
+impl ProfileDiff {
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+}
+
+impl Profile {
+    /// How `actual` differs from this profile. Host-side actions aren't compared, as the keypad
+    /// doesn't hold them.
+    pub fn diff(&self, actual: &Profile) -> ProfileDiff {
+        let mut differences = Vec::new();
+
+        for key in 0..KEY_COUNT {
+            if self.keys[key] != actual.keys[key] {
+                differences.push(Difference::Key {
+                    key,
+                    name: key_name(key),
+                    expected: self.keys[key],
+                    actual: actual.keys[key],
+                });
+            }
+        }
+        if self.lighting != actual.lighting {
+            differences.push(Difference::Lighting {
+                expected: self.lighting,
+                actual: actual.lighting,
+            });
+        }
+        for key in 0..KEY_COUNT {
+            if self.colors[key] != actual.colors[key] {
+                differences.push(Difference::Color {
+                    key,
+                    name: key_name(key),
+                    expected: self.colors[key],
+                    actual: actual.colors[key],
+                });
+            }
+        }
+
+        let mut slots: Vec<u8> = self
+            .macros
+            .keys()
+            .chain(actual.macros.keys())
+            .copied()
+            .collect();
+        slots.sort_unstable();
+        slots.dedup();
+        for slot in slots {
+            let expected = self.macros.get(&slot);
+            let actual = actual.macros.get(&slot);
+            if expected != actual {
+                differences.push(Difference::Macro {
+                    slot,
+                    expected: expected.cloned(),
+                    actual: actual.cloned(),
+                });
+            }
+        }
+
+        ProfileDiff { differences }
+    }
+}
+
+fn describe_macro(m: Option<&Macro>) -> String {
+    let Some(m) = m else {
+        return "nothing".to_string();
+    };
+    let steps = match macro_steps(&m.events) {
+        steps if steps.is_empty() => "no events".to_string(),
+        steps => steps.join(", "),
+    };
+    match m.repeat {
+        1 => format!("[{steps}]"),
+        repeat => format!("[{steps}] {repeat} times"),
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Key {
+                name,
+                expected,
+                actual,
+                ..
+            } => write!(f, "{name}: expected {expected}, found {actual}"),
+            Self::Lighting { expected, actual } => {
+                let mut changes = Vec::new();
+                if expected.mode != actual.mode {
+                    changes.push(format!("mode {} -> {}", expected.mode, actual.mode));
+                }
+                if expected.speed != actual.speed {
+                    changes.push(format!("speed {} -> {}", expected.speed, actual.speed));
+                }
+                if expected.direction != actual.direction {
+                    let (from, to) = (expected.direction, actual.direction);
+                    changes.push(format!("direction {from} -> {to}"));
+                }
+                if expected.brightness != actual.brightness {
+                    let (from, to) = (expected.brightness, actual.brightness);
+                    changes.push(format!("brightness {from} -> {to}"));
+                }
+                write!(f, "lighting: {}", changes.join(", "))
+            }
+            Self::Color {
+                name,
+                expected,
+                actual,
+                ..
+            } => write!(
+                f,
+                "{name} colour: expected {}, found {}",
+                hex_color(*expected),
+                hex_color(*actual)
+            ),
+            Self::Macro {
+                slot,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "macro {slot}: expected {}, found {}",
+                describe_macro(expected.as_ref()),
+                describe_macro(actual.as_ref())
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ProfileDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "no differences");
+        }
+        for difference in &self.differences {
+            writeln!(f, "{difference}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::protocol::{usage, Direction, LightingMode, Modifiers};
+
+    fn profile() -> Profile {
+        Profile {
+            keys: [KeyBinding::key(usage::keyboard::F13); KEY_COUNT],
+            lighting: LightingSettings {
+                mode: LightingMode::Wave,
+                speed: 40,
+                direction: Direction::LeftToRight,
+                brightness: 255,
+            },
+            colors: [[0xFF; 3]; KEY_COUNT],
+            macros: [(2, Macro::new().tap(usage::keyboard::A))].into(),
+            actions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_identical() {
+        let diff = profile().diff(&profile());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no differences\n");
+    }
+
+    #[test]
+    fn test_differences() {
+        let expected = profile();
+        let mut actual = profile();
+        actual.keys[1] = KeyBinding::combo(Modifiers::LEFT_CTRL, usage::keyboard::A);
+        actual.lighting.mode = LightingMode::Static;
+        actual.lighting.brightness = 128;
+        actual.colors[7] = [0xFF, 0x80, 0x00];
+        actual
+            .macros
+            .insert(2, Macro::new().repeat(3).tap(usage::keyboard::A).delay(20));
+        actual.macros.insert(5, Macro::new());
+
+        let diff = expected.diff(&actual);
+        assert_eq!(
+            diff.differences[0],
+            Difference::Key {
+                key: 1,
+                name: "key2".to_string(),
+                expected: expected.keys[1],
+                actual: actual.keys[1],
+            }
+        );
+        assert_eq!(
+            diff.to_string(),
+            "key2: expected F13, found Ctrl+A\n\
+             lighting: mode wave -> static, brightness 255 -> 128\n\
+             key8 colour: expected #FFFFFF, found #FF8000\n\
+             macro 2: expected [tap A], found [tap A, delay 20] 3 times\n\
+             macro 5: expected nothing, found [no events]\n"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let mut actual = profile();
+        actual.colors[0] = [1, 2, 3];
+        actual.macros.clear();
+
+        let json = serde_json::to_value(profile().diff(&actual)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "differences": [
+                    {"kind": "color", "key": 0, "name": "key1", "expected": [255, 255, 255], "actual": [1, 2, 3]},
+                    {
+                        "kind": "macro",
+                        "slot": 2,
+                        "expected": {
+                            "repeat": 1,
+                            "events": [
+                                {"type": "key_down", "usage": 4},
+                                {"type": "key_up", "usage": 4},
+                            ],
+                        },
+                        "actual": null,
+                    },
+                ],
+            })
+        );
+    }
+}
@@ -0,0 +1,309 @@
+//! Snapshots of the keypad's whole configuration memory, to put it back exactly as it was.
+//!
+//! A dump file is a header followed by the memory image, all little-endian:
+//!
+//! ```text
+//! 0x00  "F8DUMP"         magic
+//! 0x06  u16              format version
+//! 0x08  u16  u16         vendor and product ID
+//! 0x0C  u8 u8 u8 u16 u8  firmware major, minor, patch, build and protocol version
+//! 0x12  2 bytes          reserved, zero
+//! 0x14  u64              when the dump was taken, in seconds since the Unix epoch
+//! 0x1C  u32              length of the memory image
+//! 0x20  u32              CRC-32 of everything except this field
+//! 0x24                   memory image
+//! ```
+
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    protocol::{FirmwareInfoReport, MEMORY_SIZE},
+    transport::Transport,
+    Falcon8, Falcon8Error, Result, PID, VID,
+};
+
+const MAGIC: &[u8; 6] = b"F8DUMP";
+const FORMAT_VERSION: u16 = 1;
+const HEADER_SIZE: usize = 0x24;
+const CRC_OFFSET: usize = 0x20;
+
+/// CRC-32 (IEEE 802.3), as used by zip and PNG.
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in chunks.iter().flat_map(|chunk| chunk.iter()) {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+/// The keypad's configuration memory, key maps, lighting and macros included, and where it
+/// came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dump {
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub firmware: FirmwareInfoReport,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub memory: Vec<u8>,
+}
+
+impl Dump {
+    pub fn encode(&self) -> Vec<u8> {
+        let firmware = &self.firmware;
+        let mut data = Vec::with_capacity(HEADER_SIZE + self.memory.len());
+        data.extend_from_slice(MAGIC);
+        data.extend(FORMAT_VERSION.to_le_bytes());
+        data.extend(self.vendor_id.to_le_bytes());
+        data.extend(self.product_id.to_le_bytes());
+        data.extend([firmware.major, firmware.minor, firmware.patch]);
+        data.extend(firmware.build.to_le_bytes());
+        data.extend([firmware.protocol_version, 0, 0]);
+        data.extend(self.timestamp.to_le_bytes());
+        data.extend((self.memory.len() as u32).to_le_bytes());
+
+        let crc = crc32(&[&data, &self.memory]);
+        data.extend(crc.to_le_bytes());
+        data.extend_from_slice(&self.memory);
+        data
+    }
+
+    /// Parses a dump, checking its CRC.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.len() < HEADER_SIZE || &data[..MAGIC.len()] != MAGIC {
+            return Err(Falcon8Error::InvalidDump("not a Falcon-8 dump"));
+        }
+        let u16_at = |offset: usize| u16::from_le_bytes([data[offset], data[offset + 1]]);
+        if u16_at(0x06) != FORMAT_VERSION {
+            return Err(Falcon8Error::InvalidDump(
+                "written by a newer version of Falcon8Touch",
+            ));
+        }
+
+        let len = u32::from_le_bytes(data[0x1C..0x20].try_into().unwrap()) as usize;
+        let memory = &data[HEADER_SIZE..];
+        if memory.len() != len {
+            return Err(Falcon8Error::InvalidDump("truncated"));
+        }
+        let expected = u32::from_le_bytes(data[CRC_OFFSET..HEADER_SIZE].try_into().unwrap());
+        let actual = crc32(&[&data[..CRC_OFFSET], memory]);
+        if actual != expected {
+            return Err(Falcon8Error::DumpChecksumMismatch { expected, actual });
+        }
+
+        Ok(Self {
+            vendor_id: u16_at(0x08),
+            product_id: u16_at(0x0A),
+            firmware: FirmwareInfoReport {
+                major: data[0x0C],
+                minor: data[0x0D],
+                patch: data[0x0E],
+                build: u16_at(0x0F),
+                protocol_version: data[0x11],
+            },
+            timestamp: u64::from_le_bytes(data[0x14..0x1C].try_into().unwrap()),
+            memory: memory.to_vec(),
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::decode(&fs::read(path).map_err(|e| Falcon8Error::file(path, e))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.encode()).map_err(|e| Falcon8Error::file(path, e))
+    }
+
+    pub fn firmware_version(&self) -> String {
+        let firmware = &self.firmware;
+        format!("{}.{}.{}", firmware.major, firmware.minor, firmware.patch)
+    }
+}
+
+impl<T: Transport> Falcon8<T> {
+    /// Reads the whole configuration memory.
+    pub fn dump(&self) -> Result<Dump> {
+        let _guard = self.transaction()?;
+        let firmware = self.firmware_info()?;
+        let memory = self.read_memory(0, MEMORY_SIZE)?;
+
+        Ok(Dump {
+            vendor_id: VID,
+            product_id: PID,
+            firmware,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            memory,
+        })
+    }
+
+    /// Fails unless `dump` was taken from a keypad with the same memory layout as this one.
+    pub fn check_dump(&self, dump: &Dump) -> Result<()> {
+        let firmware = self.firmware_info()?;
+        let incompatible = |reason: String| Err(Falcon8Error::IncompatibleDump(reason));
+
+        if (dump.vendor_id, dump.product_id) != (VID, PID) {
+            return incompatible(format!(
+                "it was taken from a different device ({:04x}:{:04x})",
+                dump.vendor_id, dump.product_id
+            ));
+        }
+        if dump.firmware.protocol_version != firmware.protocol_version {
+            return incompatible(format!(
+                "it was taken with firmware {} (protocol version {}), this keypad runs \
+                 protocol version {}",
+                dump.firmware_version(),
+                dump.firmware.protocol_version,
+                firmware.protocol_version
+            ));
+        }
+        if dump.memory.len() != MEMORY_SIZE {
+            return incompatible(format!(
+                "it holds {} bytes of memory, this keypad has {MEMORY_SIZE}",
+                dump.memory.len()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Writes `dump` back to the keypad and reads it back to make sure it took. Nothing is
+    /// written unless `check_dump` passes.
+    pub fn restore(&self, dump: &Dump) -> Result<()> {
+        let _guard = self.transaction()?;
+        self.check_dump(dump)?;
+
+        self.write_memory(0, &dump.memory)?;
+        let written = self.read_memory(0, MEMORY_SIZE)?;
+        match written.iter().zip(&dump.memory).position(|(a, b)| a != b) {
+            Some(address) => Err(Falcon8Error::RestoreMismatch { address }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::{
+        protocol::{KeyBinding, Macro, PROTOCOL_VERSION},
+        transport::SimulatedFalcon8,
+    };
+
+    fn simulated() -> Falcon8<SimulatedFalcon8> {
+        Falcon8::with_transport(SimulatedFalcon8::new())
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(&[b"123456789"]), 0xCBF4_3926);
+        assert_eq!(crc32(&[b"1234", b"56789"]), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let dump = simulated().dump().unwrap();
+        assert_eq!(dump.memory.len(), MEMORY_SIZE);
+        assert_eq!(dump.firmware.protocol_version, PROTOCOL_VERSION);
+
+        let data = dump.encode();
+        assert_eq!(data.len(), HEADER_SIZE + MEMORY_SIZE);
+        assert_eq!(&data[..6], b"F8DUMP");
+        assert_eq!(Dump::decode(&data), Ok(dump));
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let data = simulated().dump().unwrap().encode();
+
+        let mut corrupted = data.clone();
+        corrupted[HEADER_SIZE + 5] ^= 0x01;
+        assert!(matches!(
+            Dump::decode(&corrupted),
+            Err(Falcon8Error::DumpChecksumMismatch { .. })
+        ));
+
+        let mut timestamp = data.clone();
+        timestamp[0x14] ^= 0x01;
+        assert!(matches!(
+            Dump::decode(&timestamp),
+            Err(Falcon8Error::DumpChecksumMismatch { .. })
+        ));
+
+        assert_eq!(
+            Dump::decode(&data[..data.len() - 1]),
+            Err(Falcon8Error::InvalidDump("truncated"))
+        );
+        assert_eq!(
+            Dump::decode(b"PK\x03\x04"),
+            Err(Falcon8Error::InvalidDump("not a Falcon-8 dump"))
+        );
+    }
+
+    #[test]
+    fn test_restore() {
+        let falcon = simulated();
+        falcon.set_key_binding(0, KeyBinding::key(0x04)).unwrap();
+        let dump = falcon.dump().unwrap();
+
+        falcon.set_key_binding(0, KeyBinding::key(0x05)).unwrap();
+        falcon.upload_macro(3, &Macro::new().tap(0x04)).unwrap();
+        assert_ne!(falcon.transport.memory(), dump.memory);
+
+        falcon.restore(&dump).unwrap();
+        assert_eq!(falcon.transport.memory(), dump.memory);
+        assert_eq!(falcon.key_map().unwrap()[0], KeyBinding::key(0x04));
+    }
+
+    #[test]
+    fn test_restore_checks_compatibility() {
+        let falcon = simulated();
+        let before = falcon.transport.memory();
+        let dump = Dump {
+            memory: vec![0xAA; MEMORY_SIZE],
+            ..falcon.dump().unwrap()
+        };
+
+        let other_device = Dump {
+            product_id: 0x1234,
+            ..dump.clone()
+        };
+        let other_protocol = Dump {
+            firmware: FirmwareInfoReport {
+                protocol_version: PROTOCOL_VERSION + 1,
+                ..dump.firmware
+            },
+            ..dump.clone()
+        };
+        let short = Dump {
+            memory: vec![0xAA; 16],
+            ..dump.clone()
+        };
+        for dump in [other_device, other_protocol, short] {
+            assert!(matches!(
+                falcon.restore(&dump),
+                Err(Falcon8Error::IncompatibleDump(_))
+            ));
+        }
+        assert_eq!(falcon.transport.memory(), before);
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let path = std::env::temp_dir().join(format!("falcon8-dump-{}.bin", std::process::id()));
+        let dump = simulated().dump().unwrap();
+        dump.save(&path).unwrap();
+        assert_eq!(Dump::load(&path), Ok(dump));
+        fs::remove_file(path).unwrap();
+    }
+}
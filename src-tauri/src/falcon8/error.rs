@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Serialize, Serializer};
+
+use super::{
+    protocol::{ComboError, DecodeError, KeyBinding, UntypeableText},
+    virtual_keyboard::Key,
+};
+
+pub type Result<T> = std::result::Result<T, Falcon8Error>;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum Falcon8Error {
+    #[error("no Falcon-8 keypad found, is it plugged in?")]
+    NotFound,
+    #[error("the Falcon-8 was disconnected")]
+    Disconnected,
+    #[error(
+        "permission denied opening the Falcon-8; install the udev rule from \
+         udev/70-falcon8.rules and replug the keypad"
+    )]
+    PermissionDenied,
+    #[error("the Falcon-8 is busy, close any other program configuring it and try again")]
+    Busy,
+    #[error("timed out waiting for the Falcon-8")]
+    Timeout,
+    #[error("could not read the USB configuration descriptor: {0}")]
+    Descriptor(rusb::Error),
+    #[error("USB error: {0}")]
+    Usb(rusb::Error),
+    #[error("report {report_id:#04x} checksum is {actual:#04x}, expected {expected:#04x}")]
+    ChecksumMismatch {
+        report_id: u8,
+        expected: u8,
+        actual: u8,
+    },
+    #[error("malformed report from the Falcon-8: {0}")]
+    Protocol(DecodeError),
+    /// A 0-based key index, as the API takes them, rather than a `key1` to `key8` name.
+    #[error("key index {0} does not exist, indexes run from 0 (key1) to 7 (key8)")]
+    InvalidKey(usize),
+    #[error("report {report_id:#04x} cannot be {len} bytes long")]
+    InvalidReport { report_id: u8, len: usize },
+    #[error("short transfer: {actual} of {expected} bytes")]
+    ShortTransfer { expected: usize, actual: usize },
+    #[error(
+        "unsupported firmware {version} (protocol version {protocol_version}), \
+         update the keypad's firmware"
+    )]
+    UnsupportedFirmware {
+        version: String,
+        protocol_version: u8,
+    },
+    #[error("profile slot {0} does not exist on this keypad")]
+    InvalidProfileSlot(u8),
+    #[error("memory range {address:#06x}+{len} is outside the keypad's configuration memory")]
+    InvalidMemoryRange { address: usize, len: usize },
+    #[error("asked for memory at {expected:#06x}, the keypad returned {actual:#06x}")]
+    MemoryReadMismatch { expected: u16, actual: u16 },
+    #[error(
+        "asked for {expected} bytes of memory at {address:#06x}, the keypad returned {actual}"
+    )]
+    MemoryReadLength {
+        address: u16,
+        expected: u8,
+        actual: u8,
+    },
+    #[error("macro slot {0} does not exist, slots are numbered 0 to 15")]
+    InvalidMacroSlot(usize),
+    #[error("invalid macro: {0}")]
+    InvalidMacro(&'static str),
+    #[error("macro needs {size} bytes but a macro slot only holds {capacity}, remove some events")]
+    MacroTooLarge { size: usize, capacity: usize },
+    #[error("animations run at 1 to 60 frames per second, not {0}")]
+    InvalidFrameRate(u32),
+    #[error("{0:?} is not a device index, serial number, port path or nickname")]
+    InvalidSelector(String),
+    #[error("no Falcon-8 matches {0}")]
+    NoMatchingDevice(String),
+    #[error("{count} keypads match {selector}, pick one by serial number or port path")]
+    AmbiguousDevice { selector: String, count: usize },
+    #[error("cannot use {name:?} as a nickname: {reason}")]
+    InvalidNickname { name: String, reason: &'static str },
+    #[error("line {line}, column {column}: {message}")]
+    ProfileSyntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    #[error("not a valid dump: {0}")]
+    InvalidDump(&'static str),
+    #[error("dump is corrupted: its CRC is {actual:#010x}, expected {expected:#010x}")]
+    DumpChecksumMismatch { expected: u32, actual: u32 },
+    #[error("cannot restore this dump: {0}")]
+    IncompatibleDump(String),
+    #[error("restored memory reads back differently at {address:#06x}, restore the dump again")]
+    RestoreMismatch { address: usize },
+    #[error("{}: {message}", path.display())]
+    File { path: PathBuf, message: String },
+    #[error("could not run {command:?}: {message}")]
+    CommandFailed { command: String, message: String },
+    #[error("{0} is not supported on this system")]
+    ActionUnsupported(&'static str),
+    #[error(
+        "permission denied opening /dev/uinput; install the udev rule from \
+         udev/70-falcon8.rules and log in again"
+    )]
+    UinputPermissionDenied,
+    #[error("virtual keyboard error: {0}")]
+    VirtualKeyboard(String),
+    #[error("the virtual keyboard has no {0} key")]
+    UnmappedKey(Key),
+    #[error("{0} can't be sent as a key combination")]
+    NotAKeyCombo(KeyBinding),
+    #[error("{0}")]
+    Untypeable(UntypeableText),
+    #[error("{0}")]
+    InvalidCombo(ComboError),
+}
+
+impl Falcon8Error {
+    pub(crate) fn file(path: &Path, error: impl std::fmt::Display) -> Self {
+        Self::File {
+            path: path.to_path_buf(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl From<rusb::Error> for Falcon8Error {
+    fn from(error: rusb::Error) -> Self {
+        match error {
+            rusb::Error::Access => Self::PermissionDenied,
+            rusb::Error::NoDevice => Self::Disconnected,
+            rusb::Error::Busy => Self::Busy,
+            rusb::Error::Timeout => Self::Timeout,
+            error => Self::Usb(error),
+        }
+    }
+}
+
+impl From<DecodeError> for Falcon8Error {
+    fn from(error: DecodeError) -> Self {
+        match error {
+            DecodeError::Checksum {
+                report_id,
+                expected,
+                actual,
+            } => Self::ChecksumMismatch {
+                report_id,
+                expected,
+                actual,
+            },
+            error => Self::Protocol(error),
+        }
+    }
+}
+
+impl From<UntypeableText> for Falcon8Error {
+    fn from(error: UntypeableText) -> Self {
+        Self::Untypeable(error)
+    }
+}
+
+impl From<ComboError> for Falcon8Error {
+    fn from(error: ComboError) -> Self {
+        Self::InvalidCombo(error)
+    }
+}
+
+/// Tauri hands command errors to the frontend serialized, and the message is what it shows.
+impl Serialize for Falcon8Error {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    ops::Deref,
+    sync::{Condvar, Mutex, MutexGuard},
+    thread::{self, ThreadId},
+};
+
+use super::{transport::Transport, Falcon8, Result, CONFIG_INTERFACE};
+
+/// Claim bookkeeping shared by every `InterfaceGuard` of one `Falcon8`.
+#[derive(Debug, Default)]
+pub(crate) struct ClaimState {
+    count: usize,
+    reattach: bool,
+}
+
+/// Exclusive access to the configuration interface.
+///
+/// Creating the first guard detaches the kernel HID driver from `CONFIG_INTERFACE` and claims
+/// it; dropping the last one releases the interface and hands it back to the kernel, whether the
+/// guard goes out of scope normally, through an early `?` return, or while unwinding a panic.
+/// Only the configuration interface is touched, so the keypad keeps typing throughout.
+#[derive(Debug)]
+pub struct InterfaceGuard<'a, T: Transport> {
+    falcon: &'a Falcon8<T>,
+}
+
+impl<'a, T: Transport> InterfaceGuard<'a, T> {
+    pub(crate) fn new(falcon: &'a Falcon8<T>) -> Result<Self> {
+        let mut claim = falcon.claim_state();
+
+        if claim.count == 0 {
+            let transport = &falcon.transport;
+            // Platforms without kernel driver support report NotSupported here.
+            let reattach = transport
+                .kernel_driver_active(CONFIG_INTERFACE)
+                .unwrap_or(false);
+            if reattach {
+                transport.detach_kernel_driver(CONFIG_INTERFACE)?;
+            }
+
+            if let Err(e) = transport.claim_interface(CONFIG_INTERFACE) {
+                if reattach {
+                    let _ = transport.attach_kernel_driver(CONFIG_INTERFACE);
+                }
+                return Err(e.into());
+            }
+            claim.reattach = reattach;
+        }
+
+        claim.count += 1;
+        Ok(Self { falcon })
+    }
+}
+
+/// Lets one thread at a time run transactions on a `Falcon8`, nested as deeply as it likes.
+#[derive(Debug, Default)]
+pub(crate) struct TransactionLock {
+    /// The thread in a transaction, and how many it's nested in.
+    owner: Mutex<Option<(ThreadId, usize)>>,
+    released: Condvar,
+}
+
+impl TransactionLock {
+    fn owner(&self) -> MutexGuard<'_, Option<(ThreadId, usize)>> {
+        self.owner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn enter(&self) {
+        let me = thread::current().id();
+        let mut owner = self.owner();
+        loop {
+            match &mut *owner {
+                None => {
+                    *owner = Some((me, 1));
+                    return;
+                }
+                Some((thread, depth)) if *thread == me => {
+                    *depth += 1;
+                    return;
+                }
+                Some(_) => owner = self.released.wait(owner).unwrap_or_else(|e| e.into_inner()),
+            }
+        }
+    }
+
+    fn leave(&self) {
+        let mut owner = self.owner();
+        if let Some((_, depth)) = &mut *owner {
+            *depth -= 1;
+            if *depth == 0 {
+                *owner = None;
+                self.released.notify_one();
+            }
+        }
+    }
+}
+
+/// A run of transfers that no other thread's transfers get in between, such as selecting a
+/// profile slot and then reading it.
+///
+/// Every report call runs in one. Holds the configuration interface like an `InterfaceGuard`;
+/// calls from other threads wait for it to be dropped, while those on the same thread join it.
+#[derive(Debug)]
+pub struct Transaction<'a, T: Transport> {
+    claim: InterfaceGuard<'a, T>,
+}
+
+impl<'a, T: Transport> Transaction<'a, T> {
+    pub(crate) fn new(falcon: &'a Falcon8<T>) -> Result<Self> {
+        falcon.transaction.enter();
+        match falcon.claim() {
+            Ok(claim) => Ok(Self { claim }),
+            Err(e) => {
+                falcon.transaction.leave();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<T: Transport> Deref for Transaction<'_, T> {
+    type Target = Falcon8<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.claim.falcon
+    }
+}
+
+impl<T: Transport> Drop for Transaction<'_, T> {
+    fn drop(&mut self) {
+        self.claim.falcon.transaction.leave();
+    }
+}
+
+impl<T: Transport> Deref for InterfaceGuard<'_, T> {
+    type Target = Falcon8<T>;
+
+    fn deref(&self) -> &Self::Target {
+        self.falcon
+    }
+}
+
+impl<T: Transport> Drop for InterfaceGuard<'_, T> {
+    fn drop(&mut self) {
+        let mut claim = self.falcon.claim_state();
+        claim.count -= 1;
+        if claim.count > 0 {
+            return;
+        }
+
+        let transport = &self.falcon.transport;
+        if let Err(e) = transport.release_interface(CONFIG_INTERFACE) {
+            eprintln!("Failed to release interface {CONFIG_INTERFACE}: {e}");
+        }
+        if std::mem::take(&mut claim.reattach) {
+            if let Err(e) = transport.attach_kernel_driver(CONFIG_INTERFACE) {
+                eprintln!("Failed to reattach kernel driver to interface {CONFIG_INTERFACE}: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Notices keypads being plugged in, unplugged, or re-enumerating.
+//!
+//! libusb's hotplug callbacks are used where the platform has them; elsewhere the bus is polled.
+
+use std::{
+    collections::BTreeSet,
+    sync::mpsc::{self, RecvTimeoutError, Sender, TryRecvError},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use rusb::{Device, Hotplug, HotplugBuilder, UsbContext};
+use serde::{Deserialize, Serialize};
+
+use super::{transport::port_path, Result, PID, VID};
+
+/// How long the event loop blocks in libusb, and how often the fallback rescans the bus.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const RESCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// udev applies device permissions shortly after the kernel announces the device, so opening it
+/// straight away can fail with EACCES.
+const SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct DeviceLocation {
+    pub bus_number: u8,
+    pub address: u8,
+    /// Bus and port chain in sysfs notation, e.g. `3-1.4`.
+    pub port_path: String,
+}
+
+impl DeviceLocation {
+    fn of<C: UsbContext>(device: &Device<C>) -> Self {
+        Self {
+            bus_number: device.bus_number(),
+            address: device.address(),
+            port_path: port_path(
+                device.bus_number(),
+                &device.port_numbers().unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "device", rename_all = "snake_case")]
+pub enum HotplugEvent {
+    DeviceAdded(DeviceLocation),
+    DeviceRemoved(DeviceLocation),
+}
+
+impl HotplugEvent {
+    /// Name of the Tauri event this is forwarded as.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DeviceAdded(_) => "device-added",
+            Self::DeviceRemoved(_) => "device-removed",
+        }
+    }
+
+    pub fn location(&self) -> &DeviceLocation {
+        match self {
+            Self::DeviceAdded(location) | Self::DeviceRemoved(location) => location,
+        }
+    }
+}
+
+/// Events turning the `previous` set of keypads into the `current` one.
+fn diff(
+    previous: &BTreeSet<DeviceLocation>,
+    current: &BTreeSet<DeviceLocation>,
+) -> Vec<HotplugEvent> {
+    let removed = previous
+        .difference(current)
+        .cloned()
+        .map(HotplugEvent::DeviceRemoved);
+    let added = current
+        .difference(previous)
+        .cloned()
+        .map(HotplugEvent::DeviceAdded);
+    removed.chain(added).collect()
+}
+
+fn scan<C: UsbContext>(context: &C) -> BTreeSet<DeviceLocation> {
+    let Ok(devices) = context.devices() else {
+        return BTreeSet::new();
+    };
+
+    devices
+        .iter()
+        .filter(|device| {
+            device
+                .device_descriptor()
+                .is_ok_and(|desc| desc.vendor_id() == VID && desc.product_id() == PID)
+        })
+        .map(|device| DeviceLocation::of(&device))
+        .collect()
+}
+
+fn dispatch(event: HotplugEvent, on_event: &mut impl FnMut(HotplugEvent)) {
+    if matches!(event, HotplugEvent::DeviceAdded(_)) {
+        thread::sleep(SETTLE_DELAY);
+    }
+    on_event(event);
+}
+
+/// Runs inside libusb's event handling, where blocking calls on device handles aren't allowed,
+/// so events are only queued here and handed out from the watcher thread afterwards.
+struct Forwarder {
+    events: Sender<HotplugEvent>,
+}
+
+impl<C: UsbContext> Hotplug<C> for Forwarder {
+    fn device_arrived(&mut self, device: Device<C>) {
+        let _ = self
+            .events
+            .send(HotplugEvent::DeviceAdded(DeviceLocation::of(&device)));
+    }
+
+    fn device_left(&mut self, device: Device<C>) {
+        let _ = self
+            .events
+            .send(HotplugEvent::DeviceRemoved(DeviceLocation::of(&device)));
+    }
+}
+
+/// Watches the bus for Falcon-8s coming and going, calling `on_event` from a background thread.
+/// Watching stops when this is dropped.
+#[derive(Debug)]
+pub struct HotplugWatcher {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HotplugWatcher {
+    pub fn start<C: UsbContext + 'static>(
+        context: C,
+        on_event: impl FnMut(HotplugEvent) + Send + 'static,
+    ) -> Result<Self> {
+        if rusb::has_hotplug() {
+            Self::start_native(context, on_event)
+        } else {
+            Ok(Self::start_polling(context, on_event))
+        }
+    }
+
+    fn start_native<C: UsbContext + 'static>(
+        context: C,
+        mut on_event: impl FnMut(HotplugEvent) + Send + 'static,
+    ) -> Result<Self> {
+        let (stop, stopped) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let (events, queued) = mpsc::channel();
+            let registration = HotplugBuilder::new()
+                .vendor_id(VID)
+                .product_id(PID)
+                .register::<C, _>(&context, Box::new(Forwarder { events }));
+            let _registration = match registration {
+                Ok(registration) => {
+                    let _ = ready_tx.send(Ok(()));
+                    registration
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return;
+                }
+            };
+
+            while matches!(stopped.try_recv(), Err(TryRecvError::Empty)) {
+                if let Err(e) = context.handle_events(Some(POLL_INTERVAL)) {
+                    eprintln!("Failed to handle USB events: {e}");
+                    thread::sleep(POLL_INTERVAL);
+                }
+                for event in queued.try_iter() {
+                    dispatch(event, &mut on_event);
+                }
+            }
+        });
+
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                stop: Some(stop),
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(rusb::Error::Other.into()),
+        }
+    }
+
+    /// Rescans the bus every second instead of relying on libusb hotplug support.
+    pub fn start_polling<C: UsbContext + 'static>(
+        context: C,
+        mut on_event: impl FnMut(HotplugEvent) + Send + 'static,
+    ) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread = thread::spawn(move || {
+            let mut known = scan(&context);
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(RESCAN_INTERVAL) {
+                let current = scan(&context);
+                for event in diff(&known, &current) {
+                    dispatch(event, &mut on_event);
+                }
+                known = current;
+            }
+        });
+
+        Self {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for HotplugWatcher {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(address: u8, port_path: &str) -> DeviceLocation {
+        DeviceLocation {
+            bus_number: 1,
+            address,
+            port_path: port_path.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let previous = BTreeSet::from([location(3, "1-1"), location(4, "1-2")]);
+        let current = BTreeSet::from([location(4, "1-2"), location(7, "1-1")]);
+
+        assert_eq!(
+            diff(&previous, &current),
+            [
+                HotplugEvent::DeviceRemoved(location(3, "1-1")),
+                HotplugEvent::DeviceAdded(location(7, "1-1")),
+            ]
+        );
+        assert!(diff(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = HotplugEvent::DeviceAdded(location(3, "1-1"));
+        assert_eq!(event.name(), "device-added");
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "kind": "device_added",
+                "device": { "bus_number": 1, "address": 3, "port_path": "1-1" },
+            })
+        );
+    }
+}
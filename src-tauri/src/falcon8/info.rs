@@ -0,0 +1,121 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    protocol::FirmwareInfoReport,
+    transport::{Transport, UsbSpeed},
+    Falcon8, Result, TIMEOUT,
+};
+
+/// Everything there is to know about a connected keypad without reading its configuration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceInfo {
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+    pub language: Option<u16>,
+    pub active_configuration: u8,
+    pub bus_number: u8,
+    pub address: u8,
+    /// Bus and port chain in sysfs notation, e.g. `3-1.4`.
+    pub port_path: String,
+    pub speed: UsbSpeed,
+    /// bcdDevice from the device descriptor, e.g. `1.20`.
+    pub device_version: String,
+    /// Version reported by the firmware itself over the vendor interface.
+    pub firmware: FirmwareInfoReport,
+}
+
+impl DeviceInfo {
+    pub fn firmware_version(&self) -> String {
+        let firmware = &self.firmware;
+        format!(
+            "{}.{}.{} (build {})",
+            firmware.major, firmware.minor, firmware.patch, firmware.build
+        )
+    }
+}
+
+impl fmt::Display for DeviceInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_unknown = |s: &Option<String>| s.clone().unwrap_or_else(|| "Not Found".to_string());
+
+        writeln!(f, "Manufacturer: {}", or_unknown(&self.manufacturer))?;
+        writeln!(f, "Product: {}", or_unknown(&self.product))?;
+        writeln!(f, "Serial Number: {}", or_unknown(&self.serial_number))?;
+        if let Some(language) = self.language {
+            writeln!(f, "Language: {language:#06x}")?;
+        }
+        writeln!(f, "Active configuration: {}", self.active_configuration)?;
+        writeln!(
+            f,
+            "Location: {} (bus {}, address {})",
+            self.port_path, self.bus_number, self.address
+        )?;
+        writeln!(f, "Speed: {:?}", self.speed)?;
+        writeln!(f, "Device version: {}", self.device_version)?;
+        write!(
+            f,
+            "Firmware: {} (protocol {})",
+            self.firmware_version(),
+            self.firmware.protocol_version
+        )
+    }
+}
+
+impl<T: Transport> Falcon8<T> {
+    pub fn device_info(&self) -> Result<DeviceInfo> {
+        let strings = self.transport.read_strings(TIMEOUT)?;
+        let usb = self.transport.usb_details()?;
+        let (major, minor, sub_minor) = usb.device_version;
+
+        Ok(DeviceInfo {
+            manufacturer: strings.manufacturer,
+            product: strings.product,
+            serial_number: strings.serial_number,
+            language: strings.language,
+            active_configuration: self.transport.active_configuration()?,
+            bus_number: usb.bus_number,
+            address: usb.address,
+            port_path: usb.port_path(),
+            speed: usb.speed,
+            device_version: format!("{major}.{minor}{sub_minor}"),
+            firmware: self.read_report()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::transport::SimulatedFalcon8;
+
+    #[test]
+    fn test_device_info() {
+        let sim = SimulatedFalcon8::new();
+        sim.set_location(3, &[1, 4]);
+        let falcon = Falcon8::with_transport(sim);
+
+        let info = falcon.device_info().unwrap();
+        assert_eq!(info.product.as_deref(), Some("Falcon-8"));
+        assert_eq!(info.serial_number.as_deref(), Some("SIM00001"));
+        assert_eq!(info.port_path, "3-1.4");
+        assert_eq!(info.speed, UsbSpeed::Full);
+        assert_eq!(info.device_version, "1.20");
+        assert_eq!(info.firmware_version(), "1.2.0 (build 42)");
+
+        let text = info.to_string();
+        assert!(text.contains("Location: 3-1.4 (bus 3, address 5)"));
+        assert!(text.ends_with("Firmware: 1.2.0 (build 42) (protocol 1)"));
+    }
+
+    #[test]
+    fn test_device_info_serializes() {
+        let falcon = Falcon8::with_transport(SimulatedFalcon8::new());
+        let json = serde_json::to_value(falcon.device_info().unwrap()).unwrap();
+        assert_eq!(json["speed"], "full");
+        assert_eq!(json["port_path"], "1-2");
+        assert_eq!(json["firmware"]["build"], 42);
+    }
+}
@@ -0,0 +1,233 @@
+//! Key presses read live from the keypad, for testing keys and for acting on them on the host.
+//!
+//! The keypad sends a `KeyStateReport` on the configuration interface's interrupt endpoint each
+//! time a key goes down or comes up. Consecutive reports are compared to tell which keys changed.
+
+use std::{
+    sync::{
+        mpsc::{self, Sender, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    protocol::{FeatureReport, KeyStateReport, KEY_COUNT},
+    transport::Transport,
+    Falcon8, Falcon8Error, Result, CONFIG_INTERFACE,
+};
+
+/// How long each read waits for a report, which bounds how long stopping takes.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Full-speed interrupt endpoints can't send more than this in one packet.
+const MAX_PACKET_SIZE: usize = 64;
+
+/// A key going down or coming up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyEvent {
+    /// 0 to 7, from left to right.
+    pub key_index: usize,
+    pub pressed: bool,
+    /// When the report arrived, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+/// Events turning the `previous` key state into the `current` one, in key order.
+fn key_events(previous: u8, current: u8, timestamp: u64) -> impl Iterator<Item = KeyEvent> {
+    (0..KEY_COUNT)
+        .filter(move |key| (previous ^ current) & 1 << key != 0)
+        .map(move |key| KeyEvent {
+            key_index: key,
+            pressed: current & 1 << key != 0,
+            timestamp,
+        })
+}
+
+/// The time in the same form as `KeyEvent::timestamp`.
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+impl<T: Transport> Falcon8<T> {
+    /// The interrupt endpoint key state reports arrive on.
+    pub fn key_event_endpoint(&self) -> Result<u8> {
+        self.find_readable_endpoints()?
+            .into_iter()
+            .find(|endpoint| endpoint.iface == CONFIG_INTERFACE)
+            .map(|endpoint| endpoint.address)
+            .ok_or(Falcon8Error::Descriptor(rusb::Error::NotFound))
+    }
+}
+
+/// Reads key presses from a keypad on a background thread, calling `on_event` for each one.
+/// Stops when dropped.
+///
+/// Like `Animation`, the thread keeps the configuration interface claimed while it runs; other
+/// commands to the same keypad still go through.
+#[derive(Debug)]
+pub struct KeyEventReader {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl KeyEventReader {
+    pub fn start<T: Transport + 'static>(
+        falcon: Arc<Falcon8<T>>,
+        mut on_event: impl FnMut(KeyEvent) + Send + 'static,
+    ) -> Result<Self> {
+        let endpoint = falcon.key_event_endpoint()?;
+        let (stop, stopped) = mpsc::channel::<()>();
+        let (ready_tx, ready_rx) = mpsc::channel();
+
+        let thread = thread::spawn(move || {
+            let _guard = match falcon.claim() {
+                Ok(guard) => {
+                    let _ = ready_tx.send(Ok(()));
+                    guard
+                }
+                Err(e) => {
+                    let _ = ready_tx.send(Err(e));
+                    return Ok(());
+                }
+            };
+            let mut pressed = 0;
+            let mut buf = [0; MAX_PACKET_SIZE];
+
+            while let Err(TryRecvError::Empty) = stopped.try_recv() {
+                let len = match falcon
+                    .transport
+                    .read_interrupt(endpoint, &mut buf, READ_TIMEOUT)
+                {
+                    Ok(len) => len,
+                    Err(rusb::Error::Timeout) => continue,
+                    Err(e) => return Err(e.into()),
+                };
+                let report = match KeyStateReport::decode(&buf[..len]) {
+                    Ok(report) => report,
+                    Err(e) => {
+                        eprintln!("Ignoring key state report: {e}");
+                        continue;
+                    }
+                };
+
+                key_events(pressed, report.pressed, now_millis()).for_each(&mut on_event);
+                pressed = report.pressed;
+            }
+            Ok(())
+        });
+
+        // Report a claim that fails here rather than from `stop`.
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(Self {
+                stop: Some(stop),
+                thread: Some(thread),
+            }),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(rusb::Error::Other.into()),
+        }
+    }
+
+    /// Whether reading has ended by itself, e.g. because the keypad was unplugged.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Stops reading, returning the error that ended it early, if any.
+    pub fn stop(mut self) -> Result<()> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<()> {
+        drop(self.stop.take());
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for KeyEventReader {
+    fn drop(&mut self) {
+        if let Err(e) = self.join() {
+            eprintln!("Reading key presses failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::transport::SimulatedFalcon8;
+
+    fn press(falcon: &Falcon8<SimulatedFalcon8>, pressed: u8) {
+        falcon
+            .transport
+            .push_interrupt(0x83, &KeyStateReport { pressed }.encode());
+    }
+
+    #[test]
+    fn test_key_events() {
+        let events: Vec<_> = key_events(0b0000_0101, 0b1000_0001, 7).collect();
+        assert_eq!(
+            events,
+            [
+                KeyEvent {
+                    key_index: 2,
+                    pressed: false,
+                    timestamp: 7,
+                },
+                KeyEvent {
+                    key_index: 7,
+                    pressed: true,
+                    timestamp: 7,
+                },
+            ]
+        );
+        assert_eq!(key_events(0b11, 0b11, 0).count(), 0);
+    }
+
+    #[test]
+    fn test_reader() {
+        let falcon = Arc::new(Falcon8::with_transport(SimulatedFalcon8::new()));
+        assert_eq!(falcon.key_event_endpoint(), Ok(0x83));
+
+        let (sender, events) = mpsc::channel();
+        let reader = KeyEventReader::start(falcon.clone(), move |event| {
+            sender.send(event).unwrap();
+        })
+        .unwrap();
+        let next = || {
+            let event = events.recv_timeout(Duration::from_secs(1)).unwrap();
+            (event.key_index, event.pressed)
+        };
+
+        press(&falcon, 0b0001);
+        assert_eq!(next(), (0, true));
+        falcon.transport.push_interrupt(0x83, &[0x04, 0xFF, 0x00]);
+        press(&falcon, 0b1001);
+        assert_eq!(next(), (3, true));
+        press(&falcon, 0b0000);
+        assert_eq!(next(), (0, false));
+        assert_eq!(next(), (3, false));
+
+        reader.stop().unwrap();
+        assert!(!falcon.transport.is_claimed(CONFIG_INTERFACE));
+    }
+    #[test]
+    fn test_start_fails_when_claimed() {
+        let sim = SimulatedFalcon8::new();
+        let other = Falcon8::with_transport(sim.reopen());
+        let _guard = other.claim().unwrap();
+
+        let falcon = Arc::new(Falcon8::with_transport(sim));
+        let result = KeyEventReader::start(falcon, |_| {});
+        assert_eq!(result.err(), Some(Falcon8Error::Busy));
+    }
+}
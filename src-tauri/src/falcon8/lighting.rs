@@ -0,0 +1,216 @@
+//! The keypad's LEDs: per-key colours plus a built-in effect drawn over them.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    protocol::{
+        Direction, LedColorsReport, LightingMode, LightingReport, Rgb, KEY_COUNT, PROFILE_COUNT,
+    },
+    transport::Transport,
+    Falcon8, Falcon8Error, Result,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LightingSettings {
+    pub mode: LightingMode,
+    pub speed: u8,
+    pub direction: Direction,
+    pub brightness: u8,
+}
+
+impl LightingSettings {
+    pub(super) fn from_report(report: &LightingReport) -> Self {
+        Self {
+            mode: report.mode,
+            speed: report.speed,
+            direction: report.direction,
+            brightness: report.brightness,
+        }
+    }
+
+    pub(super) fn to_report(self, slot: u8) -> LightingReport {
+        LightingReport {
+            slot,
+            mode: self.mode,
+            speed: self.speed,
+            direction: self.direction,
+            brightness: self.brightness,
+        }
+    }
+}
+
+/// What the LEDs of the active profile are showing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightingState {
+    pub slot: u8,
+    pub settings: LightingSettings,
+    pub colors: [Rgb; KEY_COUNT],
+}
+
+impl<T: Transport> Falcon8<T> {
+    /// Reads the active profile's effect and key colours back from the keypad.
+    pub fn lighting(&self) -> Result<LightingState> {
+        let _guard = self.transaction()?;
+        let slot = self.select_active_slot()?;
+
+        let settings: LightingReport = self.read_report()?;
+        let colors: LedColorsReport = self.read_report()?;
+
+        Ok(LightingState {
+            slot,
+            settings: LightingSettings::from_report(&settings),
+            colors: colors.colors,
+        })
+    }
+
+    pub fn set_lighting(&self, lighting: LightingSettings) -> Result<()> {
+        let _guard = self.transaction()?;
+        let slot = self.select_active_slot()?;
+
+        self.write_report(&lighting.to_report(slot))
+    }
+
+    /// Changes the built-in effect, keeping the brightness.
+    pub fn set_effect(&self, mode: LightingMode, speed: u8, direction: Direction) -> Result<()> {
+        self.update_lighting(|lighting| {
+            lighting.mode = mode;
+            lighting.speed = speed;
+            lighting.direction = direction;
+        })
+    }
+
+    pub fn set_brightness(&self, brightness: u8) -> Result<()> {
+        self.update_lighting(|lighting| lighting.brightness = brightness)
+    }
+
+    fn update_lighting(&self, update: impl FnOnce(&mut LightingSettings)) -> Result<()> {
+        let _guard = self.transaction()?;
+        let slot = self.select_active_slot()?;
+
+        let report: LightingReport = self.read_report()?;
+        let mut lighting = LightingSettings::from_report(&report);
+        update(&mut lighting);
+        self.write_report(&lighting.to_report(slot))
+    }
+
+    /// Sets the static colour of every key in the active profile.
+    pub fn set_key_colors(&self, colors: &[Rgb; KEY_COUNT]) -> Result<()> {
+        self.write_key_colors(0xFF, *colors)
+    }
+
+    /// Sets the static colour of one key, leaving the others alone.
+    pub fn set_key_color(&self, key: usize, color: Rgb) -> Result<()> {
+        if key >= KEY_COUNT {
+            return Err(Falcon8Error::InvalidKey(key));
+        }
+
+        let mut colors = [[0; 3]; KEY_COUNT];
+        colors[key] = color;
+        self.write_key_colors(1 << key, colors)
+    }
+
+    fn write_key_colors(&self, mask: u8, colors: [Rgb; KEY_COUNT]) -> Result<()> {
+        let _guard = self.transaction()?;
+        let slot = self.select_active_slot()?;
+        debug_assert!(usize::from(slot) < PROFILE_COUNT);
+
+        self.write_report(&LedColorsReport {
+            slot,
+            flags: 0,
+            mask,
+            colors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::transport::SimulatedFalcon8;
+
+    fn simulated() -> Falcon8<SimulatedFalcon8> {
+        Falcon8::with_transport(SimulatedFalcon8::new())
+    }
+
+    #[test]
+    fn test_read_back() {
+        let falcon = simulated();
+        let state = falcon.lighting().unwrap();
+        assert_eq!(state.slot, 0);
+        assert_eq!(
+            state.settings,
+            LightingSettings {
+                mode: LightingMode::Static,
+                speed: 0x80,
+                direction: Direction::LeftToRight,
+                brightness: 0xFF,
+            }
+        );
+        assert_eq!(state.colors, [[0xFF; 3]; KEY_COUNT]);
+    }
+
+    #[test]
+    fn test_set_lighting() {
+        let falcon = simulated();
+        let lighting = LightingSettings {
+            mode: LightingMode::Breathing,
+            speed: 10,
+            direction: Direction::RightToLeft,
+            brightness: 99,
+        };
+        falcon.set_lighting(lighting).unwrap();
+        assert_eq!(falcon.lighting().unwrap().settings, lighting);
+        assert_eq!(falcon.read_config().unwrap().lighting, lighting);
+    }
+
+    #[test]
+    fn test_effect_and_brightness() {
+        let falcon = simulated();
+        falcon.set_brightness(40).unwrap();
+        falcon
+            .set_effect(LightingMode::Wave, 200, Direction::RightToLeft)
+            .unwrap();
+
+        let settings = falcon.lighting().unwrap().settings;
+        assert_eq!(settings.mode, LightingMode::Wave);
+        assert_eq!(settings.speed, 200);
+        assert_eq!(settings.direction, Direction::RightToLeft);
+        assert_eq!(settings.brightness, 40);
+
+        falcon
+            .set_effect(LightingMode::Off, 0, Direction::LeftToRight)
+            .unwrap();
+        assert_eq!(falcon.lighting().unwrap().settings.brightness, 40);
+    }
+
+    #[test]
+    fn test_key_colors() {
+        let falcon = simulated();
+        falcon.transport.press_profile_button();
+
+        let colors = [
+            [1, 2, 3],
+            [4, 5, 6],
+            [7, 8, 9],
+            [0; 3],
+            [0; 3],
+            [0; 3],
+            [0; 3],
+            [0xFF, 0, 0],
+        ];
+        falcon.set_key_colors(&colors).unwrap();
+        falcon.set_key_color(3, [0, 0xFF, 0]).unwrap();
+
+        let mut expected = colors;
+        expected[3] = [0, 0xFF, 0];
+        assert_eq!(falcon.lighting().unwrap().colors, expected);
+        assert_eq!(falcon.transport.led_colors(1), expected);
+        assert_eq!(falcon.transport.led_colors(0), [[0xFF; 3]; KEY_COUNT]);
+
+        assert_eq!(
+            falcon.set_key_color(KEY_COUNT, [0; 3]),
+            Err(Falcon8Error::InvalidKey(KEY_COUNT))
+        );
+    }
+}
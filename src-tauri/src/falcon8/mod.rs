@@ -1,222 +1,808 @@
-use std::time::Duration;
+use std::sync::{Mutex, MutexGuard};
 
-use rusb::{Context, Device, DeviceHandle, Direction, Recipient, RequestType, Result, UsbContext};
+use rusb::{Context, Direction, Recipient, RequestType, UsbContext};
 
+mod config;
 mod consts;
+mod diff;
+mod dump;
+mod error;
+mod guard;
+mod info;
+mod lighting;
+mod profile;
+mod profile_file;
+mod selector;
 
+pub use config::Config;
 pub use consts::*;
+pub use diff::{Difference, ProfileDiff};
+pub use dump::Dump;
+pub use error::{Falcon8Error, Result};
+pub use guard::{InterfaceGuard, Transaction};
+pub use info::DeviceInfo;
+pub use lighting::{LightingSettings, LightingState};
+pub use profile::{Profile, ProfileSlot};
+pub use profile_file::{migrate_profile, upgrade_profile_file, MigrationReport, SCHEMA_VERSION};
+pub use selector::{DeviceIdentity, DeviceSelector, Nicknames};
+pub mod actions;
+pub mod animation;
+pub mod hotplug;
+pub mod key_events;
 pub mod protocol;
+pub mod transport;
+pub mod virtual_keyboard;
+
+use guard::{ClaimState, TransactionLock};
+use protocol::{
+    FeatureReport, FirmwareInfoReport, KeyMapReport, Macro, MemoryAccessReport, MACRO_CAPACITY,
+    MACRO_HEADER_SIZE, MACRO_SLOT_COUNT, MEMORY_SIZE, PAGE_SIZE,
+};
+use transport::{Transport, UsbTransport};
 
 #[derive(Debug)]
 pub struct Endpoint {
-    config: u8,
-    iface: u8,
-    setting: u8,
-    address: u8,
+    pub config: u8,
+    pub iface: u8,
+    pub setting: u8,
+    pub address: u8,
 }
 
+/// A single keypad.
+///
+/// Every method takes `&self` and the transports synchronise internally, so a `Falcon8` can be
+/// shared between threads behind an `Arc` (or kept in Tauri's managed state) as-is. Methods
+/// that take several transfers run them as one `Transaction`, so calls from different threads
+/// don't see each other's half-finished work.
 #[derive(Debug)]
-pub struct Falcon8<'a, T: UsbContext> {
-    pub context: &'a mut T,
-    pub device: Device<T>,
-    pub handle: DeviceHandle<T>,
+pub struct Falcon8<T: Transport> {
+    pub transport: T,
+    claim: Mutex<ClaimState>,
+    transaction: TransactionLock,
 }
 
-impl<'a> Falcon8<'a, Context> {
+impl Falcon8<UsbTransport<Context>> {
     pub fn new() -> Result<Vec<Self>> {
-        let mut context = Context::new()?;
-        let devices = Self::open_devices(&mut context, VID, PID)?;
+        Self::open_all(&Context::new()?)
+    }
+}
+
+impl<C: UsbContext> Falcon8<UsbTransport<C>> {
+    /// Opens every Falcon-8 visible through `context`. Each one keeps its own clone of the
+    /// context, so the caller's copy can be dropped.
+    pub fn open_all(context: &C) -> Result<Vec<Self>> {
+        let devices = UsbTransport::open_all(context, VID, PID)?;
 
         if devices.is_empty() {
-            return Err(rusb::Error::NotFound);
+            return Err(Falcon8Error::NotFound);
         }
 
-        Ok(devices)
+        Ok(devices.into_iter().map(Falcon8::with_transport).collect())
+    }
+
+    /// Opens the one Falcon-8 that `selector` picks out.
+    pub fn open_selected(
+        context: &C,
+        selector: &DeviceSelector,
+        nicknames: &Nicknames,
+    ) -> Result<Self> {
+        let mut devices = Self::open_all(context)?;
+        let identities = devices
+            .iter()
+            .map(|device| device.identity(nicknames))
+            .collect::<Result<Vec<_>>>()?;
+
+        let index = selector.find(&identities)?;
+        Ok(devices.swap_remove(index))
+    }
+
+    pub fn context(&self) -> &C {
+        self.transport.context()
     }
 }
 
-impl<'a, T: UsbContext> Falcon8<'a, T> {
-    fn open_devices(context: &mut T, vid: u16, pid: u16) -> Result<Vec<Falcon8<T>>> {
-        let devices = context.devices()?;
-        let mut result = Vec::new();
-
-        for device in devices.iter() {
-            let Ok(device_desc) = device.device_descriptor() else {
-                continue;
-            };
-
-            if device_desc.vendor_id() == vid && device_desc.product_id() == pid {
-                if let Ok(handle) = device.open() {
-                    result.push(Falcon8 {
-                        context,
-                        device,
-                        handle,
-                    });
-                }
-            }
+impl<T: Transport> Falcon8<T> {
+    pub fn with_transport(transport: T) -> Self {
+        Self {
+            transport,
+            claim: Mutex::new(ClaimState::default()),
+            transaction: TransactionLock::default(),
         }
+    }
+
+    /// The keypad's IN endpoints, across all interfaces.
+    pub fn find_readable_endpoints(&self) -> Result<Vec<Endpoint>> {
+        let mut endpoints = self
+            .transport
+            .endpoints()
+            .map_err(Falcon8Error::Descriptor)?;
 
-        Ok(result)
+        endpoints.retain(|endpoint| endpoint.address & rusb::constants::LIBUSB_ENDPOINT_IN != 0);
+        Ok(endpoints)
     }
 
-    pub fn print_device_info(&self) -> Result<()> {
-        let device_desc = self.handle.device().device_descriptor()?;
-        let timeout = std::time::Duration::from_secs(1);
-        let languages = self.handle.read_languages(timeout)?;
+    /// Claims the configuration interface until the returned guard is dropped.
+    ///
+    /// Report calls claim it on their own; holding a guard across several of them saves
+    /// detaching and reattaching the kernel driver for each one. Other threads' calls still get
+    /// in between them, unlike with a `transaction`.
+    pub fn claim(&self) -> Result<InterfaceGuard<'_, T>> {
+        InterfaceGuard::new(self)
+    }
 
-        println!(
-            "Active configuration: {}",
-            self.handle.active_configuration()?
-        );
+    /// Starts a transaction: other threads' calls wait until it's dropped.
+    pub fn transaction(&self) -> Result<Transaction<'_, T>> {
+        Transaction::new(self)
+    }
 
-        if !languages.is_empty() {
-            let language = languages[0];
-            println!("Language: {:?}", language);
+    fn claim_state(&self) -> MutexGuard<'_, ClaimState> {
+        self.claim.lock().unwrap_or_else(|e| e.into_inner())
+    }
 
-            println!(
-                "Manufacturer: {}",
-                self.handle
-                    .read_manufacturer_string(language, &device_desc, timeout)
-                    .unwrap_or_else(|_| "Not Found".to_string())
-            );
-            println!(
-                "Product: {}",
-                self.handle
-                    .read_product_string(language, &device_desc, timeout)
-                    .unwrap_or_else(|_| "Not Found".to_string())
-            );
-            println!(
-                "Serial Number: {}",
-                self.handle
-                    .read_serial_number_string(language, &device_desc, timeout)
-                    .unwrap_or_else(|_| "Not Found".to_string())
-            );
+    /// Checks `len` against the protocol's definition of `report_id`.
+    fn check_report_len(report_id: u8, len: usize) -> Result<()> {
+        match protocol::feature_report_len(report_id) {
+            Some(expected) if expected == len => Ok(()),
+            _ => Err(Falcon8Error::InvalidReport { report_id, len }),
         }
-        Ok(())
     }
 
-    pub fn find_readable_endpoints(&self) -> Result<Vec<Endpoint>> {
-        let config_desc = match self.device.config_descriptor(0) {
-            Ok(c) => c,
-            Err(_) => return Err(rusb::Error::NoDevice),
-        };
-        let mut endpoints = vec![];
-
-        for interface in config_desc.interfaces() {
-            for interface_desc in interface.descriptors() {
-                for endpoint_desc in interface_desc.endpoint_descriptors() {
-                    println!("{:#?}", endpoint_desc);
-                    endpoints.push(Endpoint {
-                        config: config_desc.number(),
-                        iface: interface_desc.interface_number(),
-                        setting: interface_desc.setting_number(),
-                        address: endpoint_desc.address(),
-                    });
-                }
-            }
-        }
+    /// Reads feature report `report_id`, returning only the bytes the device actually sent.
+    pub fn get_feature_report(&self, report_id: u8, len: usize) -> Result<Vec<u8>> {
+        Self::check_report_len(report_id, len)?;
+        let _guard = self.transaction()?;
 
-        println!("Endpoints: {:?}", endpoints);
-        Ok(endpoints)
+        let mut data = vec![0; len];
+        let size = self.transport.read_control(
+            rusb::request_type(Direction::In, RequestType::Class, Recipient::Interface),
+            protocol::HID_GET_REPORT,
+            protocol::feature_report_value(report_id),
+            u16::from(CONFIG_INTERFACE),
+            &mut data,
+            TIMEOUT,
+        )?;
+        data.truncate(size);
+
+        Ok(data)
     }
 
-    pub fn claim_interfaces(&self) -> Result<()> {
-        let config_desc = match self.device.config_descriptor(0) {
-            Ok(c) => c,
-            Err(_) => return Err(rusb::Error::NoDevice),
-        };
-        println!("got desc");
-        for iface in config_desc.interfaces() {
-            // claim
-            println!("claiming {}", iface.number());
-            self.handle.claim_interface(iface.number())?;
-            println!("claimed {}", iface.number());
-            break;
+    /// Writes feature report `report_id`. `data` is the full report, starting with its ID.
+    pub fn set_feature_report(&self, report_id: u8, data: &[u8]) -> Result<()> {
+        Self::check_report_len(report_id, data.len())?;
+        if data[0] != report_id {
+            return Err(Falcon8Error::InvalidReport {
+                report_id,
+                len: data.len(),
+            });
+        }
+        let _guard = self.transaction()?;
+
+        let size = self.transport.write_control(
+            rusb::request_type(Direction::Out, RequestType::Class, Recipient::Interface),
+            protocol::HID_SET_REPORT,
+            protocol::feature_report_value(report_id),
+            u16::from(CONFIG_INTERFACE),
+            data,
+            TIMEOUT,
+        )?;
+        if size != data.len() {
+            return Err(Falcon8Error::ShortTransfer {
+                expected: data.len(),
+                actual: size,
+            });
         }
+
         Ok(())
     }
 
-    pub fn release_interfaces(&self) -> Result<()> {
-        let config_desc = match self.device.config_descriptor(0) {
-            Ok(c) => c,
-            Err(_) => return Err(rusb::Error::NoDevice),
-        };
+    pub fn read_report<R: FeatureReport>(&self) -> Result<R> {
+        let data = self.get_feature_report(R::ID, R::LEN)?;
+        Ok(R::decode(&data)?)
+    }
 
-        for iface in config_desc.interfaces() {
-            // release
-            self.handle.release_interface(iface.number())?;
+    pub fn write_report<R: FeatureReport>(&self, report: &R) -> Result<()> {
+        self.set_feature_report(R::ID, &report.encode())
+    }
+
+    fn check_memory_range(address: usize, len: usize) -> Result<()> {
+        if address
+            .checked_add(len)
+            .is_some_and(|end| end <= MEMORY_SIZE)
+        {
+            Ok(())
+        } else {
+            Err(Falcon8Error::InvalidMemoryRange { address, len })
         }
-        Ok(())
     }
 
-    fn detach_kernel_driver(&self, endpoint: &Endpoint) -> Result<()> {
-        let has_kernel_driver = match self.handle.kernel_driver_active(endpoint.iface) {
-            Ok(true) => {
-                self.handle.detach_kernel_driver(endpoint.iface)?;
-                true
+    /// Reads `len` bytes of configuration memory, a page at a time.
+    pub fn read_memory(&self, address: usize, len: usize) -> Result<Vec<u8>> {
+        Self::check_memory_range(address, len)?;
+        let _guard = self.transaction()?;
+
+        let mut data = Vec::with_capacity(len);
+        while data.len() < len {
+            let page_address = (address + data.len()) as u16;
+            let page_len = (len - data.len()).min(PAGE_SIZE) as u8;
+            self.write_report(&MemoryAccessReport::read(page_address, page_len))?;
+
+            let page: MemoryAccessReport = self.read_report()?;
+            if page.address != page_address {
+                return Err(Falcon8Error::MemoryReadMismatch {
+                    expected: page_address,
+                    actual: page.address,
+                });
             }
-            _ => false,
-        };
-        if has_kernel_driver {
-            println!("Detached kernel driver");
+            if page.len != page_len {
+                return Err(Falcon8Error::MemoryReadLength {
+                    address: page_address,
+                    expected: page_len,
+                    actual: page.len,
+                });
+            }
+            data.extend_from_slice(page.data());
+        }
+
+        Ok(data)
+    }
+
+    /// Writes `data` to configuration memory, a page at a time.
+    pub fn write_memory(&self, address: usize, data: &[u8]) -> Result<()> {
+        Self::check_memory_range(address, data.len())?;
+        let _guard = self.transaction()?;
+
+        for (i, page) in data.chunks(PAGE_SIZE).enumerate() {
+            let page_address = (address + i * PAGE_SIZE) as u16;
+            self.write_report(&MemoryAccessReport::write(page_address, page))?;
         }
+
         Ok(())
     }
 
-    fn reattach_kernel_driver(&mut self, endpoint: &Endpoint) -> Result<()> {
-        let has_kernel_driver = match self.handle.kernel_driver_active(endpoint.iface) {
-            Ok(true) => {
-                self.handle.detach_kernel_driver(endpoint.iface)?;
-                true
-            }
-            _ => false,
-        };
-        if has_kernel_driver {
-            println!("Reattached kernel driver");
+    fn check_macro_slot(slot: usize) -> Result<()> {
+        if slot < MACRO_SLOT_COUNT {
+            Ok(())
+        } else {
+            Err(Falcon8Error::InvalidMacroSlot(slot))
+        }
+    }
+
+    /// Checks that `m` can be stored in macro slot `slot`.
+    fn check_macro(slot: usize, m: &Macro) -> Result<()> {
+        Self::check_macro_slot(slot)?;
+        if m.repeat == 0 {
+            return Err(Falcon8Error::InvalidMacro(
+                "the repeat count must be at least 1",
+            ));
+        }
+        if m.events.is_empty() {
+            return Err(Falcon8Error::InvalidMacro("it has no events"));
+        }
+        let size = m.encoded_len();
+        if size > MACRO_CAPACITY {
+            return Err(Falcon8Error::MacroTooLarge {
+                size,
+                capacity: MACRO_CAPACITY,
+            });
         }
         Ok(())
     }
 
-    pub fn get_report(&self) -> Result<Vec<u8>> {
-        let mut data = Vec::new();
+    /// Stores `m` in macro slot `slot`, for `KeyBinding::Macro` bindings to play.
+    pub fn upload_macro(&self, slot: usize, m: &Macro) -> Result<()> {
+        Self::check_macro(slot, m)?;
+        self.write_memory(protocol::macro_address(slot), &m.encode())
+    }
 
-        let endpoint = &self.find_readable_endpoints()?[0];
+    /// Reads back macro slot `slot`, or `None` if it's empty.
+    pub fn download_macro(&self, slot: usize) -> Result<Option<Macro>> {
+        Self::check_macro_slot(slot)?;
+        let _guard = self.transaction()?;
 
-        println!("endpoint!: {:?}", endpoint);
-        self.detach_kernel_driver(&endpoint)?;
-        println!("detached kernel driver");
-        self.claim_interfaces()?;
-        println!("claimed ifaces");
+        let address = protocol::macro_address(slot);
+        let header = self.read_memory(address, MACRO_HEADER_SIZE)?;
+        let len = usize::from(header[1]).min(MACRO_CAPACITY);
+        if len == 0 {
+            return Ok(None);
+        }
 
-        println!("Reading!");
-        let size = self.handle.read_control(
-            rusb::request_type(Direction::In, RequestType::Class, Recipient::Interface),
-            0x01,
-            0x0307,
-            0x0002,
-            data.as_mut_slice(),
-            Duration::from_secs(1),
-        )?;
-        println!("size: {:?}", size);
+        let stream = self.read_memory(address + MACRO_HEADER_SIZE, len)?;
+        Ok(Some(Macro::decode(header[0], &stream)?))
+    }
 
-        self.release_interfaces()?;
-        println!("released ifaces");
-        self.reattach_kernel_driver(endpoint)?;
-        println!("reattached kernel driver");
-        Ok(data)
+    /// Empties macro slot `slot`.
+    pub fn clear_macro(&self, slot: usize) -> Result<()> {
+        Self::check_macro_slot(slot)?;
+        self.write_memory(protocol::macro_address(slot), &[0; MACRO_HEADER_SIZE])
+    }
+
+    /// Reads the firmware version, failing if it speaks a protocol this driver doesn't.
+    pub fn firmware_info(&self) -> Result<FirmwareInfoReport> {
+        let info: FirmwareInfoReport = self.read_report()?;
+        if info.protocol_version != protocol::PROTOCOL_VERSION {
+            return Err(Falcon8Error::UnsupportedFirmware {
+                version: format!("{}.{}.{}", info.major, info.minor, info.patch),
+                protocol_version: info.protocol_version,
+            });
+        }
+        Ok(info)
+    }
+
+    pub fn get_report(&self) -> Result<Vec<u8>> {
+        self.get_feature_report(KeyMapReport::ID, KeyMapReport::LEN)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use super::transport::SimulatedFalcon8;
     use super::*;
 
+    fn simulated() -> Falcon8<SimulatedFalcon8> {
+        Falcon8::with_transport(SimulatedFalcon8::new())
+    }
+
     #[test]
+    #[ignore = "requires a Falcon-8 plugged in"]
     fn test_get_report() {
-        let mut falcons = Falcon8::new().unwrap();
+        let falcons = Falcon8::new().unwrap();
         for falcon in falcons {
-            falcon.print_device_info().unwrap();
+            println!("{}", falcon.device_info().unwrap());
             falcon.get_report().unwrap();
         }
     }
+
+    #[test]
+    fn test_falcon8_is_send_sync() {
+        fn assert_send_sync<T: Send + Sync + 'static>() {}
+        assert_send_sync::<Falcon8<UsbTransport<Context>>>();
+        assert_send_sync::<Falcon8<SimulatedFalcon8>>();
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let falcon = std::sync::Arc::new(simulated());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let falcon = falcon.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..16 {
+                        falcon.read_report::<KeyMapReport>().unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        assert!(!falcon.transport.is_claimed(CONFIG_INTERFACE));
+        assert!(falcon.transport.kernel_driver_attached(CONFIG_INTERFACE));
+    }
+
+    #[test]
+    fn test_simulated_device_info() {
+        let falcon = simulated();
+        let strings = falcon.transport.read_strings(Duration::ZERO).unwrap();
+        assert_eq!(strings.product.as_deref(), Some("Falcon-8"));
+    }
+
+    #[test]
+    fn test_simulated_endpoints() {
+        let falcon = simulated();
+        let endpoints = falcon.find_readable_endpoints().unwrap();
+        let ifaces: Vec<u8> = endpoints.iter().map(|e| e.iface).collect();
+        assert_eq!(ifaces, [0, 1, 2]);
+    }
+
+    #[test]
+    fn test_simulated_claim_requires_detach() {
+        let falcon = simulated();
+        let sim = &falcon.transport;
+
+        assert_eq!(sim.claim_interface(2), Err(rusb::Error::Busy));
+        sim.detach_kernel_driver(2).unwrap();
+        sim.claim_interface(2).unwrap();
+        assert!(sim.is_claimed(2));
+        assert_eq!(sim.attach_kernel_driver(2), Err(rusb::Error::Busy));
+
+        sim.release_interface(2).unwrap();
+        sim.attach_kernel_driver(2).unwrap();
+        assert!(sim.kernel_driver_attached(2));
+    }
+
+    #[test]
+    fn test_simulated_feature_report_round_trip() {
+        let sim = SimulatedFalcon8::new();
+        sim.detach_kernel_driver(CONFIG_INTERFACE).unwrap();
+
+        let report = KeyMapReport {
+            slot: 1,
+            bindings: [[0x01, 0x02, 0x04, 0x00]; protocol::KEY_COUNT],
+        };
+        let out = rusb::request_type(Direction::Out, RequestType::Class, Recipient::Interface);
+        let written = sim
+            .write_control(
+                out,
+                protocol::HID_SET_REPORT,
+                protocol::feature_report_value(KeyMapReport::ID),
+                u16::from(CONFIG_INTERFACE),
+                &report.encode(),
+                Duration::ZERO,
+            )
+            .unwrap();
+        assert_eq!(written, KeyMapReport::LEN);
+        assert_eq!(sim.key_map(1), report.bindings);
+
+        let state = protocol::ProfileStateReport {
+            active_slot: 0,
+            edit_slot: 1,
+            slot_count: protocol::PROFILE_COUNT as u8,
+        };
+        sim.write_control(
+            out,
+            protocol::HID_SET_REPORT,
+            protocol::feature_report_value(protocol::ProfileStateReport::ID),
+            u16::from(CONFIG_INTERFACE),
+            &state.encode(),
+            Duration::ZERO,
+        )
+        .unwrap();
+
+        let mut buf = [0; KeyMapReport::LEN];
+        let read = sim
+            .read_control(
+                rusb::request_type(Direction::In, RequestType::Class, Recipient::Interface),
+                protocol::HID_GET_REPORT,
+                protocol::feature_report_value(KeyMapReport::ID),
+                u16::from(CONFIG_INTERFACE),
+                &mut buf,
+                Duration::ZERO,
+            )
+            .unwrap();
+        assert_eq!(KeyMapReport::decode(&buf[..read]), Ok(report));
+    }
+
+    #[test]
+    fn test_simulated_rejects_corrupt_report() {
+        let sim = SimulatedFalcon8::new();
+        sim.detach_kernel_driver(CONFIG_INTERFACE).unwrap();
+
+        let mut data = protocol::MemoryAccessReport::write(0x0100, &[1, 2, 3]).encode();
+        data[4] ^= 0xFF;
+        let out = rusb::request_type(Direction::Out, RequestType::Class, Recipient::Interface);
+        assert_eq!(
+            sim.write_control(
+                out,
+                protocol::HID_SET_REPORT,
+                protocol::feature_report_value(protocol::MemoryAccessReport::ID),
+                u16::from(CONFIG_INTERFACE),
+                &data,
+                Duration::ZERO,
+            ),
+            Err(rusb::Error::Pipe)
+        );
+        assert_eq!(&sim.memory()[0x0100..0x0103], [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_get_feature_report() {
+        let falcon = simulated();
+
+        let data = falcon
+            .get_feature_report(KeyMapReport::ID, KeyMapReport::LEN)
+            .unwrap();
+        assert_eq!(data.len(), KeyMapReport::LEN);
+        let report = KeyMapReport::decode(&data).unwrap();
+        assert_eq!(report.bindings, falcon.transport.key_map(0));
+    }
+
+    #[test]
+    fn test_set_feature_report() {
+        let falcon = simulated();
+
+        let report = KeyMapReport {
+            slot: 2,
+            bindings: [[0x01, 0x00, 0x04, 0x00]; protocol::KEY_COUNT],
+        };
+        falcon
+            .set_feature_report(KeyMapReport::ID, &report.encode())
+            .unwrap();
+        assert_eq!(falcon.transport.key_map(2), report.bindings);
+    }
+
+    #[test]
+    fn test_feature_report_length_validation() {
+        let falcon = simulated();
+
+        assert_eq!(
+            falcon.get_feature_report(KeyMapReport::ID, 64),
+            Err(Falcon8Error::InvalidReport {
+                report_id: KeyMapReport::ID,
+                len: 64
+            })
+        );
+        assert_eq!(
+            falcon.get_feature_report(0x7F, 8),
+            Err(Falcon8Error::InvalidReport {
+                report_id: 0x7F,
+                len: 8
+            })
+        );
+        assert_eq!(
+            falcon.set_feature_report(KeyMapReport::ID, &[KeyMapReport::ID; 8]),
+            Err(Falcon8Error::InvalidReport {
+                report_id: KeyMapReport::ID,
+                len: 8
+            })
+        );
+
+        let mut data = KeyMapReport {
+            slot: 0,
+            bindings: [[0; 4]; protocol::KEY_COUNT],
+        }
+        .encode();
+        data[0] = protocol::LightingReport::ID;
+        assert_eq!(
+            falcon.set_feature_report(KeyMapReport::ID, &data),
+            Err(Falcon8Error::InvalidReport {
+                report_id: KeyMapReport::ID,
+                len: KeyMapReport::LEN
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_write_report() {
+        let falcon = simulated();
+        let mut report: KeyMapReport = falcon.read_report().unwrap();
+        report.bindings[3] = [0x01, 0x01, 0x06, 0x00];
+        falcon.write_report(&report).unwrap();
+        assert_eq!(falcon.read_report::<KeyMapReport>().unwrap(), report);
+    }
+
+    #[test]
+    fn test_unsupported_firmware() {
+        let falcon = simulated();
+        assert_eq!(falcon.firmware_info().unwrap().major, 1);
+
+        falcon.transport.set_firmware(FirmwareInfoReport {
+            major: 2,
+            minor: 0,
+            patch: 0,
+            build: 0,
+            protocol_version: 9,
+        });
+        let err = falcon.firmware_info().unwrap_err();
+        assert_eq!(
+            err,
+            Falcon8Error::UnsupportedFirmware {
+                version: "2.0.0".to_string(),
+                protocol_version: 9
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_messages() {
+        assert!(Falcon8Error::from(rusb::Error::Access)
+            .to_string()
+            .contains("udev rule"));
+        assert_eq!(
+            Falcon8Error::from(rusb::Error::NoDevice),
+            Falcon8Error::Disconnected
+        );
+        assert_eq!(
+            serde_json::to_string(&Falcon8Error::Timeout).unwrap(),
+            "\"timed out waiting for the Falcon-8\""
+        );
+        assert_eq!(
+            Falcon8Error::from(protocol::DecodeError::Checksum {
+                report_id: 1,
+                expected: 2,
+                actual: 3
+            }),
+            Falcon8Error::ChecksumMismatch {
+                report_id: 1,
+                expected: 2,
+                actual: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_feature_reports_leave_kernel_driver_attached() {
+        let falcon = simulated();
+        falcon.get_report().unwrap();
+
+        let sim = &falcon.transport;
+        assert!(!sim.is_claimed(CONFIG_INTERFACE));
+        for iface in 0..=CONFIG_INTERFACE {
+            assert!(sim.kernel_driver_attached(iface));
+        }
+    }
+
+    #[test]
+    fn test_guard_claims_only_config_interface() {
+        let falcon = simulated();
+        let guard = falcon.claim().unwrap();
+
+        let sim = &guard.transport;
+        assert!(sim.is_claimed(CONFIG_INTERFACE));
+        assert!(!sim.kernel_driver_attached(CONFIG_INTERFACE));
+        assert!(sim.kernel_driver_attached(0));
+        assert!(sim.kernel_driver_attached(1));
+
+        drop(guard);
+        assert!(!falcon.transport.is_claimed(CONFIG_INTERFACE));
+        assert!(falcon.transport.kernel_driver_attached(CONFIG_INTERFACE));
+    }
+
+    #[test]
+    fn test_nested_guards_release_once() {
+        let falcon = simulated();
+        let outer = falcon.claim().unwrap();
+        let data = outer
+            .get_feature_report(KeyMapReport::ID, KeyMapReport::LEN)
+            .unwrap();
+        assert_eq!(data.len(), KeyMapReport::LEN);
+        assert!(outer.transport.is_claimed(CONFIG_INTERFACE));
+
+        drop(outer);
+        assert!(falcon.transport.kernel_driver_attached(CONFIG_INTERFACE));
+    }
+
+    #[test]
+    fn test_guard_restores_on_early_return() {
+        fn fails<T: Transport>(falcon: &Falcon8<T>) -> Result<()> {
+            let guard = falcon.claim()?;
+            guard.get_feature_report(0x7F, 8)?;
+            unreachable!();
+        }
+
+        let falcon = simulated();
+        assert!(matches!(
+            fails(&falcon),
+            Err(Falcon8Error::InvalidReport { .. })
+        ));
+        assert!(!falcon.transport.is_claimed(CONFIG_INTERFACE));
+        assert!(falcon.transport.kernel_driver_attached(CONFIG_INTERFACE));
+    }
+
+    #[test]
+    fn test_guard_restores_on_panic() {
+        let falcon = simulated();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = falcon.claim().unwrap();
+            panic!("boom");
+        }));
+
+        assert!(result.is_err());
+        assert!(!falcon.transport.is_claimed(CONFIG_INTERFACE));
+        assert!(falcon.transport.kernel_driver_attached(CONFIG_INTERFACE));
+    }
+
+    #[test]
+    fn test_simulated_interrupt_read() {
+        let sim = SimulatedFalcon8::new();
+        let mut buf = [0; 8];
+
+        assert_eq!(
+            sim.read_interrupt(0x83, &mut buf, Duration::ZERO),
+            Err(rusb::Error::Busy)
+        );
+
+        sim.detach_kernel_driver(2).unwrap();
+        sim.claim_interface(2).unwrap();
+        assert_eq!(
+            sim.read_interrupt(0x83, &mut buf, Duration::ZERO),
+            Err(rusb::Error::Timeout)
+        );
+
+        sim.push_interrupt(0x83, &[4, 0b1]);
+        let read = sim
+            .read_interrupt(0x83, &mut buf, Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(&buf[..read], [4, 0b1]);
+    }
+
+    #[test]
+    fn test_memory_round_trip() {
+        let falcon = simulated();
+        let data: Vec<u8> = (0..100).collect();
+        falcon.write_memory(0x1000, &data).unwrap();
+
+        assert_eq!(falcon.read_memory(0x1000, 100).unwrap(), data);
+        assert_eq!(&falcon.transport.memory()[0x1000..0x1064], data);
+        assert_eq!(
+            falcon.read_memory(MEMORY_SIZE - 4, 8),
+            Err(Falcon8Error::InvalidMemoryRange {
+                address: MEMORY_SIZE - 4,
+                len: 8,
+            })
+        );
+    }
+
+    #[test]
+    fn test_short_memory_read() {
+        let falcon = simulated();
+        falcon.transport.limit_memory_reads(16);
+        assert_eq!(falcon.read_memory(0x1000, 16).unwrap().len(), 16);
+
+        let error = falcon.read_memory(0x1000, 100).unwrap_err();
+        assert_eq!(
+            error,
+            Falcon8Error::MemoryReadLength {
+                address: 0x1000,
+                expected: PAGE_SIZE as u8,
+                actual: 16,
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            format!("asked for {PAGE_SIZE} bytes of memory at 0x1000, the keypad returned 16")
+        );
+    }
+
+    #[test]
+    fn test_macro_round_trip() {
+        let falcon = simulated();
+        assert_eq!(falcon.download_macro(3).unwrap(), None);
+
+        let m = Macro::new()
+            .repeat(3)
+            .key_down(protocol::usage::keyboard::LEFT_SHIFT)
+            .tap(protocol::usage::keyboard::A)
+            .key_up(protocol::usage::keyboard::LEFT_SHIFT)
+            .delay(1000);
+        falcon.upload_macro(3, &m).unwrap();
+        assert_eq!(falcon.download_macro(3).unwrap(), Some(m));
+        assert_eq!(falcon.download_macro(2).unwrap(), None);
+
+        falcon.clear_macro(3).unwrap();
+        assert_eq!(falcon.download_macro(3).unwrap(), None);
+    }
+
+    #[test]
+    fn test_macro_budget() {
+        let falcon = simulated();
+
+        // Delays of 3 bytes, topped up with a 2-byte key down, fill the slot exactly.
+        let delays = (0..MACRO_CAPACITY / 3).fold(Macro::new(), |m, i| m.delay(i as u16));
+        let full = delays.clone().key_down(0x04);
+        assert_eq!(full.encoded_len(), MACRO_CAPACITY);
+        falcon.upload_macro(15, &full).unwrap();
+        assert_eq!(falcon.download_macro(15).unwrap(), Some(full));
+
+        // One more delay instead is a byte too many.
+        let over = delays.delay(0);
+        let err = falcon.upload_macro(0, &over).unwrap_err();
+        assert_eq!(
+            err,
+            Falcon8Error::MacroTooLarge {
+                size: MACRO_CAPACITY + 1,
+                capacity: MACRO_CAPACITY,
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "macro needs 255 bytes but a macro slot only holds 254, remove some events"
+        );
+        assert_eq!(falcon.download_macro(0).unwrap(), None);
+
+        assert_eq!(
+            falcon.upload_macro(MACRO_SLOT_COUNT, &Macro::new().tap(0x04)),
+            Err(Falcon8Error::InvalidMacroSlot(MACRO_SLOT_COUNT))
+        );
+        assert!(matches!(
+            falcon.upload_macro(0, &Macro::new()),
+            Err(Falcon8Error::InvalidMacro(_))
+        ));
+        assert!(matches!(
+            falcon.upload_macro(0, &Macro::new().repeat(0).tap(0x04)),
+            Err(Falcon8Error::InvalidMacro(_))
+        ));
+    }
 }
@@ -0,0 +1,219 @@
+//! The keypad's onboard profile slots, which the hardware button cycles through.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    actions::KeyActions,
+    protocol::{
+        KeyBinding, KeyMapReport, LedColorsReport, Macro, ProfileStateReport, Rgb, KEY_COUNT,
+    },
+    transport::Transport,
+    Falcon8, LightingSettings, Result,
+};
+
+/// Everything stored for one profile slot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    pub keys: [KeyBinding; KEY_COUNT],
+    pub lighting: LightingSettings,
+    pub colors: [Rgb; KEY_COUNT],
+    /// The macros this profile's keys play, by macro slot. Macro slots are shared between
+    /// profiles, so writing a profile overwrites these slots for every profile using them.
+    #[serde(default)]
+    pub macros: BTreeMap<u8, Macro>,
+    /// What each key does on the computer, see `actions`. The keypad can't store these, so a
+    /// profile read from it has none.
+    #[serde(default)]
+    pub actions: [KeyActions; KEY_COUNT],
+}
+
+impl Profile {
+    /// Macro slots that the keys play.
+    pub fn macro_slots(&self) -> impl Iterator<Item = u8> + '_ {
+        self.keys.iter().filter_map(|key| match key {
+            KeyBinding::Macro { slot } => Some(*slot),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileSlot {
+    pub slot: u8,
+    pub active: bool,
+}
+
+impl<T: Transport> Falcon8<T> {
+    /// The profile the keypad is using, which the hardware button changes.
+    pub fn active_profile(&self) -> Result<u8> {
+        let state: ProfileStateReport = self.read_report()?;
+        Ok(state.active_slot)
+    }
+
+    /// Switches the keypad to profile `slot`, as if the hardware button had been pressed.
+    pub fn set_active_profile(&self, slot: u8) -> Result<()> {
+        let _guard = self.transaction()?;
+        self.select_slot(slot)?;
+
+        let state: ProfileStateReport = self.read_report()?;
+        self.write_report(&ProfileStateReport {
+            active_slot: slot,
+            ..state
+        })
+    }
+
+    pub fn profiles(&self) -> Result<Vec<ProfileSlot>> {
+        let state: ProfileStateReport = self.read_report()?;
+        Ok((0..state.slot_count)
+            .map(|slot| ProfileSlot {
+                slot,
+                active: slot == state.active_slot,
+            })
+            .collect())
+    }
+
+    /// Reads profile `slot`, including the macros its keys play.
+    pub fn read_profile(&self, slot: u8) -> Result<Profile> {
+        let _guard = self.transaction()?;
+        self.select_slot(slot)?;
+        let config = self.read_selected_config(slot)?;
+
+        let mut profile = Profile {
+            keys: config.keys,
+            lighting: config.lighting,
+            colors: config.colors,
+            macros: BTreeMap::new(),
+            actions: Default::default(),
+        };
+        for macro_slot in profile.macro_slots().collect::<Vec<_>>() {
+            if let Some(m) = self.download_macro(usize::from(macro_slot))? {
+                profile.macros.insert(macro_slot, m);
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Writes `profile` to slot `slot`. Every macro is checked before anything is written, so a
+    /// macro that doesn't fit leaves the keypad untouched.
+    pub fn write_profile(&self, slot: u8, profile: &Profile) -> Result<()> {
+        for (&macro_slot, m) in &profile.macros {
+            Self::check_macro(usize::from(macro_slot), m)?;
+        }
+
+        let _guard = self.transaction()?;
+        self.select_slot(slot)?;
+
+        for (&macro_slot, m) in &profile.macros {
+            self.upload_macro(usize::from(macro_slot), m)?;
+        }
+        self.write_report(&KeyMapReport::from_keys(slot, &profile.keys))?;
+        self.write_report(&profile.lighting.to_report(slot))?;
+        self.write_report(&LedColorsReport {
+            slot,
+            flags: 0,
+            mask: 0xFF,
+            colors: profile.colors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::{
+        protocol::{usage, LightingMode, MACRO_CAPACITY},
+        transport::SimulatedFalcon8,
+        Falcon8Error,
+    };
+
+    fn simulated() -> Falcon8<SimulatedFalcon8> {
+        Falcon8::with_transport(SimulatedFalcon8::new())
+    }
+
+    #[test]
+    fn test_list_and_switch() {
+        let falcon = simulated();
+        let profiles = falcon.profiles().unwrap();
+        assert_eq!(profiles.len(), 4);
+        assert!(profiles[0].active);
+
+        falcon.set_active_profile(2).unwrap();
+        assert_eq!(falcon.active_profile().unwrap(), 2);
+        assert_eq!(falcon.transport.active_slot(), 2);
+        assert!(falcon.profiles().unwrap()[2].active);
+
+        falcon.transport.press_profile_button();
+        assert_eq!(falcon.active_profile().unwrap(), 3);
+
+        assert_eq!(
+            falcon.set_active_profile(4),
+            Err(Falcon8Error::InvalidProfileSlot(4))
+        );
+    }
+
+    #[test]
+    fn test_profile_round_trip() {
+        let falcon = simulated();
+        let mut profile = falcon.read_profile(2).unwrap();
+        assert!(profile.macros.is_empty());
+
+        let m = Macro::new().tap(usage::keyboard::A).delay(50);
+        profile.keys[4] = KeyBinding::Macro { slot: 9 };
+        profile.macros.insert(9, m.clone());
+        profile.lighting.mode = LightingMode::Reactive;
+        profile.colors[1] = [0x12, 0x34, 0x56];
+        falcon.write_profile(2, &profile).unwrap();
+
+        assert_eq!(falcon.read_profile(2).unwrap(), profile);
+        assert_eq!(falcon.download_macro(9).unwrap(), Some(m));
+        // The active profile is untouched.
+        assert_eq!(falcon.active_profile().unwrap(), 0);
+        assert_ne!(falcon.read_config().unwrap().keys, profile.keys);
+        assert_eq!(
+            falcon.read_profile(0).unwrap().keys,
+            falcon.key_map().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_concurrent_reads_keep_their_slots() {
+        let falcon = simulated();
+        let mut profile = falcon.read_profile(1).unwrap();
+        profile.lighting.mode = LightingMode::Reactive;
+        profile.colors = [[0x12, 0x34, 0x56]; KEY_COUNT];
+        falcon.write_profile(1, &profile).unwrap();
+        let lighting = falcon.lighting().unwrap();
+        assert_ne!(lighting.colors, profile.colors);
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for _ in 0..2000 {
+                    assert_eq!(falcon.read_profile(1).unwrap(), profile);
+                }
+            });
+            for _ in 0..2000 {
+                assert_eq!(falcon.lighting().unwrap(), lighting);
+            }
+        });
+    }
+
+    #[test]
+    fn test_oversized_macro_writes_nothing() {
+        let falcon = simulated();
+        let before = falcon.transport.memory();
+
+        let mut profile = falcon.read_profile(1).unwrap();
+        profile.keys[0] = KeyBinding::Disabled;
+        let huge = (0..MACRO_CAPACITY).fold(Macro::new(), |m, _| m.tap(usage::keyboard::A));
+        profile.macros.insert(0, huge);
+
+        assert!(matches!(
+            falcon.write_profile(1, &profile),
+            Err(Falcon8Error::MacroTooLarge { .. })
+        ));
+        assert_eq!(falcon.transport.memory(), before);
+    }
+}
@@ -0,0 +1,253 @@
+//! Upgrades profile files written for an older `schema_version`.
+//!
+//! `MIGRATIONS` holds one step per schema change, each taking a document from one version to
+//! the next. Steps edit the TOML document in place, so the user's comments and formatting survive
+//! the upgrade. Changing the file layout means bumping `SCHEMA_VERSION`, appending a step here
+//! and adding a golden file for the version being left behind to `testdata`. The layout hasn't
+//! changed since version 1, so there are no steps yet.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+use toml_edit::{Document, Value};
+
+use super::{position, schema_version, SCHEMA_VERSION};
+use crate::falcon8::{Falcon8Error, Result};
+
+/// What upgrading a profile file did.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    pub from_version: u32,
+    pub to_version: u32,
+    /// A description of each change, oldest first.
+    pub changes: Vec<String>,
+    /// Where the original file was copied to, when upgrading a file on disk.
+    pub backup: Option<PathBuf>,
+}
+
+type Migration = fn(&mut Document, &mut Vec<String>);
+
+/// `MIGRATIONS[n]` upgrades a version `n + 1` document to version `n + 2`.
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize - 1] = [];
+
+/// Steps that upgrade documents to schema version `latest`, one per version before it.
+struct Migrations<'a> {
+    latest: u32,
+    steps: &'a [Migration],
+}
+
+const CURRENT: Migrations<'static> = Migrations {
+    latest: SCHEMA_VERSION,
+    steps: &MIGRATIONS,
+};
+
+/// Rewrites `schema_version`, keeping any comment after it.
+fn set_schema_version(document: &mut Document, version: u32) {
+    if let Some(value) = document["schema_version"].as_value_mut() {
+        let decor = value.decor().clone();
+        *value = Value::from(i64::from(version));
+        *value.decor_mut() = decor;
+    }
+}
+
+impl Migrations<'_> {
+    fn migrate(&self, text: &str) -> Result<(String, MigrationReport)> {
+        let from_version = schema_version(text, self.latest)?;
+        let mut report = MigrationReport {
+            from_version,
+            to_version: self.latest,
+            changes: Vec::new(),
+            backup: None,
+        };
+        if from_version == self.latest {
+            return Ok((text.to_string(), report));
+        }
+
+        let mut document: Document = text.parse().map_err(|e: toml_edit::TomlError| {
+            let (line, column) = position(text, e.span().map_or(0, |span| span.start));
+            Falcon8Error::ProfileSyntax {
+                line,
+                column,
+                message: e.message().to_string(),
+            }
+        })?;
+        for (version, migrate) in (from_version..).zip(&self.steps[from_version as usize - 1..]) {
+            let mut changes = Vec::new();
+            migrate(&mut document, &mut changes);
+            set_schema_version(&mut document, version + 1);
+            report.changes.extend(
+                changes
+                    .into_iter()
+                    .map(|change| format!("version {}: {change}", version + 1)),
+            );
+        }
+
+        Ok((document.to_string(), report))
+    }
+
+    fn upgrade_file(&self, path: &Path) -> Result<Option<MigrationReport>> {
+        let text = fs::read_to_string(path).map_err(|e| Falcon8Error::file(path, e))?;
+        let (migrated, mut report) = self.migrate(&text)?;
+        if report.from_version == report.to_version {
+            return Ok(None);
+        }
+
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".v{}.bak", report.from_version));
+        let backup = PathBuf::from(backup);
+        fs::write(&backup, &text).map_err(|e| Falcon8Error::file(&backup, e))?;
+        fs::write(path, migrated).map_err(|e| Falcon8Error::file(path, e))?;
+
+        report.backup = Some(backup);
+        Ok(Some(report))
+    }
+}
+
+/// Upgrades a profile file to `SCHEMA_VERSION`, returning the new text and what changed. Files
+/// that are already current come back unchanged.
+pub fn migrate_profile(text: &str) -> Result<(String, MigrationReport)> {
+    CURRENT.migrate(text)
+}
+
+/// Upgrades the profile file at `path` in place, first copying the original next to it as
+/// `<name>.v<version>.bak`. Returns `None` if the file was already current.
+pub fn upgrade_profile_file(path: impl AsRef<Path>) -> Result<Option<MigrationReport>> {
+    CURRENT.upgrade_file(path.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::{
+        protocol::{usage, Direction, KeyBinding, LightingMode, Macro, Modifiers},
+        LightingSettings, Profile,
+    };
+    use toml_edit::Item;
+
+    /// The current version's golden file, which future migrations will start from.
+    const V1: &str = include_str!("testdata/v1.toml");
+
+    /// A made-up version 2 that moves `[colors]` into `[lighting.colors]`, to exercise the
+    /// pipeline until there's a real schema change.
+    const MADE_UP: Migrations<'static> = Migrations {
+        latest: 2,
+        steps: &[colors_under_lighting],
+    };
+
+    fn colors_under_lighting(document: &mut Document, changes: &mut Vec<String>) {
+        if !document.get("lighting").is_none_or(Item::is_table_like) {
+            return;
+        }
+        let Some(colors) = document.remove("colors") else {
+            return;
+        };
+
+        let lighting = document.entry("lighting").or_insert_with(|| {
+            let mut table = toml_edit::Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+        });
+        lighting
+            .as_table_like_mut()
+            .expect("checked above")
+            .insert("colors", colors);
+        changes.push("moved [colors] to [lighting.colors]".to_string());
+    }
+
+    /// What the golden file describes.
+    fn golden_profile() -> Profile {
+        let mut keys = [KeyBinding::Disabled; 8];
+        keys[0] = KeyBinding::combo(Modifiers::LEFT_CTRL, 0x06);
+        keys[1] = KeyBinding::combo(Modifiers::LEFT_CTRL, 0x19);
+        keys[2] = KeyBinding::Consumer {
+            usage: usage::consumer::PLAY_PAUSE,
+        };
+        keys[7] = KeyBinding::Macro { slot: 2 };
+
+        let mut colors = [[0; 3]; 8];
+        colors[0] = [0xFF, 0x80, 0x00];
+        colors[7] = [0x00, 0x40, 0xFF];
+
+        Profile {
+            keys,
+            lighting: LightingSettings {
+                mode: LightingMode::Breathing,
+                speed: 64,
+                direction: Direction::LeftToRight,
+                brightness: 180,
+            },
+            colors,
+            macros: [(2, Macro::new().repeat(2).tap(usage::keyboard::A).delay(30))].into(),
+            actions: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_golden_file() {
+        assert_eq!(Profile::from_toml(V1), Ok(golden_profile()));
+    }
+
+    #[test]
+    fn test_current_files_are_unchanged() {
+        for text in [V1.to_string(), golden_profile().to_toml()] {
+            let (migrated, report) = migrate_profile(&text).unwrap();
+            assert_eq!(migrated, text);
+            assert_eq!(report.from_version, SCHEMA_VERSION);
+            assert!(report.changes.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_migration_steps() {
+        let upgrade = |text: &str| MADE_UP.migrate(text).unwrap();
+
+        let (migrated, report) = upgrade(V1);
+        assert_eq!(
+            migrated,
+            V1.replace("schema_version = 1", "schema_version = 2")
+                .replace("[colors]", "[lighting.colors]")
+        );
+        assert_eq!((report.from_version, report.to_version), (1, 2));
+        assert_eq!(
+            report.changes,
+            ["version 2: moved [colors] to [lighting.colors]"]
+        );
+
+        assert_eq!(
+            upgrade("schema_version = 1 # old\ncolors = { key2 = \"#010203\" }\n").0,
+            "schema_version = 2 # old\n\n[lighting]\ncolors = { key2 = \"#010203\" }\n"
+        );
+        assert_eq!(
+            upgrade("schema_version = 1\n[keys]\nkey1 = \"A\"\n").0,
+            "schema_version = 2\n[keys]\nkey1 = \"A\"\n"
+        );
+        assert!(MADE_UP.migrate("schema_version = 3\n").is_err());
+    }
+
+    #[test]
+    fn test_upgrade_file() {
+        let dir = std::env::temp_dir().join(format!("falcon8-migrate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("old.toml");
+        fs::write(&path, V1).unwrap();
+
+        assert_eq!(upgrade_profile_file(&path), Ok(None));
+        assert_eq!(fs::read_to_string(&path).unwrap(), V1);
+
+        let report = MADE_UP.upgrade_file(&path).unwrap().unwrap();
+        let backup = dir.join("old.toml.v1.bak");
+        assert_eq!(report.backup.as_deref(), Some(backup.as_path()));
+        assert_eq!(fs::read_to_string(&backup).unwrap(), V1);
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            MADE_UP.migrate(V1).unwrap().0
+        );
+
+        assert_eq!(MADE_UP.upgrade_file(&path), Ok(None));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
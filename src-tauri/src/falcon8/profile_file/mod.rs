@@ -0,0 +1,1047 @@
+//! Profiles as TOML files that people can read, edit and share.
+//!
+//! ```toml
+//! schema_version = 1
+//!
+//! [keys]
+//! key1 = "Ctrl+Shift+F13"
+//! key2 = "Media.VolumeUp"
+//! key3 = "Macro.0"
+//!
+//! [lighting]
+//! mode = "breathing"
+//! speed = 128
+//! direction = "left_to_right"
+//! brightness = 255
+//!
+//! [colors]
+//! key1 = "#FF8000"
+//!
+//! [typing]
+//! layout = "de"
+//!
+//! [macros.0]
+//! repeat = 1
+//! events = ["down LeftShift", "tap H", "up LeftShift", "delay 50", "tap I", "type , Jürgen"]
+//!
+//! [actions.key4]
+//! press = { shell = "playerctl play-pause" }
+//! long_press = { url = "https://example.com" }
+//! hold_ms = 800
+//!
+//! [actions.key5]
+//! press = { text = "Grüße" }
+//! release = { text = "ça va", layout = "fr", unicode_input = true }
+//! ```
+//!
+//! Keys are named `key1` to `key8` as printed on the keypad; keys left out are disabled and
+//! colours left out are black. `[actions]` holds what keys do on the computer, on `press`,
+//! `release` or `long_press`: a `shell` command, a `url` to open, `text` to type, a `combo` to
+//! send or a `profile` slot to switch to.
+//!
+//! `[typing]` says which keyboard `layout` the computer is set to (`us`, `uk`, `de`, `fr` or
+//! `dvorak`, `us` if left out), for the text of `type` macro events and `text` actions. With
+//! `unicode_input = true`, characters the layout has no key for are typed by code point instead
+//! of being an error. A `text` action can set its own `layout` and `unicode_input` instead.
+//!
+//! `type` macro events are only a way of writing macros: the keypad stores the keys they press,
+//! so a profile read back from it, or saved again, has `down`, `up` and `tap` events instead.
+//!
+//! Files written for an older `schema_version` are upgraded when they're read, see `migrate`.
+
+use std::{collections::BTreeMap, fmt, fs, marker::PhantomData, path::Path};
+
+use serde::{
+    de::{self, MapAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use toml::Spanned;
+use toml_edit::{Document, Item, Value};
+
+use super::{
+    actions::{Action, KeyActions, DEFAULT_HOLD_MS},
+    protocol::{
+        keyboard_usage, keyboard_usage_name, KeyBinding, Layout, Macro, MacroEvent, Rgb, KEY_COUNT,
+        MACRO_SLOT_COUNT,
+    },
+    Falcon8Error, LightingSettings, Profile, Result,
+};
+
+mod migrate;
+
+pub use migrate::{migrate_profile, upgrade_profile_file, MigrationReport};
+
+/// The `schema_version` written to new files.
+pub const SCHEMA_VERSION: u32 = 1;
+
+const HEADER: &str = "\
+# Falcon-8 profile. Keys are named key1 to key8, from left to right.
+# Bindings look like \"Ctrl+Shift+F13\", \"Media.VolumeUp\", \"System.Sleep\", \"Mouse.Left\",
+# \"Macro.0\" or \"None\". Macro events are \"down <key>\", \"up <key>\", \"tap <key>\",
+# \"delay <ms>\" and \"type <text>\".
+
+";
+
+/// The file's layout. Macro steps are read as `Spanned<String>` and parsed afterwards, because
+/// TOML doesn't report where inside an array an error happened.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields, bound(deserialize = "Step: Deserialize<'de>"))]
+struct ProfileFile<Step> {
+    schema_version: u32,
+    #[serde(default)]
+    keys: PerKey<Combo>,
+    #[serde(default)]
+    lighting: LightingSettings,
+    #[serde(default)]
+    colors: PerKey<HexColor>,
+    #[serde(default, skip_serializing_if = "TypingSection::is_default")]
+    typing: TypingSection,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    macros: BTreeMap<MacroSlot, MacroSection<Step>>,
+    #[serde(default, skip_serializing_if = "PerKey::is_empty")]
+    actions: PerKey<Option<ActionsSection>>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TypingSection {
+    layout: Layout,
+    unicode_input: bool,
+}
+
+impl TypingSection {
+    /// Taken from the first `text` action. Others typed differently say so themselves.
+    fn new(actions: &[KeyActions]) -> Self {
+        let text = actions
+            .iter()
+            .flat_map(|actions| [&actions.press, &actions.release, &actions.long_press])
+            .find_map(|action| match action {
+                Some(Action::Text {
+                    layout,
+                    unicode_input,
+                    ..
+                }) => Some(Self {
+                    layout: *layout,
+                    unicode_input: *unicode_input,
+                }),
+                _ => None,
+            });
+        text.unwrap_or_default()
+    }
+
+    fn is_default(&self) -> bool {
+        self.layout == Layout::default() && !self.unicode_input
+    }
+}
+
+#[derive(Deserialize)]
+struct Version {
+    schema_version: Option<Spanned<u32>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MacroSection<Step> {
+    #[serde(default = "one")]
+    repeat: u8,
+    events: Vec<Step>,
+}
+
+fn one() -> u8 {
+    1
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ActionsSection {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    press: Option<ActionEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    release: Option<ActionEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    long_press: Option<ActionEntry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hold_ms: Option<u32>,
+}
+
+impl ActionsSection {
+    fn new(actions: &KeyActions, typing: &TypingSection) -> Option<Self> {
+        let entry = |action: &Option<Action>| {
+            action
+                .clone()
+                .map(|action| ActionEntry::new(action, typing))
+        };
+        (!actions.is_empty()).then(|| Self {
+            press: entry(&actions.press),
+            release: entry(&actions.release),
+            long_press: entry(&actions.long_press),
+            hold_ms: (actions.hold_ms != DEFAULT_HOLD_MS).then_some(actions.hold_ms),
+        })
+    }
+
+    fn into_actions(self, typing: &TypingSection) -> KeyActions {
+        let action = |entry: Option<ActionEntry>| entry.map(|entry| entry.into_action(typing));
+        KeyActions {
+            press: action(self.press),
+            release: action(self.release),
+            long_press: action(self.long_press),
+            hold_ms: self.hold_ms.unwrap_or(DEFAULT_HOLD_MS),
+        }
+    }
+}
+
+/// An `Action`, written as a table with one of its keys, such as `{ shell = "…" }`. `text`
+/// only gives the `layout` and `unicode_input` that differ from `[typing]`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "ActionTable", into = "ActionTable")]
+enum ActionEntry {
+    Shell(String),
+    Url(String),
+    Text {
+        text: String,
+        layout: Option<Layout>,
+        unicode_input: Option<bool>,
+    },
+    Combo(Combo),
+    Profile(u8),
+}
+
+/// How an `ActionEntry` is written, with every key it can have.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ActionTable {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    shell: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layout: Option<Layout>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unicode_input: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    combo: Option<Combo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<u8>,
+}
+
+impl TryFrom<ActionTable> for ActionEntry {
+    type Error = String;
+
+    fn try_from(table: ActionTable) -> std::result::Result<Self, String> {
+        let ActionTable {
+            shell,
+            url,
+            text,
+            layout,
+            unicode_input,
+            combo,
+            profile,
+        } = table;
+        if text.is_none() && (layout.is_some() || unicode_input.is_some()) {
+            return Err("`layout` and `unicode_input` only go with `text`".to_string());
+        }
+
+        let text = text.map(|text| Self::Text {
+            text,
+            layout,
+            unicode_input,
+        });
+        let mut entries = [
+            shell.map(Self::Shell),
+            url.map(Self::Url),
+            text,
+            combo.map(Self::Combo),
+            profile.map(Self::Profile),
+        ]
+        .into_iter()
+        .flatten();
+        match (entries.next(), entries.next()) {
+            (Some(entry), None) => Ok(entry),
+            _ => Err(
+                "expected exactly one of `shell`, `url`, `text`, `combo` or `profile`".to_string(),
+            ),
+        }
+    }
+}
+
+impl From<ActionEntry> for ActionTable {
+    fn from(entry: ActionEntry) -> Self {
+        match entry {
+            ActionEntry::Shell(shell) => Self {
+                shell: Some(shell),
+                ..Self::default()
+            },
+            ActionEntry::Url(url) => Self {
+                url: Some(url),
+                ..Self::default()
+            },
+            ActionEntry::Text {
+                text,
+                layout,
+                unicode_input,
+            } => Self {
+                text: Some(text),
+                layout,
+                unicode_input,
+                ..Self::default()
+            },
+            ActionEntry::Combo(combo) => Self {
+                combo: Some(combo),
+                ..Self::default()
+            },
+            ActionEntry::Profile(profile) => Self {
+                profile: Some(profile),
+                ..Self::default()
+            },
+        }
+    }
+}
+
+impl ActionEntry {
+    fn new(action: Action, typing: &TypingSection) -> Self {
+        match action {
+            Action::Shell { command } => Self::Shell(command),
+            Action::Url { url } => Self::Url(url),
+            Action::Text {
+                text,
+                layout,
+                unicode_input,
+            } => Self::Text {
+                text,
+                layout: (layout != typing.layout).then_some(layout),
+                unicode_input: (unicode_input != typing.unicode_input).then_some(unicode_input),
+            },
+            Action::Combo { combo } => Self::Combo(Combo(combo)),
+            Action::Profile { slot } => Self::Profile(slot),
+        }
+    }
+
+    fn into_action(self, typing: &TypingSection) -> Action {
+        match self {
+            Self::Shell(command) => Action::Shell { command },
+            Self::Url(url) => Action::Url { url },
+            Self::Text {
+                text,
+                layout,
+                unicode_input,
+            } => Action::Text {
+                text,
+                layout: layout.unwrap_or(typing.layout),
+                unicode_input: unicode_input.unwrap_or(typing.unicode_input),
+            },
+            Self::Combo(combo) => Action::Combo { combo: combo.0 },
+            Self::Profile(slot) => Action::Profile { slot },
+        }
+    }
+}
+
+/// Turns the `[actions.keyN.press]` style sections `toml` writes for actions into inline tables.
+fn inline_actions(body: &str) -> String {
+    let mut document: Document = body.parse().expect("toml writes valid TOML");
+    let keys = document
+        .get_mut("actions")
+        .and_then(Item::as_table_mut)
+        .into_iter()
+        .flat_map(|actions| actions.iter_mut());
+    for (_, key) in keys {
+        let Some(key) = key.as_table_mut() else {
+            continue;
+        };
+        let hold_ms = key.remove("hold_ms");
+        for (_, trigger) in key.iter_mut() {
+            if let Item::Table(table) = trigger {
+                let inline = std::mem::take(table).into_inline_table();
+                *trigger = Item::Value(Value::InlineTable(inline));
+            }
+        }
+        if let Some(hold_ms) = hold_ms {
+            key.insert("hold_ms", hold_ms);
+        }
+        key.fmt();
+    }
+    document.to_string()
+}
+
+/// A value for each key, written as a table from `key1` to `key8`.
+#[derive(Default)]
+struct PerKey<T>([T; KEY_COUNT]);
+
+impl<T> PerKey<Option<T>> {
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(Option::is_none)
+    }
+}
+
+impl<T: Serialize> Serialize for PerKey<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(KEY_COUNT))?;
+        for (key, value) in self.0.iter().enumerate() {
+            map.serialize_entry(&key_name(key), value)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Default> Deserialize<'de> for PerKey<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct PerKeyVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Deserialize<'de> + Default> Visitor<'de> for PerKeyVisitor<T> {
+            type Value = PerKey<T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a table with entries key1 to key8")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> std::result::Result<Self::Value, A::Error> {
+                let mut values = PerKey::<T>::default();
+                while let Some(KeyName(key)) = map.next_key()? {
+                    values.0[key] = map.next_value()?;
+                }
+                Ok(values)
+            }
+        }
+
+        deserializer.deserialize_map(PerKeyVisitor(PhantomData))
+    }
+}
+
+/// How profile files name the key with 0-based index `key`, e.g. `key1` for 0.
+pub(crate) fn key_name(key: usize) -> String {
+    format!("key{}", key + 1)
+}
+
+/// A key's index, written as `key1` to `key8`.
+struct KeyName(usize);
+
+impl<'de> Deserialize<'de> for KeyName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.strip_prefix("key")
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|n| (1..=KEY_COUNT).contains(n))
+            .map(|n| Self(n - 1))
+            .ok_or_else(|| {
+                de::Error::custom(format!("unknown key `{name}`, keys are named key1 to key8"))
+            })
+    }
+}
+
+/// A `KeyBinding` written as a combo string.
+#[derive(Clone, Copy, Default)]
+struct Combo(KeyBinding);
+
+impl Serialize for Combo {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Combo {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let combo = String::deserialize(deserializer)?;
+        combo.parse().map(Self).map_err(de::Error::custom)
+    }
+}
+
+/// An `Rgb` colour written as `#RRGGBB`.
+#[derive(Default)]
+struct HexColor(Rgb);
+
+impl Serialize for HexColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&hex_color(self.0))
+    }
+}
+
+/// `color` as written in profile files, e.g. `#FF8000`.
+pub(crate) fn hex_color([r, g, b]: Rgb) -> String {
+    format!("#{r:02X}{g:02X}{b:02X}")
+}
+
+/// `events` as written in profile files, e.g. `["tap A", "delay 50"]`.
+pub(crate) fn macro_steps(events: &[MacroEvent]) -> Vec<String> {
+    MacroStep::group(events)
+        .iter()
+        .map(MacroStep::to_string)
+        .collect()
+}
+
+impl<'de> Deserialize<'de> for HexColor {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let color = String::deserialize(deserializer)?;
+        color
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6 && hex.is_ascii())
+            .and_then(|hex| {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+                Some(Self([channel(0)?, channel(2)?, channel(4)?]))
+            })
+            .ok_or_else(|| de::Error::custom(format!("{color:?} is not a #RRGGBB colour")))
+    }
+}
+
+/// A macro slot number, written as a table key.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct MacroSlot(u8);
+
+impl Serialize for MacroSlot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for MacroSlot {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let slot = String::deserialize(deserializer)?;
+        slot.parse::<u8>()
+            .ok()
+            .filter(|&slot| usize::from(slot) < MACRO_SLOT_COUNT)
+            .map(Self)
+            .ok_or_else(|| {
+                de::Error::custom(format!(
+                    "macro slot `{slot}` does not exist, slots are numbered 0 to 15"
+                ))
+            })
+    }
+}
+
+/// One entry of a macro's `events`: `down <key>`, `up <key>`, `tap <key>`, `delay <ms>` or
+/// `type <text>`. A tap is a key down followed by the matching key up, and typing is a series
+/// of taps, only ever read.
+struct MacroStep(Vec<MacroEvent>);
+
+impl fmt::Display for MacroStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [MacroEvent::KeyDown { usage }, MacroEvent::KeyUp { .. }] => {
+                write!(f, "tap {}", keyboard_usage_name(*usage))
+            }
+            [MacroEvent::KeyDown { usage }] => write!(f, "down {}", keyboard_usage_name(*usage)),
+            [MacroEvent::KeyUp { usage }] => write!(f, "up {}", keyboard_usage_name(*usage)),
+            [MacroEvent::Delay { ms }] => write!(f, "delay {ms}"),
+            _ => unreachable!("steps come from `MacroStep::group`"),
+        }
+    }
+}
+
+impl Serialize for MacroStep {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl MacroStep {
+    /// Parses a step, typing the text of `type` steps as `typing` says.
+    fn parse(step: &str, typing: &TypingSection) -> std::result::Result<Self, String> {
+        let invalid = |reason: &str| format!("invalid macro event {step:?}: {reason}");
+
+        // Everything after `type ` is typed, spaces included.
+        if let Some(text) = step.trim_start().strip_prefix("type ") {
+            let keystrokes = if typing.unicode_input {
+                typing.layout.keystrokes_or_unicode(text)
+            } else {
+                typing.layout.keystrokes(text).map_err(|e| {
+                    invalid(&format!(
+                        "{e}, set `unicode_input = true` under [typing] to type it by code point"
+                    ))
+                })?
+            };
+            return Ok(Self(Macro::new().type_keystrokes(&keystrokes).events));
+        }
+
+        let (verb, argument) = step
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| invalid("expected down, up, tap, delay or type followed by a value"))?;
+        let argument = argument.trim();
+        let usage = || keyboard_usage(argument).map_err(|e| invalid(&e.to_string()));
+
+        Ok(Self(match verb {
+            "down" => vec![MacroEvent::KeyDown { usage: usage()? }],
+            "up" => vec![MacroEvent::KeyUp { usage: usage()? }],
+            "tap" => {
+                let usage = usage()?;
+                vec![MacroEvent::KeyDown { usage }, MacroEvent::KeyUp { usage }]
+            }
+            "delay" => vec![MacroEvent::Delay {
+                ms: argument
+                    .parse()
+                    .map_err(|_| invalid("delays are 0 to 65535 milliseconds"))?,
+            }],
+            _ => return Err(invalid("expected down, up, tap, delay or type")),
+        }))
+    }
+
+    /// Splits `events` into steps, writing a key down directly followed by its key up as a tap.
+    fn group(events: &[MacroEvent]) -> Vec<Self> {
+        let mut steps = Vec::new();
+        let mut rest = events;
+        while let Some((first, tail)) = rest.split_first() {
+            let len = match (first, tail.first()) {
+                (MacroEvent::KeyDown { usage }, Some(MacroEvent::KeyUp { usage: up }))
+                    if usage == up =>
+                {
+                    2
+                }
+                _ => 1,
+            };
+            steps.push(Self(rest[..len].to_vec()));
+            rest = &rest[len..];
+        }
+        steps
+    }
+}
+
+/// 1-based line and column of the byte at `offset`.
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+/// Turns a TOML error into one pointing at the line and column it happened at.
+fn syntax_error(text: &str, error: toml::de::Error) -> Falcon8Error {
+    let (line, column) = position(text, error.span().map_or(0, |span| span.start));
+    Falcon8Error::ProfileSyntax {
+        line,
+        column,
+        message: error.message().trim_end().to_string(),
+    }
+}
+
+/// Reads the file's `schema_version`, failing unless it's between 1 and `latest`.
+fn schema_version(text: &str, latest: u32) -> Result<u32> {
+    let version: Version = toml::from_str(text).map_err(|e| syntax_error(text, e))?;
+    let Some(version) = version.schema_version else {
+        return Err(Falcon8Error::ProfileSyntax {
+            line: 1,
+            column: 1,
+            message: "missing `schema_version`".to_string(),
+        });
+    };
+
+    if !(1..=latest).contains(version.get_ref()) {
+        let (line, column) = position(text, version.span().start);
+        return Err(Falcon8Error::ProfileSyntax {
+            line,
+            column,
+            message: format!(
+                "unsupported schema_version {}, the newest this app reads is {latest}",
+                version.get_ref()
+            ),
+        });
+    }
+    Ok(version.into_inner())
+}
+
+impl Profile {
+    /// Parses a profile file, upgrading it first if it was written for an older schema.
+    ///
+    /// Migrations edit the document in place, so errors in an upgraded file still point close
+    /// to where the problem is in the original.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        if schema_version(text, SCHEMA_VERSION)? < SCHEMA_VERSION {
+            let (migrated, _) = migrate_profile(text)?;
+            return Self::from_current_toml(&migrated);
+        }
+        Self::from_current_toml(text)
+    }
+
+    fn from_current_toml(text: &str) -> Result<Self> {
+        let file: ProfileFile<Spanned<String>> =
+            toml::from_str(text).map_err(|e| syntax_error(text, e))?;
+        let mut macros = BTreeMap::new();
+        for (slot, section) in file.macros {
+            let mut events = Vec::new();
+            for step in section.events {
+                let parsed = MacroStep::parse(step.get_ref(), &file.typing).map_err(|message| {
+                    let (line, column) = position(text, step.span().start);
+                    Falcon8Error::ProfileSyntax {
+                        line,
+                        column,
+                        message,
+                    }
+                })?;
+                events.extend(parsed.0);
+            }
+            let repeat = section.repeat;
+            macros.insert(slot.0, Macro { repeat, events });
+        }
+
+        Ok(Self {
+            keys: file.keys.0.map(|combo| combo.0),
+            lighting: file.lighting,
+            colors: file.colors.0.map(|color| color.0),
+            macros,
+            actions: file.actions.0.map(|section| {
+                section.map_or_else(KeyActions::default, |section| {
+                    section.into_actions(&file.typing)
+                })
+            }),
+        })
+    }
+
+    /// Writes the profile as a commented profile file.
+    pub fn to_toml(&self) -> String {
+        let typing = TypingSection::new(&self.actions);
+        let actions = self
+            .actions
+            .each_ref()
+            .map(|actions| ActionsSection::new(actions, &typing));
+        let file = ProfileFile {
+            schema_version: SCHEMA_VERSION,
+            keys: PerKey(self.keys.map(Combo)),
+            lighting: self.lighting,
+            colors: PerKey(self.colors.map(HexColor)),
+            typing,
+            macros: self
+                .macros
+                .iter()
+                .map(|(&slot, definition)| {
+                    let section = MacroSection {
+                        repeat: definition.repeat,
+                        events: MacroStep::group(&definition.events),
+                    };
+                    (MacroSlot(slot), section)
+                })
+                .collect(),
+            actions: PerKey(actions),
+        };
+        let body = toml::to_string(&file).expect("profile files serialize");
+        format!("{HEADER}{}", inline_actions(&body))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|e| Falcon8Error::file(path, e))?;
+        Self::from_toml(&text)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(|e| Falcon8Error::file(path, e))?;
+        }
+        fs::write(path, self.to_toml()).map_err(|e| Falcon8Error::file(path, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::{
+        actions::Action,
+        protocol::{usage, Direction, LightingMode, Modifiers},
+    };
+
+    const EXAMPLE: &str = r##"
+# Editing shortcuts.
+schema_version = 1
+
+[keys]
+key1 = "Ctrl+C"  # copy
+key2 = "Ctrl+V"
+key8 = "Macro.3"
+
+[lighting]
+mode = "wave"
+speed = 40
+direction = "right_to_left"
+brightness = 200
+
+[colors]
+key1 = "#ff8000"
+
+[macros.3]
+events = ["down LeftShift", "tap H", "up LeftShift", "delay 50", "tap I"]
+
+[actions.key3]
+press = { shell = "playerctl play-pause" }
+long_press = { combo = "Ctrl+Shift+M" }
+hold_ms = 800
+
+[actions.key4]
+release = { profile = 2 }
+"##;
+
+    const C: u8 = 0x06;
+    const M: u8 = 0x10;
+    const H: u8 = 0x0B;
+    const I: u8 = 0x0C;
+    const V: u8 = 0x19;
+
+    #[test]
+    fn test_parse() {
+        let profile = Profile::from_toml(EXAMPLE).unwrap();
+
+        assert_eq!(profile.keys[0], KeyBinding::combo(Modifiers::LEFT_CTRL, C));
+        assert_eq!(profile.keys[1], KeyBinding::combo(Modifiers::LEFT_CTRL, V));
+        assert!(profile.keys[2..7]
+            .iter()
+            .all(|key| *key == KeyBinding::Disabled));
+        assert_eq!(profile.keys[7], KeyBinding::Macro { slot: 3 });
+        assert_eq!(
+            profile.lighting,
+            LightingSettings {
+                mode: LightingMode::Wave,
+                speed: 40,
+                direction: Direction::RightToLeft,
+                brightness: 200,
+            }
+        );
+        assert_eq!(profile.colors[0], [0xFF, 0x80, 0x00]);
+        assert_eq!(profile.colors[1], [0, 0, 0]);
+        assert_eq!(
+            profile.macros[&3],
+            Macro::new()
+                .key_down(usage::keyboard::LEFT_SHIFT)
+                .tap(H)
+                .key_up(usage::keyboard::LEFT_SHIFT)
+                .delay(50)
+                .tap(I)
+        );
+        assert_eq!(
+            profile.actions[2],
+            KeyActions {
+                press: Some(Action::Shell {
+                    command: "playerctl play-pause".to_string()
+                }),
+                release: None,
+                long_press: Some(Action::Combo {
+                    combo: KeyBinding::combo(Modifiers::LEFT_CTRL | Modifiers::LEFT_SHIFT, M)
+                }),
+                hold_ms: 800,
+            }
+        );
+        assert_eq!(
+            profile.actions[3].release,
+            Some(Action::Profile { slot: 2 })
+        );
+        assert_eq!(profile.actions[3].hold_ms, DEFAULT_HOLD_MS);
+        assert!(profile.actions[0].is_empty());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut profile = Profile::from_toml(EXAMPLE).unwrap();
+        profile.keys[2] = KeyBinding::Consumer {
+            usage: usage::consumer::VOLUME_UP,
+        };
+        profile.keys[3] = KeyBinding::Consumer { usage: 0x1B8 };
+        profile.colors[7] = [1, 2, 3];
+        profile.macros.insert(
+            7,
+            Macro::new()
+                .repeat(3)
+                .key_down(usage::keyboard::A)
+                .key_down(usage::keyboard::Z)
+                .key_up(usage::keyboard::A)
+                .key_up(usage::keyboard::Z),
+        );
+
+        profile.actions[0].press = Some(Action::Text {
+            text: "Hello, \"world\"".to_string(),
+            layout: Layout::Fr,
+            unicode_input: true,
+        });
+        profile.actions[7].release = Some(Action::Url {
+            url: "https://example.com".to_string(),
+        });
+
+        let text = profile.to_toml();
+        assert!(text.starts_with("# Falcon-8 profile."));
+        assert!(text.contains("[actions.key3]\npress = { shell = \"playerctl play-pause\" }\n"));
+        assert!(text.contains("hold_ms = 800\n"));
+        assert!(!text.contains("[actions.key2]"));
+        assert!(text.contains("key1 = \"Ctrl+C\""));
+        assert!(text.contains("key4 = \"Media.0x01B8\""));
+        assert!(text.contains("key8 = \"#010203\""));
+        assert!(text.contains("\"tap H\""));
+        assert!(text.contains("[typing]\nlayout = \"fr\"\nunicode_input = true\n"));
+        assert_eq!(Profile::from_toml(&text), Ok(profile));
+    }
+
+    #[test]
+    fn test_typing() {
+        let typed = |typing: &str, step: &str| {
+            let text = format!(
+                "schema_version = 1\n{typing}\n[macros.0]\nevents = [{step:?}]\n\
+                 [actions.key1]\npress = {{ text = \"ok\" }}\n"
+            );
+            Profile::from_toml(&text)
+        };
+
+        let profile = typed("[typing]\nlayout = \"de\"", "type zü ").unwrap();
+        assert_eq!(
+            profile.macros[&0],
+            Macro::new().tap(0x1C).tap(0x2F).tap(usage::keyboard::SPACE)
+        );
+        assert_eq!(
+            profile.actions[0].press,
+            Some(Action::Text {
+                text: "ok".to_string(),
+                layout: Layout::De,
+                unicode_input: false,
+            })
+        );
+
+        let Err(Falcon8Error::ProfileSyntax { line, message, .. }) = typed("", "type zü") else {
+            panic!("ü can't be typed on a US layout");
+        };
+        assert_eq!(line, 4);
+        assert!(
+            message.contains("'ü' can't be typed on the us"),
+            "{message}"
+        );
+        assert!(message.contains("unicode_input"), "{message}");
+
+        let profile = typed("[typing]\nunicode_input = true", "type ü").unwrap();
+        let expected = Macro::new().type_keystrokes(&Layout::Us.unicode_input('ü'));
+        assert_eq!(profile.macros[&0], expected);
+
+        assert!(typed("[typing]\nlayout = \"azerty\"", "tap A").is_err());
+    }
+
+    #[test]
+    fn test_type_is_written_as_taps() {
+        let text = "schema_version = 1\n[macros.0]\nevents = [\"type Hi\"]\n";
+        let profile = Profile::from_toml(text).unwrap();
+        let toml = profile.to_toml();
+        assert!(!toml.contains("type Hi"), "{toml}");
+        assert!(
+            toml.contains("events = [\"down LeftShift\", \"tap H\", \"up LeftShift\", \"tap I\"]"),
+            "{toml}"
+        );
+        assert_eq!(Profile::from_toml(&toml), Ok(profile));
+    }
+
+    #[test]
+    fn test_text_layouts() {
+        let text = |text: &str, layout, unicode_input| {
+            Some(Action::Text {
+                text: text.to_string(),
+                layout,
+                unicode_input,
+            })
+        };
+        let mut profile = Profile::from_toml(EXAMPLE).unwrap();
+        profile.actions[0].press = text("Grüße", Layout::De, false);
+        profile.actions[1].press = text("ça va", Layout::Fr, true);
+        profile.actions[1].release = text("ok", Layout::De, true);
+
+        let toml = profile.to_toml();
+        assert!(toml.contains("[typing]\nlayout = \"de\"\n"), "{toml}");
+        assert!(toml.contains("press = { text = \"Grüße\" }\n"), "{toml}");
+        assert!(
+            toml.contains("press = { text = \"ça va\", layout = \"fr\", unicode_input = true }\n"),
+            "{toml}"
+        );
+        assert!(
+            toml.contains("release = { text = \"ok\", unicode_input = true }\n"),
+            "{toml}"
+        );
+        assert_eq!(Profile::from_toml(&toml), Ok(profile));
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |text: &str| match Profile::from_toml(text) {
+            Err(Falcon8Error::ProfileSyntax {
+                line,
+                column,
+                message,
+            }) => (line, column, message),
+            result => panic!("expected a syntax error, got {result:?}"),
+        };
+
+        let (line, column, message) = error(&EXAMPLE.replace("Ctrl+V", "Ctrl+Hyper+V"));
+        assert_eq!((line, column), (7, 8));
+        assert!(
+            message.contains("unknown key \"Hyper\", did you mean \"Super\"?"),
+            "{message}"
+        );
+
+        let (line, column, message) = error(&EXAMPLE.replace("key8 =", "key9 ="));
+        assert_eq!((line, column), (8, 1));
+        assert!(message.contains("key1 to key8"), "{message}");
+
+        let (line, column, message) = error(&EXAMPLE.replace("#ff8000", "orange"));
+        assert_eq!((line, column), (17, 8));
+        assert!(message.contains("#RRGGBB"), "{message}");
+
+        let (line, column, message) = error(&EXAMPLE.replace("\"tap I\"", "\"tap Escpae\""));
+        assert_eq!((line, column), (20, 66));
+        assert!(
+            message.contains("unknown key \"Escpae\", did you mean \"Escape\"?"),
+            "{message}"
+        );
+
+        let (line, column, message) = error(&EXAMPLE.replace("wave", "sparkle"));
+        assert_eq!((line, column), (11, 8));
+        assert!(message.contains("sparkle"), "{message}");
+
+        let (line, _, _) = error(&EXAMPLE.replace("[macros.3]", "[macros.16]"));
+        assert_eq!(line, 19);
+
+        let (line, _, _) = error(&EXAMPLE.replace("speed = 40", "speed = 40\nsped = 4"));
+        assert_eq!(line, 13);
+
+        let (line, _, message) = error(&EXAMPLE.replace("shell =", "launch ="));
+        assert_eq!(line, 23);
+        assert!(message.contains("launch"), "{message}");
+
+        let (line, _, message) =
+            error(&EXAMPLE.replace("play-pause\"", "play-pause\", layout = \"de\""));
+        assert_eq!(line, 23);
+        assert!(message.contains("only go with `text`"), "{message}");
+
+        let (line, _, message) =
+            error(&EXAMPLE.replace("{ profile = 2 }", "{ profile = 2, url = \"x\" }"));
+        assert_eq!(line, 28);
+        assert!(message.contains("exactly one of"), "{message}");
+
+        let (line, _, message) = error(&EXAMPLE.replace("Ctrl+Shift+M", "Ctrl+Shift+Nope"));
+        assert_eq!(line, 24);
+        assert!(message.contains("Nope"), "{message}");
+
+        let (line, column, message) =
+            error(&EXAMPLE.replace("schema_version = 1", "schema_version = 2"));
+        assert_eq!((line, column), (3, 18));
+        assert!(
+            message.contains("unsupported schema_version 2"),
+            "{message}"
+        );
+
+        let (line, column, message) = error("[keys]\nkey1 = \"A\"\n");
+        assert_eq!((line, column), (1, 1));
+        assert!(message.contains("schema_version"), "{message}");
+
+        let (line, _, _) = error("schema_version = 1\n[keys\n");
+        assert_eq!(line, 2);
+    }
+
+    #[test]
+    fn test_load_and_save() {
+        let dir = std::env::temp_dir().join(format!("falcon8-profile-{}", std::process::id()));
+        let path = dir.join("profiles").join("editing.toml");
+
+        let profile = Profile::from_toml(EXAMPLE).unwrap();
+        profile.save(&path).unwrap();
+        assert_eq!(Profile::load(&path), Ok(profile));
+
+        assert!(matches!(
+            Profile::load(dir.join("missing.toml")),
+            Err(Falcon8Error::File { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+//! What a key sends, as stored in a profile's key map.
+//!
+//! Each binding is `BINDING_SIZE` bytes, a type byte followed by its arguments:
+//!
+//! ```text
+//! 0x00                            disabled
+//! 0x01  modifiers  usage     0    keyboard (usage page 0x07)
+//! 0x02  usage (u16 LE)       0    consumer control (usage page 0x0C)
+//! 0x03  usage      0         0    system control (usage page 0x01)
+//! 0x04  buttons    0         0    mouse buttons
+//! 0x05  slot       0         0    play a macro slot
+//! ```
+
+use std::ops::{BitOr, BitOrAssign};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    check_range, DecodeError, FeatureReport, KeyMapReport, BINDING_SIZE, MACRO_SLOT_COUNT,
+};
+
+/// Usage IDs for the bindings' usage fields, from the HID Usage Tables.
+pub mod usage {
+    /// Keyboard/Keypad page (0x07).
+    pub mod keyboard {
+        pub const A: u8 = 0x04;
+        pub const Z: u8 = 0x1D;
+        pub const N1: u8 = 0x1E;
+        pub const N0: u8 = 0x27;
+        pub const ENTER: u8 = 0x28;
+        pub const ESCAPE: u8 = 0x29;
+        pub const BACKSPACE: u8 = 0x2A;
+        pub const TAB: u8 = 0x2B;
+        pub const SPACE: u8 = 0x2C;
+        pub const F1: u8 = 0x3A;
+        pub const F12: u8 = 0x45;
+        pub const PRINT_SCREEN: u8 = 0x46;
+        pub const DELETE: u8 = 0x4C;
+        pub const RIGHT: u8 = 0x4F;
+        pub const LEFT: u8 = 0x50;
+        pub const DOWN: u8 = 0x51;
+        pub const UP: u8 = 0x52;
+        pub const F13: u8 = 0x68;
+        pub const F24: u8 = 0x73;
+        pub const LEFT_CTRL: u8 = 0xE0;
+        pub const LEFT_SHIFT: u8 = 0xE1;
+        pub const LEFT_ALT: u8 = 0xE2;
+        pub const LEFT_GUI: u8 = 0xE3;
+        pub const RIGHT_CTRL: u8 = 0xE4;
+        pub const RIGHT_SHIFT: u8 = 0xE5;
+        pub const RIGHT_ALT: u8 = 0xE6;
+        pub const RIGHT_GUI: u8 = 0xE7;
+    }
+
+    /// Consumer page (0x0C).
+    pub mod consumer {
+        pub const NEXT_TRACK: u16 = 0xB5;
+        pub const PREVIOUS_TRACK: u16 = 0xB6;
+        pub const STOP: u16 = 0xB7;
+        pub const PLAY_PAUSE: u16 = 0xCD;
+        pub const MUTE: u16 = 0xE2;
+        pub const VOLUME_UP: u16 = 0xE9;
+        pub const VOLUME_DOWN: u16 = 0xEA;
+        pub const CALCULATOR: u16 = 0x192;
+        pub const BROWSER_HOME: u16 = 0x223;
+    }
+
+    /// Generic Desktop page (0x01) system controls.
+    pub mod system {
+        pub const POWER_DOWN: u8 = 0x81;
+        pub const SLEEP: u8 = 0x82;
+        pub const WAKE_UP: u8 = 0x83;
+    }
+}
+
+/// Keyboard modifier bits, in the order of the boot keyboard report's first byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Modifiers(pub u8);
+
+impl Modifiers {
+    pub const NONE: Self = Self(0);
+    pub const LEFT_CTRL: Self = Self(1 << 0);
+    pub const LEFT_SHIFT: Self = Self(1 << 1);
+    pub const LEFT_ALT: Self = Self(1 << 2);
+    pub const LEFT_GUI: Self = Self(1 << 3);
+    pub const RIGHT_CTRL: Self = Self(1 << 4);
+    pub const RIGHT_SHIFT: Self = Self(1 << 5);
+    pub const RIGHT_ALT: Self = Self(1 << 6);
+    pub const RIGHT_GUI: Self = Self(1 << 7);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Keyboard usages of the modifier keys held, from Left Ctrl to Right GUI.
+    pub fn usages(self) -> impl DoubleEndedIterator<Item = u8> {
+        (0..8)
+            .filter(move |bit| self.0 & 1 << bit != 0)
+            .map(|bit| usage::keyboard::LEFT_CTRL + bit)
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Modifiers {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Mouse button bits, as in the boot mouse report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MouseButtons(pub u8);
+
+impl MouseButtons {
+    pub const LEFT: Self = Self(1 << 0);
+    pub const RIGHT: Self = Self(1 << 1);
+    pub const MIDDLE: Self = Self(1 << 2);
+    pub const BACK: Self = Self(1 << 3);
+    pub const FORWARD: Self = Self(1 << 4);
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for MouseButtons {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// What pressing a key sends.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KeyBinding {
+    #[default]
+    Disabled,
+    /// A keyboard usage with modifiers held. `usage` may be 0 for a modifier-only key.
+    Keyboard {
+        modifiers: Modifiers,
+        usage: u8,
+    },
+    /// Media and application keys.
+    Consumer {
+        usage: u16,
+    },
+    /// Power, sleep and wake.
+    System {
+        usage: u8,
+    },
+    Mouse {
+        buttons: MouseButtons,
+    },
+    /// Plays back the macro stored in a macro slot.
+    Macro {
+        slot: u8,
+    },
+}
+
+impl KeyBinding {
+    const DISABLED: u8 = 0x00;
+    const KEYBOARD: u8 = 0x01;
+    const CONSUMER: u8 = 0x02;
+    const SYSTEM: u8 = 0x03;
+    const MOUSE: u8 = 0x04;
+    const MACRO: u8 = 0x05;
+
+    pub const fn key(usage: u8) -> Self {
+        Self::Keyboard {
+            modifiers: Modifiers::NONE,
+            usage,
+        }
+    }
+
+    pub const fn combo(modifiers: Modifiers, usage: u8) -> Self {
+        Self::Keyboard { modifiers, usage }
+    }
+
+    pub fn encode(&self) -> [u8; BINDING_SIZE] {
+        match *self {
+            Self::Disabled => [Self::DISABLED, 0, 0, 0],
+            Self::Keyboard { modifiers, usage } => [Self::KEYBOARD, modifiers.0, usage, 0],
+            Self::Consumer { usage } => {
+                let [lo, hi] = usage.to_le_bytes();
+                [Self::CONSUMER, lo, hi, 0]
+            }
+            Self::System { usage } => [Self::SYSTEM, usage, 0, 0],
+            Self::Mouse { buttons } => [Self::MOUSE, buttons.0, 0, 0],
+            Self::Macro { slot } => [Self::MACRO, slot, 0, 0],
+        }
+    }
+
+    pub fn decode(raw: [u8; BINDING_SIZE]) -> Result<Self, DecodeError> {
+        match raw[0] {
+            Self::DISABLED => Ok(Self::Disabled),
+            Self::KEYBOARD => Ok(Self::Keyboard {
+                modifiers: Modifiers(raw[1]),
+                usage: raw[2],
+            }),
+            Self::CONSUMER => Ok(Self::Consumer {
+                usage: u16::from_le_bytes([raw[1], raw[2]]),
+            }),
+            Self::SYSTEM => Ok(Self::System { usage: raw[1] }),
+            Self::MOUSE => Ok(Self::Mouse {
+                buttons: MouseButtons(raw[1]),
+            }),
+            Self::MACRO => Ok(Self::Macro {
+                slot: check_range(KeyMapReport::ID, "macro slot", raw[1], MACRO_SLOT_COUNT)?,
+            }),
+            value => Err(DecodeError::InvalidField {
+                report_id: KeyMapReport::ID,
+                field: "binding type",
+                value,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn binding() -> impl Strategy<Value = KeyBinding> {
+        prop_oneof![
+            Just(KeyBinding::Disabled),
+            (any::<u8>(), any::<u8>())
+                .prop_map(|(m, usage)| KeyBinding::combo(Modifiers(m), usage)),
+            any::<u16>().prop_map(|usage| KeyBinding::Consumer { usage }),
+            any::<u8>().prop_map(|usage| KeyBinding::System { usage }),
+            any::<u8>().prop_map(|b| KeyBinding::Mouse {
+                buttons: MouseButtons(b)
+            }),
+            (0..MACRO_SLOT_COUNT as u8).prop_map(|slot| KeyBinding::Macro { slot }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn binding_round_trip(binding in binding()) {
+            prop_assert_eq!(KeyBinding::decode(binding.encode()), Ok(binding));
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let copy = KeyBinding::combo(Modifiers::LEFT_CTRL, usage::keyboard::A + 2);
+        assert_eq!(copy.encode(), [0x01, 0x01, 0x06, 0x00]);
+
+        let volume = KeyBinding::Consumer {
+            usage: usage::consumer::CALCULATOR,
+        };
+        assert_eq!(volume.encode(), [0x02, 0x92, 0x01, 0x00]);
+
+        assert_eq!(
+            KeyBinding::decode([0x09, 0, 0, 0]),
+            Err(DecodeError::InvalidField {
+                report_id: KeyMapReport::ID,
+                field: "binding type",
+                value: 0x09,
+            })
+        );
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let mods = Modifiers::LEFT_CTRL | Modifiers::RIGHT_SHIFT;
+        assert!(mods.contains(Modifiers::LEFT_CTRL));
+        assert!(!mods.contains(Modifiers::LEFT_SHIFT));
+        assert!(Modifiers::NONE.is_empty());
+    }
+
+    #[test]
+    fn test_serialization() {
+        let binding = KeyBinding::combo(Modifiers::LEFT_SHIFT, usage::keyboard::F13);
+        let json = serde_json::to_value(binding).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "type": "keyboard", "modifiers": 2, "usage": 0x68 })
+        );
+        assert_eq!(serde_json::from_value::<KeyBinding>(json).unwrap(), binding);
+    }
+}
@@ -0,0 +1,628 @@
+//! Key bindings as readable strings: `Ctrl+Shift+F13`, `Media.VolumeUp`, `Mouse.Left`,
+//! `System.Sleep`, `Macro.3` and `None`.
+//!
+//! Usages without a name are written as hex (`0x87`, `Media.0x01B8`), so every binding formats
+//! to a string that parses back to it.
+//!
+//! Parsing is more forgiving than formatting: names are case-insensitive, and some keys have
+//! aliases, like `Cmd` or `Win` for `Super` and `Esc` for `Escape`. Unknown names are reported
+//! with the known ones closest to them.
+
+use std::{fmt, str::FromStr};
+
+use super::{KeyBinding, Modifiers, MouseButtons, MACRO_SLOT_COUNT};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ComboError {
+    #[error("empty key combo")]
+    Empty,
+    #[error("unknown key {name:?}{}", did_you_mean(.suggestions))]
+    UnknownKey {
+        name: String,
+        /// Known names close to `name`, closest first.
+        suggestions: Vec<String>,
+    },
+    #[error("{0:?} is not a modifier, only the last key of a combo can be a regular key")]
+    NotAModifier(String),
+    #[error("{0:?} can't be combined with other keys")]
+    NotCombinable(String),
+    #[error("macro slot {0:?} does not exist, slots are numbered 0 to 15")]
+    InvalidMacroSlot(String),
+}
+
+/// Modifier names in the order they're written, matching the bits of `Modifiers`.
+const MODIFIERS: [(&str, Modifiers); 8] = [
+    ("Ctrl", Modifiers::LEFT_CTRL),
+    ("Shift", Modifiers::LEFT_SHIFT),
+    ("Alt", Modifiers::LEFT_ALT),
+    ("Super", Modifiers::LEFT_GUI),
+    ("RCtrl", Modifiers::RIGHT_CTRL),
+    ("RShift", Modifiers::RIGHT_SHIFT),
+    ("RAlt", Modifiers::RIGHT_ALT),
+    ("RSuper", Modifiers::RIGHT_GUI),
+];
+
+/// Other names modifiers go by, as printed on Mac and Windows keyboards.
+const MODIFIER_ALIASES: &[(&str, Modifiers)] = &[
+    ("Control", Modifiers::LEFT_CTRL),
+    ("Option", Modifiers::LEFT_ALT),
+    ("Opt", Modifiers::LEFT_ALT),
+    ("Cmd", Modifiers::LEFT_GUI),
+    ("Command", Modifiers::LEFT_GUI),
+    ("Win", Modifiers::LEFT_GUI),
+    ("Meta", Modifiers::LEFT_GUI),
+    ("RControl", Modifiers::RIGHT_CTRL),
+    ("AltGr", Modifiers::RIGHT_ALT),
+    ("RCmd", Modifiers::RIGHT_GUI),
+    ("RWin", Modifiers::RIGHT_GUI),
+    ("RMeta", Modifiers::RIGHT_GUI),
+];
+
+/// Keyboard page usages from Enter to F12 that don't follow a numbering pattern.
+const KEYBOARD_NAMES: &[(u8, &str)] = &[
+    (0x28, "Enter"),
+    (0x29, "Escape"),
+    (0x2A, "Backspace"),
+    (0x2B, "Tab"),
+    (0x2C, "Space"),
+    (0x2D, "Minus"),
+    (0x2E, "Equal"),
+    (0x2F, "LeftBracket"),
+    (0x30, "RightBracket"),
+    (0x31, "Backslash"),
+    (0x32, "NonUsHash"),
+    (0x33, "Semicolon"),
+    (0x34, "Quote"),
+    (0x35, "Grave"),
+    (0x36, "Comma"),
+    (0x37, "Period"),
+    (0x38, "Slash"),
+    (0x39, "CapsLock"),
+    (0x46, "PrintScreen"),
+    (0x47, "ScrollLock"),
+    (0x48, "Pause"),
+    (0x49, "Insert"),
+    (0x4A, "Home"),
+    (0x4B, "PageUp"),
+    (0x4C, "Delete"),
+    (0x4D, "End"),
+    (0x4E, "PageDown"),
+    (0x4F, "Right"),
+    (0x50, "Left"),
+    (0x51, "Down"),
+    (0x52, "Up"),
+    (0x53, "NumLock"),
+    (0x54, "KpSlash"),
+    (0x55, "KpAsterisk"),
+    (0x56, "KpMinus"),
+    (0x57, "KpPlus"),
+    (0x58, "KpEnter"),
+    (0x62, "Kp0"),
+    (0x63, "KpPeriod"),
+    (0x64, "NonUsBackslash"),
+    (0x65, "Application"),
+    (0x66, "Power"),
+    (0x67, "KpEqual"),
+    (0xE0, "LeftCtrl"),
+    (0xE1, "LeftShift"),
+    (0xE2, "LeftAlt"),
+    (0xE3, "LeftSuper"),
+    (0xE4, "RightCtrl"),
+    (0xE5, "RightShift"),
+    (0xE6, "RightAlt"),
+    (0xE7, "RightSuper"),
+];
+
+/// Other names keys go by, accepted when parsing but never written.
+const KEYBOARD_ALIASES: &[(u8, &str)] = &[
+    (0x28, "Return"),
+    (0x29, "Esc"),
+    (0x2C, "Spacebar"),
+    (0x46, "PrtSc"),
+    (0x49, "Ins"),
+    (0x4B, "PgUp"),
+    (0x4C, "Del"),
+    (0x4E, "PgDn"),
+    (0x65, "Menu"),
+];
+
+const CONSUMER_NAMES: &[(u16, &str)] = &[
+    (0xB5, "Next"),
+    (0xB6, "Previous"),
+    (0xB7, "Stop"),
+    (0xCD, "PlayPause"),
+    (0xE2, "Mute"),
+    (0xE9, "VolumeUp"),
+    (0xEA, "VolumeDown"),
+    (0x183, "MediaPlayer"),
+    (0x18A, "Mail"),
+    (0x192, "Calculator"),
+    (0x194, "FileBrowser"),
+    (0x221, "Search"),
+    (0x223, "BrowserHome"),
+    (0x224, "BrowserBack"),
+    (0x225, "BrowserForward"),
+    (0x227, "BrowserRefresh"),
+];
+
+const CONSUMER_ALIASES: &[(u16, &str)] = &[
+    (0xB5, "NextTrack"),
+    (0xB6, "PreviousTrack"),
+    (0xB6, "Prev"),
+    (0xE2, "VolumeMute"),
+];
+
+const SYSTEM_NAMES: &[(u8, &str)] = &[(0x81, "PowerDown"), (0x82, "Sleep"), (0x83, "WakeUp")];
+
+const MOUSE_NAMES: [(&str, MouseButtons); 5] = [
+    ("Left", MouseButtons::LEFT),
+    ("Right", MouseButtons::RIGHT),
+    ("Middle", MouseButtons::MIDDLE),
+    ("Back", MouseButtons::BACK),
+    ("Forward", MouseButtons::FORWARD),
+];
+
+/// Name of a keyboard page usage, e.g. `A`, `1`, `F13`, `LeftShift` or `0x87`.
+pub fn keyboard_usage_name(usage: u8) -> String {
+    match usage {
+        0x04..=0x1D => char::from(b'A' + usage - 0x04).to_string(),
+        0x1E..=0x26 => (usage - 0x1D).to_string(),
+        0x27 => "0".to_string(),
+        0x3A..=0x45 => format!("F{}", usage - 0x39),
+        0x59..=0x61 => format!("Kp{}", usage - 0x58),
+        0x68..=0x73 => format!("F{}", usage - 0x68 + 13),
+        _ => match KEYBOARD_NAMES.iter().find(|(u, _)| *u == usage) {
+            Some((_, name)) => name.to_string(),
+            None => format!("{usage:#04X}").replace("0X", "0x"),
+        },
+    }
+}
+
+fn parse_hex<T: TryFrom<u32>>(s: &str) -> Option<T> {
+    let digits = strip_prefix(s, "0x")?;
+    T::try_from(u32::from_str_radix(digits, 16).ok()?).ok()
+}
+
+/// `s` without `prefix`, ignoring case.
+fn strip_prefix<'a>(s: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = s.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &s[prefix.len()..])
+}
+
+/// Keyboard usages with a name, as opposed to hex.
+fn named_keyboard_usages() -> impl Iterator<Item = (u8, String)> {
+    (0..=u8::MAX)
+        .map(|usage| (usage, keyboard_usage_name(usage)))
+        .filter(|(_, name)| !name.starts_with("0x"))
+}
+
+/// Parses a name written by `keyboard_usage_name`, or one of its aliases, ignoring case.
+pub fn parse_keyboard_usage(name: &str) -> Option<u8> {
+    parse_hex(name)
+        .or_else(|| lookup(KEYBOARD_ALIASES, name))
+        .or_else(|| {
+            named_keyboard_usages()
+                .find(|(_, n)| n.eq_ignore_ascii_case(name))
+                .map(|(usage, _)| usage)
+        })
+}
+
+/// Like `parse_keyboard_usage`, with an error suggesting close names.
+pub fn keyboard_usage(name: &str) -> Result<u8, ComboError> {
+    parse_keyboard_usage(name)
+        .ok_or_else(|| unknown_key(name, named_keyboard_usages().map(|(_, name)| name)))
+}
+
+fn lookup<T: Copy>(table: &[(T, &str)], name: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(_, n)| n.eq_ignore_ascii_case(name))
+        .map(|(v, _)| *v)
+}
+
+fn name_of<T: Copy + PartialEq>(table: &[(T, &'static str)], value: T) -> Option<&'static str> {
+    table.iter().find(|(v, _)| *v == value).map(|(_, n)| *n)
+}
+
+fn parse_modifier(name: &str) -> Option<Modifiers> {
+    MODIFIERS
+        .iter()
+        .chain(MODIFIER_ALIASES)
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, m)| *m)
+}
+
+/// Edit distance between `a` and `b`, ignoring case, where swapping two letters is one edit.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    // rows[i][j] is the distance between the first i letters of `a` and the first j of `b`.
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i];
+        for j in 1..=b.len() {
+            let mut d = (rows[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]))
+                .min(rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(rows[i - 2][j - 2] + 1);
+            }
+            row.push(d);
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+/// An unknown key error for `name`, suggesting up to three of `candidates`: those a few edits
+/// away from it, or that start with it.
+fn unknown_key(name: &str, candidates: impl IntoIterator<Item = String>) -> ComboError {
+    // The namespace of `Media.Mute` and the like doesn't count towards the edits allowed.
+    let key = name.rsplit('.').next().unwrap_or(name);
+    let max_distance = ((key.chars().count() + 1) / 3).max(1);
+    let mut close: Vec<(usize, String)> = candidates
+        .into_iter()
+        .filter_map(|candidate| {
+            let d = distance(name, &candidate);
+            let extends = name.len() >= 3 && strip_prefix(&candidate, name).is_some();
+            (d <= max_distance || extends).then_some((d, candidate))
+        })
+        .collect();
+    close.sort();
+    close.dedup_by(|a, b| a.1 == b.1);
+    ComboError::UnknownKey {
+        name: name.to_string(),
+        suggestions: close.into_iter().take(3).map(|(_, name)| name).collect(),
+    }
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("{s:?}")).collect();
+    match quoted.as_slice() {
+        [] => String::new(),
+        [only] => format!(", did you mean {only}?"),
+        [rest @ .., last] => format!(", did you mean {} or {last}?", rest.join(", ")),
+    }
+}
+
+/// Names written `<prefix><name>`, for suggestions.
+fn prefixed<T>(prefix: &str, table: &[(T, &str)]) -> Vec<String> {
+    table
+        .iter()
+        .map(|(_, name)| format!("{prefix}{name}"))
+        .collect()
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Disabled => f.write_str("None"),
+            Self::Keyboard { modifiers, usage } => {
+                let mut parts: Vec<String> = MODIFIERS
+                    .iter()
+                    .filter(|(_, m)| modifiers.contains(*m))
+                    .map(|(name, _)| name.to_string())
+                    .collect();
+                if usage != 0 || parts.is_empty() {
+                    parts.push(keyboard_usage_name(usage));
+                }
+                f.write_str(&parts.join("+"))
+            }
+            Self::Consumer { usage } => match name_of(CONSUMER_NAMES, usage) {
+                Some(name) => write!(f, "Media.{name}"),
+                None => write!(f, "Media.0x{usage:04X}"),
+            },
+            Self::System { usage } => match name_of(SYSTEM_NAMES, usage) {
+                Some(name) => write!(f, "System.{name}"),
+                None => write!(f, "System.0x{usage:02X}"),
+            },
+            Self::Mouse { buttons } => {
+                let named = MOUSE_NAMES.iter().fold(0, |bits, (_, b)| bits | b.0);
+                let mut parts: Vec<String> = MOUSE_NAMES
+                    .iter()
+                    .filter(|(_, b)| buttons.contains(*b))
+                    .map(|(name, _)| format!("Mouse.{name}"))
+                    .collect();
+                if buttons.0 & !named != 0 || parts.is_empty() {
+                    parts.push(format!("Mouse.0x{:02X}", buttons.0 & !named));
+                }
+                f.write_str(&parts.join("+"))
+            }
+            Self::Macro { slot } => write!(f, "Macro.{slot}"),
+        }
+    }
+}
+
+impl FromStr for KeyBinding {
+    type Err = ComboError;
+
+    fn from_str(s: &str) -> Result<Self, ComboError> {
+        let parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        if parts.iter().any(|part| part.is_empty()) {
+            return Err(ComboError::Empty);
+        }
+        let single = || match parts.as_slice() {
+            [part] => Ok(*part),
+            _ => Err(ComboError::NotCombinable(s.trim().to_string())),
+        };
+
+        let first = parts[0];
+        if first.eq_ignore_ascii_case("None") {
+            single()?;
+            return Ok(Self::Disabled);
+        }
+        if let Some(name) = strip_prefix(first, "Media.") {
+            single()?;
+            let usage = parse_hex(name)
+                .or_else(|| lookup(CONSUMER_NAMES, name))
+                .or_else(|| lookup(CONSUMER_ALIASES, name));
+            return usage
+                .map(|usage| Self::Consumer { usage })
+                .ok_or_else(|| unknown_key(first, prefixed("Media.", CONSUMER_NAMES)));
+        }
+        if let Some(name) = strip_prefix(first, "System.") {
+            single()?;
+            let usage = parse_hex(name).or_else(|| lookup(SYSTEM_NAMES, name));
+            return usage
+                .map(|usage| Self::System { usage })
+                .ok_or_else(|| unknown_key(first, prefixed("System.", SYSTEM_NAMES)));
+        }
+        if let Some(slot) = strip_prefix(first, "Macro.") {
+            single()?;
+            return match slot.parse::<u8>() {
+                Ok(slot) if usize::from(slot) < MACRO_SLOT_COUNT => Ok(Self::Macro { slot }),
+                _ => Err(ComboError::InvalidMacroSlot(slot.to_string())),
+            };
+        }
+        if strip_prefix(first, "Mouse.").is_some() {
+            let mut buttons = MouseButtons::default();
+            for part in &parts {
+                let name = strip_prefix(part, "Mouse.")
+                    .ok_or_else(|| ComboError::NotCombinable(part.to_string()))?;
+                let bits = parse_hex(name)
+                    .or_else(|| {
+                        MOUSE_NAMES
+                            .iter()
+                            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                            .map(|(_, b)| b.0)
+                    })
+                    .ok_or_else(|| {
+                        let names = MOUSE_NAMES.iter().map(|(name, _)| format!("Mouse.{name}"));
+                        unknown_key(part, names)
+                    })?;
+                buttons = buttons | MouseButtons(bits);
+            }
+            return Ok(Self::Mouse { buttons });
+        }
+
+        let (last, held) = parts.split_last().expect("split yields at least one part");
+        let mut modifiers = Modifiers::NONE;
+        for part in held {
+            modifiers |= parse_modifier(part).ok_or_else(|| {
+                if parse_keyboard_usage(part).is_some() {
+                    ComboError::NotAModifier(part.to_string())
+                } else {
+                    unknown_key(part, MODIFIERS.iter().map(|(name, _)| name.to_string()))
+                }
+            })?;
+        }
+        if let Some(modifier) = parse_modifier(last) {
+            return Ok(Self::combo(modifiers | modifier, 0));
+        }
+        if let Some(usage) = parse_keyboard_usage(last) {
+            return Ok(Self::combo(modifiers, usage));
+        }
+        if is_namespaced(last) {
+            return Err(ComboError::NotCombinable(s.trim().to_string()));
+        }
+        // Anything the key could have been, e.g. `Media.Mute` for `Medai.Mute`.
+        let mut names: Vec<String> = MODIFIERS.iter().map(|(name, _)| name.to_string()).collect();
+        names.extend(named_keyboard_usages().map(|(_, name)| name));
+        if held.is_empty() {
+            names.extend(prefixed("Media.", CONSUMER_NAMES));
+            names.extend(prefixed("System.", SYSTEM_NAMES));
+            names.extend(MOUSE_NAMES.iter().map(|(name, _)| format!("Mouse.{name}")));
+        }
+        Err(unknown_key(last, names))
+    }
+}
+
+/// Whether `part` names a media, system, mouse or macro binding, which can't have modifiers.
+fn is_namespaced(part: &str) -> bool {
+    ["Media.", "System.", "Mouse.", "Macro."]
+        .iter()
+        .any(|prefix| strip_prefix(part, prefix).is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::falcon8::protocol::usage;
+
+    fn binding() -> impl Strategy<Value = KeyBinding> {
+        prop_oneof![
+            Just(KeyBinding::Disabled),
+            (any::<u8>(), any::<u8>())
+                .prop_map(|(m, usage)| KeyBinding::combo(Modifiers(m), usage)),
+            any::<u16>().prop_map(|usage| KeyBinding::Consumer { usage }),
+            any::<u8>().prop_map(|usage| KeyBinding::System { usage }),
+            any::<u8>().prop_map(|b| KeyBinding::Mouse {
+                buttons: MouseButtons(b)
+            }),
+            (0..MACRO_SLOT_COUNT as u8).prop_map(|slot| KeyBinding::Macro { slot }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn combo_round_trip(binding in binding()) {
+            let text = binding.to_string();
+            prop_assert_eq!(text.parse::<KeyBinding>(), Ok(binding));
+            prop_assert_eq!(text.to_lowercase().parse::<KeyBinding>(), Ok(binding));
+            prop_assert_eq!(text.to_uppercase().parse::<KeyBinding>(), Ok(binding));
+        }
+    }
+
+    fn unknown(name: &str, suggestions: &[&str]) -> ComboError {
+        ComboError::UnknownKey {
+            name: name.into(),
+            suggestions: suggestions.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        for (input, binding) in [
+            ("None", KeyBinding::Disabled),
+            ("A", KeyBinding::key(usage::keyboard::A)),
+            ("F13", KeyBinding::key(usage::keyboard::F13)),
+            (
+                "Ctrl+Shift+F13",
+                KeyBinding::combo(
+                    Modifiers::LEFT_CTRL | Modifiers::LEFT_SHIFT,
+                    usage::keyboard::F13,
+                ),
+            ),
+            (" Super + 1 ", KeyBinding::combo(Modifiers::LEFT_GUI, 0x1E)),
+            ("RAlt", KeyBinding::combo(Modifiers::RIGHT_ALT, 0)),
+            ("Ctrl+0x87", KeyBinding::combo(Modifiers::LEFT_CTRL, 0x87)),
+            (
+                "Media.VolumeUp",
+                KeyBinding::Consumer {
+                    usage: usage::consumer::VOLUME_UP,
+                },
+            ),
+            ("Media.0x01B8", KeyBinding::Consumer { usage: 0x1B8 }),
+            (
+                "System.Sleep",
+                KeyBinding::System {
+                    usage: usage::system::SLEEP,
+                },
+            ),
+            (
+                "Mouse.Left+Mouse.Back",
+                KeyBinding::Mouse {
+                    buttons: MouseButtons::LEFT | MouseButtons::BACK,
+                },
+            ),
+            ("Macro.15", KeyBinding::Macro { slot: 15 }),
+            (
+                "ctrl+shift+f13",
+                KeyBinding::combo(
+                    Modifiers::LEFT_CTRL | Modifiers::LEFT_SHIFT,
+                    usage::keyboard::F13,
+                ),
+            ),
+            ("Cmd+Q", KeyBinding::combo(Modifiers::LEFT_GUI, 0x14)),
+            ("win+e", KeyBinding::combo(Modifiers::LEFT_GUI, 0x08)),
+            (
+                "Option+AltGr",
+                KeyBinding::combo(Modifiers::LEFT_ALT | Modifiers::RIGHT_ALT, 0),
+            ),
+            ("Control+Esc", KeyBinding::combo(Modifiers::LEFT_CTRL, 0x29)),
+            ("PgDn", KeyBinding::key(0x4E)),
+            ("none", KeyBinding::Disabled),
+            ("0X87", KeyBinding::key(0x87)),
+            (
+                "media.volumeup",
+                KeyBinding::Consumer {
+                    usage: usage::consumer::VOLUME_UP,
+                },
+            ),
+            ("Media.PreviousTrack", KeyBinding::Consumer { usage: 0xB6 }),
+            (
+                "MOUSE.LEFT",
+                KeyBinding::Mouse {
+                    buttons: MouseButtons::LEFT,
+                },
+            ),
+            ("macro.3", KeyBinding::Macro { slot: 3 }),
+        ] {
+            assert_eq!(input.parse::<KeyBinding>(), Ok(binding), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        for input in [
+            "None",
+            "Ctrl+Shift+F13",
+            "RCtrl+RSuper",
+            "Alt+Kp7",
+            "0x87",
+            "Media.PlayPause",
+            "Media.0x01B8",
+            "System.0x85",
+            "Mouse.Right+Mouse.Middle",
+            "Mouse.Forward+Mouse.0x20",
+            "Macro.3",
+        ] {
+            let binding: KeyBinding = input.parse().unwrap();
+            assert_eq!(binding.to_string(), input);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        for (input, error) in [
+            ("", ComboError::Empty),
+            ("Ctrl+", ComboError::Empty),
+            ("Hyper+A", unknown("Hyper", &["Super"])),
+            ("A+B", ComboError::NotAModifier("A".into())),
+            (
+                "Ctrl+Media.Mute",
+                ComboError::NotCombinable("Ctrl+Media.Mute".into()),
+            ),
+            (
+                "Media.Mute+A",
+                ComboError::NotCombinable("Media.Mute+A".into()),
+            ),
+            ("Mouse.Left+A", ComboError::NotCombinable("A".into())),
+            ("Media.Louder", unknown("Media.Louder", &[])),
+            ("Ctrl+F33", unknown("F33", &["F13", "F23", "F3"])),
+            ("Medai.Mute", unknown("Medai.Mute", &["Media.Mute"])),
+            ("Mouse.Lfet", unknown("Mouse.Lfet", &["Mouse.Left"])),
+            (
+                "Media.Volume",
+                unknown("Media.Volume", &["Media.VolumeUp", "Media.VolumeDown"]),
+            ),
+            ("Macro.16", ComboError::InvalidMacroSlot("16".into())),
+        ] {
+            assert_eq!(input.parse::<KeyBinding>(), Err(error), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_error_messages() {
+        for (input, message) in [
+            ("Hyper+A", r#"unknown key "Hyper", did you mean "Super"?"#),
+            (
+                "F33",
+                r#"unknown key "F33", did you mean "F13", "F23" or "F3"?"#,
+            ),
+            ("Xyzzy", r#"unknown key "Xyzzy""#),
+        ] {
+            let error = input.parse::<KeyBinding>().unwrap_err();
+            assert_eq!(error.to_string(), message, "{input}");
+        }
+        assert_eq!(
+            keyboard_usage("Bakcspace").unwrap_err().to_string(),
+            r#"unknown key "Bakcspace", did you mean "Backspace"?"#
+        );
+    }
+
+    #[test]
+    fn test_keyboard_usage_names() {
+        for usage in 0..=u8::MAX {
+            let name = keyboard_usage_name(usage);
+            assert_eq!(parse_keyboard_usage(&name), Some(usage), "{name}");
+        }
+        assert_eq!(keyboard_usage_name(0x1E), "1");
+        assert_eq!(keyboard_usage_name(0x45), "F12");
+        assert_eq!(keyboard_usage_name(0x73), "F24");
+        assert_eq!(keyboard_usage_name(0x00), "0x00");
+    }
+}
@@ -0,0 +1,428 @@
+//! Which keys type which characters, for the keyboard layouts the computer may be set to.
+//!
+//! The keypad and the virtual keyboard send key positions (HID usages), and the computer's
+//! layout decides what they type: the key that types `z` on a US layout types `y` on a German
+//! one. Typing text therefore depends on the layout, and some characters take two keystrokes,
+//! a dead key and then the letter, or can't be typed at all.
+//!
+//! Dead keys are written as combining marks in the tables below, e.g. `\u{302}` for a dead
+//! circumflex.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{usage, KeyBinding, Modifiers};
+
+/// Keys of each row from left to right, as on an ISO keyboard.
+const DIGIT_ROW: [u8; 13] = [
+    0x35, 0x1E, 0x1F, 0x20, 0x21, 0x22, 0x23, 0x24, 0x25, 0x26, 0x27, 0x2D, 0x2E,
+];
+const TOP_ROW: [u8; 13] = [
+    0x14, 0x1A, 0x08, 0x15, 0x17, 0x1C, 0x18, 0x0C, 0x12, 0x13, 0x2F, 0x30, 0x31,
+];
+const HOME_ROW: [u8; 12] = [
+    0x04, 0x16, 0x07, 0x09, 0x0A, 0x0B, 0x0D, 0x0E, 0x0F, 0x33, 0x34, 0x32,
+];
+const BOTTOM_ROW: [u8; 11] = [
+    0x64, 0x1D, 0x1B, 0x06, 0x19, 0x05, 0x11, 0x10, 0x36, 0x37, 0x38,
+];
+
+/// What a row's keys type at each shift level: plain, Shift, AltGr and Shift+AltGr. A space
+/// marks a key that types nothing at that level.
+type Row = [&'static str; 4];
+
+const US: [Row; 4] = [
+    ["`1234567890-=", "~!@#$%^&*()_+", "", ""],
+    ["qwertyuiop[]\\", "QWERTYUIOP{}|", "", ""],
+    ["asdfghjkl;'", "ASDFGHJKL:\"", "", ""],
+    [" zxcvbnm,./", " ZXCVBNM<>?", "", ""],
+];
+
+const UK: [Row; 4] = [
+    ["`1234567890-=", "¬!\"£$%^&*()_+", "¦   €", ""],
+    ["qwertyuiop[]", "QWERTYUIOP{}", "", ""],
+    ["asdfghjkl;'#", "ASDFGHJKL:@~", "", ""],
+    ["\\zxcvbnm,./", "|ZXCVBNM<>?", "", ""],
+];
+
+const DE: [Row; 4] = [
+    [
+        "\u{302}1234567890ß\u{301}",
+        "°!\"§$%&/()=?\u{300}",
+        "  ²³   {[]}\\",
+        "",
+    ],
+    ["qwertzuiopü+", "QWERTZUIOPÜ*", "@ €        ~", ""],
+    ["asdfghjklöä#", "ASDFGHJKLÖÄ'", "", ""],
+    ["<yxcvbnm,.-", ">YXCVBNM;:_", "|      µ", ""],
+];
+
+const FR: [Row; 4] = [
+    ["²&é\"'(-è_çà)=", " 1234567890°+", "  ~#{[|`\\^@]}", ""],
+    [
+        "azertyuiop\u{302}$",
+        "AZERTYUIOP\u{308}£",
+        "  €        ¤",
+        "",
+    ],
+    ["qsdfghjklmù*", "QSDFGHJKLM%µ", "", ""],
+    ["<wxcvbn,;:!", ">WXCVBN?./§", "", ""],
+];
+
+const DVORAK: [Row; 4] = [
+    ["`1234567890[]", "~!@#$%^&*(){}", "", ""],
+    ["',.pyfgcrl/=\\", "\"<>PYFGCRL?+|", "", ""],
+    ["aoeuidhtns-", "AOEUIDHTNS_", "", ""],
+    [" ;qjkxbmwvz", " :QJKXBMWVZ", "", ""],
+];
+
+/// Dead keys: the combining mark, the character it types followed by Space, and the letters it
+/// combines with and what they become.
+const DEAD_KEYS: [(char, char, &str, &str); 4] = [
+    ('\u{300}', '`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ('\u{301}', '´', "aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+    ('\u{302}', '^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ('\u{308}', '¨', "aeiouyAEIOU", "äëïöüÿÄËÏÖÜ"),
+];
+
+/// A keyboard layout the computer can be set to, named as in XKB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    #[default]
+    Us,
+    Uk,
+    De,
+    Fr,
+    Dvorak,
+}
+
+/// One key pressed with modifiers held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Keystroke {
+    pub modifiers: Modifiers,
+    pub usage: u8,
+}
+
+impl Keystroke {
+    pub const fn key(usage: u8) -> Self {
+        Self {
+            modifiers: Modifiers::NONE,
+            usage,
+        }
+    }
+}
+
+impl From<Keystroke> for KeyBinding {
+    fn from(keystroke: Keystroke) -> Self {
+        KeyBinding::combo(keystroke.modifiers, keystroke.usage)
+    }
+}
+
+impl fmt::Display for Keystroke {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        KeyBinding::from(*self).fmt(f)
+    }
+}
+
+/// Characters of a text that the layout has no keys for, each listed once.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("{} can't be typed on the {layout} keyboard layout", quoted(.chars))]
+pub struct UntypeableText {
+    pub layout: Layout,
+    pub chars: Vec<char>,
+}
+
+fn quoted(chars: &[char]) -> String {
+    let quoted: Vec<String> = chars.iter().map(|c| format!("{c:?}")).collect();
+    quoted.join(", ")
+}
+
+/// The modifiers that select shift level `level`.
+fn level_modifiers(level: usize) -> Modifiers {
+    let mut modifiers = Modifiers::NONE;
+    if level % 2 == 1 {
+        modifiers |= Modifiers::LEFT_SHIFT;
+    }
+    if level >= 2 {
+        // AltGr.
+        modifiers |= Modifiers::RIGHT_ALT;
+    }
+    modifiers
+}
+
+impl Layout {
+    pub const ALL: [Self; 5] = [Self::Us, Self::Uk, Self::De, Self::Fr, Self::Dvorak];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Us => "us",
+            Self::Uk => "uk",
+            Self::De => "de",
+            Self::Fr => "fr",
+            Self::Dvorak => "dvorak",
+        }
+    }
+
+    fn rows(self) -> &'static [Row; 4] {
+        match self {
+            Self::Us => &US,
+            Self::Uk => &UK,
+            Self::De => &DE,
+            Self::Fr => &FR,
+            Self::Dvorak => &DVORAK,
+        }
+    }
+
+    /// The key that types `c` by itself, or that is the dead key `c`.
+    fn key(self, c: char) -> Option<Keystroke> {
+        if c == ' ' {
+            return None;
+        }
+        let usages: [&[u8]; 4] = [&DIGIT_ROW, &TOP_ROW, &HOME_ROW, &BOTTOM_ROW];
+        usages
+            .into_iter()
+            .zip(self.rows())
+            .find_map(|(usages, levels)| {
+                levels.iter().enumerate().find_map(|(level, chars)| {
+                    let position = chars.chars().position(|k| k == c)?;
+                    Some(Keystroke {
+                        modifiers: level_modifiers(level),
+                        usage: usages[position],
+                    })
+                })
+            })
+    }
+
+    /// The keystrokes that type `c`, if the layout can.
+    pub fn char_keystrokes(self, c: char) -> Option<Vec<Keystroke>> {
+        let direct = match c {
+            '\n' => Some(Keystroke::key(usage::keyboard::ENTER)),
+            '\t' => Some(Keystroke::key(usage::keyboard::TAB)),
+            ' ' => Some(Keystroke::key(usage::keyboard::SPACE)),
+            _ => self.key(c),
+        };
+        if let Some(keystroke) = direct {
+            return Some(vec![keystroke]);
+        }
+
+        DEAD_KEYS
+            .iter()
+            .find_map(|&(mark, spacing, letters, combined)| {
+                let second = if c == spacing {
+                    ' '
+                } else {
+                    let index = combined.chars().position(|k| k == c)?;
+                    letters.chars().nth(index)?
+                };
+                Some(vec![self.key(mark)?, self.char_keystrokes(second)?[0]])
+            })
+    }
+
+    /// The keystrokes that type `text`, or every character in it the layout can't type.
+    pub fn keystrokes(self, text: &str) -> Result<Vec<Keystroke>, UntypeableText> {
+        let mut keystrokes = Vec::new();
+        let mut untypeable = Vec::new();
+        for c in text.chars() {
+            match self.char_keystrokes(c) {
+                Some(strokes) => keystrokes.extend(strokes),
+                None if !untypeable.contains(&c) => untypeable.push(c),
+                None => {}
+            }
+        }
+        if untypeable.is_empty() {
+            Ok(keystrokes)
+        } else {
+            Err(UntypeableText {
+                layout: self,
+                chars: untypeable,
+            })
+        }
+    }
+
+    /// Types `c` by its code point: Ctrl+Shift+U, the code point in hex, then Space. GTK and
+    /// IBus applications on Linux understand this for any character.
+    pub fn unicode_input(self, c: char) -> Vec<Keystroke> {
+        let typed = |c| {
+            self.char_keystrokes(c)
+                .expect("all layouts type 0-9, a-f and u")
+        };
+        let mut start = typed('u')[0];
+        start.modifiers |= Modifiers::LEFT_CTRL | Modifiers::LEFT_SHIFT;
+
+        let mut keystrokes = vec![start];
+        for digit in format!("{:x}", u32::from(c)).chars() {
+            keystrokes.extend(typed(digit));
+        }
+        keystrokes.push(Keystroke::key(usage::keyboard::SPACE));
+        keystrokes
+    }
+
+    /// Like `keystrokes`, but types what the layout can't with `unicode_input`.
+    pub fn keystrokes_or_unicode(self, text: &str) -> Vec<Keystroke> {
+        text.chars()
+            .flat_map(|c| {
+                self.char_keystrokes(c)
+                    .unwrap_or_else(|| self.unicode_input(c))
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|layout| layout.name() == s)
+            .ok_or_else(|| {
+                format!("unknown keyboard layout {s:?}, expected us, uk, de, fr or dvorak")
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHIFT: Modifiers = Modifiers::LEFT_SHIFT;
+    const ALTGR: Modifiers = Modifiers::RIGHT_ALT;
+
+    fn stroke(modifiers: Modifiers, usage: u8) -> Keystroke {
+        Keystroke { modifiers, usage }
+    }
+
+    fn typed(layout: Layout, text: &str) -> Vec<String> {
+        let keystrokes = layout.keystrokes(text).unwrap();
+        keystrokes.iter().map(Keystroke::to_string).collect()
+    }
+
+    #[test]
+    fn test_us() {
+        assert_eq!(
+            typed(Layout::Us, "Hi, you!\n"),
+            ["Shift+H", "I", "Comma", "Space", "Y", "O", "U", "Shift+1", "Enter"]
+        );
+        for c in ' '..='~' {
+            assert!(Layout::Us.char_keystrokes(c).is_some(), "{c:?}");
+        }
+    }
+
+    #[test]
+    fn test_de() {
+        assert_eq!(typed(Layout::De, "zy@€"), ["Y", "Z", "RAlt+Q", "RAlt+E"]);
+        assert_eq!(
+            Layout::De.char_keystrokes('ß'),
+            Some(vec![Keystroke::key(0x2D)])
+        );
+        assert_eq!(
+            Layout::De.char_keystrokes('Ü'),
+            Some(vec![stroke(SHIFT, 0x2F)])
+        );
+        // Dead keys, combined and on their own.
+        assert_eq!(
+            Layout::De.char_keystrokes('ê'),
+            Some(vec![Keystroke::key(0x35), Keystroke::key(0x08)])
+        );
+        assert_eq!(
+            Layout::De.char_keystrokes('À'),
+            Some(vec![stroke(SHIFT, 0x2E), stroke(SHIFT, 0x04)])
+        );
+        assert_eq!(
+            Layout::De.char_keystrokes('^'),
+            Some(vec![
+                Keystroke::key(0x35),
+                Keystroke::key(usage::keyboard::SPACE)
+            ])
+        );
+        assert_eq!(Layout::De.char_keystrokes('ë'), None);
+    }
+
+    #[test]
+    fn test_fr() {
+        assert_eq!(
+            typed(Layout::Fr, "aqzwm"),
+            ["Q", "A", "W", "Z", "Semicolon"]
+        );
+        assert_eq!(typed(Layout::Fr, "1é"), ["Shift+1", "2"]);
+        assert_eq!(
+            Layout::Fr.char_keystrokes('ë'),
+            Some(vec![stroke(SHIFT, 0x2F), Keystroke::key(0x08)])
+        );
+        // AltGr+9 rather than the dead key.
+        assert_eq!(
+            Layout::Fr.char_keystrokes('^'),
+            Some(vec![stroke(ALTGR, 0x26)])
+        );
+    }
+
+    #[test]
+    fn test_uk_and_dvorak() {
+        assert_eq!(
+            typed(Layout::Uk, "\"@#£"),
+            ["Shift+2", "Shift+Quote", "NonUsHash", "Shift+3"]
+        );
+        assert_eq!(
+            typed(Layout::Dvorak, "aoeu;q"),
+            ["A", "S", "D", "F", "Z", "X"]
+        );
+    }
+
+    #[test]
+    fn test_untypeable() {
+        assert_eq!(
+            Layout::Us.keystrokes("Grüße, café ✓"),
+            Err(UntypeableText {
+                layout: Layout::Us,
+                chars: vec!['ü', 'ß', 'é', '✓'],
+            })
+        );
+        assert_eq!(
+            Layout::De.keystrokes("ö✓ö✓").unwrap_err().to_string(),
+            "'✓' can't be typed on the de keyboard layout"
+        );
+    }
+
+    #[test]
+    fn test_unicode_input() {
+        let keystrokes: Vec<String> = Layout::Fr
+            .unicode_input('✓')
+            .iter()
+            .map(Keystroke::to_string)
+            .collect();
+        assert_eq!(
+            keystrokes,
+            [
+                "Ctrl+Shift+U",
+                "Shift+2",
+                "Shift+7",
+                "Shift+1",
+                "Shift+3",
+                "Space"
+            ]
+        );
+
+        let fallback = Layout::Us.keystrokes_or_unicode("aé");
+        assert_eq!(fallback[0], Keystroke::key(usage::keyboard::A));
+        assert_eq!(fallback[1..], Layout::Us.unicode_input('é'));
+    }
+
+    #[test]
+    fn test_every_layout_types_ascii_letters_and_digits() {
+        for layout in Layout::ALL {
+            for c in ('a'..='z').chain('A'..='Z').chain('0'..='9') {
+                assert!(layout.char_keystrokes(c).is_some(), "{layout} {c:?}");
+            }
+            assert_eq!(layout.to_string().parse(), Ok(layout));
+        }
+        assert!("azerty".parse::<Layout>().is_err());
+    }
+}
@@ -0,0 +1,247 @@
+//! Macros as stored in the keypad's macro slots.
+//!
+//! ```text
+//! +0  repeat count (at least 1)
+//! +1  length of the event stream in bytes, 0 for an empty slot
+//! +2  events:
+//!       0x01 usage      key down (keyboard usage page)
+//!       0x02 usage      key up
+//!       0x03 ms (u16 LE) delay
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    DecodeError, FeatureReport, Keystroke, Layout, MemoryAccessReport, UntypeableText,
+    MACRO_SLOT_SIZE,
+};
+
+pub const MACRO_HEADER_SIZE: usize = 2;
+/// Bytes of events a single macro slot holds.
+pub const MACRO_CAPACITY: usize = MACRO_SLOT_SIZE - MACRO_HEADER_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MacroEvent {
+    KeyDown { usage: u8 },
+    KeyUp { usage: u8 },
+    Delay { ms: u16 },
+}
+
+impl MacroEvent {
+    const KEY_DOWN: u8 = 0x01;
+    const KEY_UP: u8 = 0x02;
+    const DELAY: u8 = 0x03;
+
+    pub const fn encoded_len(&self) -> usize {
+        match self {
+            Self::KeyDown { .. } | Self::KeyUp { .. } => 2,
+            Self::Delay { .. } => 3,
+        }
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match *self {
+            Self::KeyDown { usage } => out.extend([Self::KEY_DOWN, usage]),
+            Self::KeyUp { usage } => out.extend([Self::KEY_UP, usage]),
+            Self::Delay { ms } => {
+                out.push(Self::DELAY);
+                out.extend(ms.to_le_bytes());
+            }
+        }
+    }
+}
+
+/// A sequence of key presses and pauses the keypad plays back by itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Macro {
+    /// How many times the events play per key press.
+    pub repeat: u8,
+    pub events: Vec<MacroEvent>,
+}
+
+impl Default for Macro {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Macro {
+    pub fn new() -> Self {
+        Self {
+            repeat: 1,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn repeat(mut self, repeat: u8) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    pub fn key_down(mut self, usage: u8) -> Self {
+        self.events.push(MacroEvent::KeyDown { usage });
+        self
+    }
+
+    pub fn key_up(mut self, usage: u8) -> Self {
+        self.events.push(MacroEvent::KeyUp { usage });
+        self
+    }
+
+    /// Presses and releases `usage`.
+    pub fn tap(self, usage: u8) -> Self {
+        self.key_down(usage).key_up(usage)
+    }
+
+    /// Taps each of `keystrokes`, pressing its modifiers around it.
+    pub fn type_keystrokes(mut self, keystrokes: &[Keystroke]) -> Self {
+        for keystroke in keystrokes {
+            for modifier in keystroke.modifiers.usages() {
+                self = self.key_down(modifier);
+            }
+            self = self.tap(keystroke.usage);
+            for modifier in keystroke.modifiers.usages().rev() {
+                self = self.key_up(modifier);
+            }
+        }
+        self
+    }
+
+    /// Types `text` on a computer set to `layout`.
+    pub fn type_text(self, text: &str, layout: Layout) -> Result<Self, UntypeableText> {
+        Ok(self.type_keystrokes(&layout.keystrokes(text)?))
+    }
+
+    pub fn delay(mut self, ms: u16) -> Self {
+        self.events.push(MacroEvent::Delay { ms });
+        self
+    }
+
+    /// Size of the event stream, which has to fit in `MACRO_CAPACITY`.
+    pub fn encoded_len(&self) -> usize {
+        self.events.iter().map(MacroEvent::encoded_len).sum()
+    }
+
+    /// Header and event stream, ready to be written to the start of a macro slot. The stream
+    /// length is truncated if the macro doesn't fit, so check `encoded_len` first.
+    pub fn encode(&self) -> Vec<u8> {
+        let len = self.encoded_len();
+        let mut out = Vec::with_capacity(MACRO_HEADER_SIZE + len);
+        out.extend([self.repeat, len.min(MACRO_CAPACITY) as u8]);
+        for event in &self.events {
+            event.encode_into(&mut out);
+        }
+        out
+    }
+
+    /// Decodes an event stream read from a slot whose header gave `repeat`.
+    pub fn decode(repeat: u8, mut stream: &[u8]) -> Result<Self, DecodeError> {
+        let invalid = |field, value| DecodeError::InvalidField {
+            report_id: MemoryAccessReport::ID,
+            field,
+            value,
+        };
+        if repeat == 0 {
+            return Err(invalid("macro repeat count", repeat));
+        }
+
+        let mut events = Vec::new();
+        while let Some(&kind) = stream.first() {
+            let len = match kind {
+                MacroEvent::KEY_DOWN | MacroEvent::KEY_UP => 2,
+                MacroEvent::DELAY => 3,
+                value => return Err(invalid("macro event", value)),
+            };
+            if stream.len() < len {
+                return Err(DecodeError::Length {
+                    report_id: MemoryAccessReport::ID,
+                    expected: len,
+                    actual: stream.len(),
+                });
+            }
+
+            events.push(match kind {
+                MacroEvent::KEY_DOWN => MacroEvent::KeyDown { usage: stream[1] },
+                MacroEvent::KEY_UP => MacroEvent::KeyUp { usage: stream[1] },
+                _ => MacroEvent::Delay {
+                    ms: u16::from_le_bytes([stream[1], stream[2]]),
+                },
+            });
+            stream = &stream[len..];
+        }
+
+        Ok(Self { repeat, events })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::falcon8::protocol::usage::keyboard;
+
+    fn event() -> impl Strategy<Value = MacroEvent> {
+        prop_oneof![
+            any::<u8>().prop_map(|usage| MacroEvent::KeyDown { usage }),
+            any::<u8>().prop_map(|usage| MacroEvent::KeyUp { usage }),
+            any::<u16>().prop_map(|ms| MacroEvent::Delay { ms }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn macro_round_trip(repeat in 1..=u8::MAX, events in prop::collection::vec(event(), 0..80)) {
+            let m = Macro { repeat, events };
+            let encoded = m.encode();
+            prop_assert_eq!(encoded.len(), MACRO_HEADER_SIZE + m.encoded_len());
+            prop_assert_eq!(Macro::decode(encoded[0], &encoded[MACRO_HEADER_SIZE..]), Ok(m));
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let m = Macro::new()
+            .repeat(2)
+            .key_down(keyboard::RIGHT_CTRL)
+            .tap(keyboard::A)
+            .delay(300)
+            .key_up(keyboard::RIGHT_CTRL);
+        assert_eq!(
+            m.encode(),
+            [2, 11, 0x01, 0xE4, 0x01, 0x04, 0x02, 0x04, 0x03, 0x2C, 0x01, 0x02, 0xE4]
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert!(matches!(
+            Macro::decode(1, &[0x01, 0x04, 0x09]),
+            Err(DecodeError::InvalidField { value: 0x09, .. })
+        ));
+        assert!(matches!(
+            Macro::decode(1, &[0x03, 0x10]),
+            Err(DecodeError::Length {
+                expected: 3,
+                actual: 2,
+                ..
+            })
+        ));
+        assert!(Macro::decode(0, &[]).is_err());
+    }
+
+    #[test]
+    fn test_type_text() {
+        let m = Macro::new().type_text("Hé", Layout::Fr).unwrap();
+        assert_eq!(
+            m,
+            Macro::new()
+                .key_down(keyboard::LEFT_SHIFT)
+                .tap(0x0B)
+                .key_up(keyboard::LEFT_SHIFT)
+                .tap(0x1F)
+        );
+        assert!(Macro::new().type_text("Hé", Layout::Us).is_err());
+    }
+}
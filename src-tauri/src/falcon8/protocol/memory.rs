@@ -0,0 +1,41 @@
+//! Layout of the keypad's configuration memory, as seen through `MemoryAccess` reports.
+//!
+//! ```text
+//! 0x0000  profile 0..PROFILE_COUNT, PROFILE_SIZE bytes each
+//!         +0x00  key map      (KEY_COUNT x BINDING_SIZE)
+//!         +0x20  lighting     (mode, speed, direction, brightness, 2 reserved)
+//!         +0x26  LED colours  (KEY_COUNT x RGB)
+//! 0x0100  macro slots 0..MACRO_SLOT_COUNT, MACRO_SLOT_SIZE bytes each
+//! 0x1100  end
+//! ```
+
+use super::KEY_COUNT;
+
+pub const PROFILE_COUNT: usize = 4;
+pub const PROFILE_SIZE: usize = 0x40;
+pub const PROFILES_BASE: usize = 0x0000;
+
+pub const BINDING_SIZE: usize = 4;
+pub const KEY_MAP_OFFSET: usize = 0x00;
+pub const KEY_MAP_SIZE: usize = KEY_COUNT * BINDING_SIZE;
+pub const LIGHTING_OFFSET: usize = 0x20;
+pub const LIGHTING_SIZE: usize = 6;
+pub const LED_COLORS_OFFSET: usize = 0x26;
+pub const LED_COLORS_SIZE: usize = KEY_COUNT * 3;
+
+pub const MACRO_SLOT_COUNT: usize = 16;
+pub const MACRO_SLOT_SIZE: usize = 0x100;
+pub const MACROS_BASE: usize = 0x0100;
+
+pub const MEMORY_SIZE: usize = MACROS_BASE + MACRO_SLOT_COUNT * MACRO_SLOT_SIZE;
+
+/// Bytes transferred by a single `MemoryAccess` report.
+pub const PAGE_SIZE: usize = 32;
+
+pub const fn profile_address(slot: usize) -> usize {
+    PROFILES_BASE + slot * PROFILE_SIZE
+}
+
+pub const fn macro_address(slot: usize) -> usize {
+    MACROS_BASE + slot * MACRO_SLOT_SIZE
+}
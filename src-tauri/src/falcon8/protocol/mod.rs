@@ -0,0 +1,145 @@
+//! Wire format of the Falcon-8's vendor interface.
+//!
+//! All configuration goes through HID feature reports on `CONFIG_INTERFACE`. Every report is a
+//! fixed-length frame: the report ID, the payload, and a trailing checksum byte holding the
+//! wrapping sum of everything before it. Key presses come back the other way as input reports,
+//! framed the same way, on the interface's interrupt endpoint.
+
+mod binding;
+mod combo;
+mod layout;
+mod macros;
+mod memory;
+mod reports;
+
+pub use binding::{usage, KeyBinding, Modifiers, MouseButtons};
+pub use combo::{keyboard_usage, keyboard_usage_name, parse_keyboard_usage, ComboError};
+pub use layout::{Keystroke, Layout, UntypeableText};
+pub use macros::{Macro, MacroEvent, MACRO_CAPACITY, MACRO_HEADER_SIZE};
+pub use memory::*;
+pub use reports::*;
+
+pub const HID_GET_REPORT: u8 = 0x01;
+pub const HID_SET_REPORT: u8 = 0x09;
+
+pub const HID_REPORT_TYPE_INPUT: u8 = 0x01;
+pub const HID_REPORT_TYPE_FEATURE: u8 = 0x03;
+
+pub const KEY_COUNT: usize = 8;
+
+/// `FirmwareInfoReport::protocol_version` this driver speaks.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum DecodeError {
+    #[error("report {report_id:#04x} should be {expected} bytes long, got {actual}")]
+    Length {
+        report_id: u8,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("expected report {expected:#04x}, got {actual:#04x}")]
+    ReportId { expected: u8, actual: u8 },
+    #[error("report {report_id:#04x} checksum is {actual:#04x}, expected {expected:#04x}")]
+    Checksum {
+        report_id: u8,
+        expected: u8,
+        actual: u8,
+    },
+    #[error("report {report_id:#04x} has an invalid {field}: {value:#04x}")]
+    InvalidField {
+        report_id: u8,
+        field: &'static str,
+        value: u8,
+    },
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &b| sum.wrapping_add(b))
+}
+
+/// The `wValue` of a GET_REPORT/SET_REPORT request for a feature report.
+pub const fn feature_report_value(report_id: u8) -> u16 {
+    (HID_REPORT_TYPE_FEATURE as u16) << 8 | report_id as u16
+}
+
+/// Wire length of the feature report with `report_id`, or `None` if the keypad doesn't have one.
+pub fn feature_report_len(report_id: u8) -> Option<usize> {
+    match report_id {
+        FirmwareInfoReport::ID => Some(FirmwareInfoReport::LEN),
+        ProfileStateReport::ID => Some(ProfileStateReport::LEN),
+        LightingReport::ID => Some(LightingReport::LEN),
+        LedColorsReport::ID => Some(LedColorsReport::LEN),
+        MemoryAccessReport::ID => Some(MemoryAccessReport::LEN),
+        KeyMapReport::ID => Some(KeyMapReport::LEN),
+        _ => None,
+    }
+}
+
+/// A fixed-length feature report with a trailing checksum. `KeyStateReport`, an input report,
+/// shares the framing.
+///
+/// Implementors only deal with the payload; `encode` and `decode` handle the framing.
+pub trait FeatureReport: Sized {
+    const ID: u8;
+    /// Total length on the wire, including the report ID and checksum.
+    const LEN: usize;
+
+    fn write_payload(&self, payload: &mut [u8]);
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError>;
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = vec![0; Self::LEN];
+        data[0] = Self::ID;
+        self.write_payload(&mut data[1..Self::LEN - 1]);
+        data[Self::LEN - 1] = checksum(&data[..Self::LEN - 1]);
+        data
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+        if data.len() != Self::LEN {
+            return Err(DecodeError::Length {
+                report_id: Self::ID,
+                expected: Self::LEN,
+                actual: data.len(),
+            });
+        }
+        if data[0] != Self::ID {
+            return Err(DecodeError::ReportId {
+                expected: Self::ID,
+                actual: data[0],
+            });
+        }
+
+        let (body, sum) = data.split_at(Self::LEN - 1);
+        let expected = checksum(body);
+        if sum[0] != expected {
+            return Err(DecodeError::Checksum {
+                report_id: Self::ID,
+                expected,
+                actual: sum[0],
+            });
+        }
+
+        Self::read_payload(&body[1..])
+    }
+}
+
+/// Fails with `DecodeError::InvalidField` unless `value < limit`.
+pub(crate) fn check_range(
+    report_id: u8,
+    field: &'static str,
+    value: u8,
+    limit: usize,
+) -> Result<u8, DecodeError> {
+    if usize::from(value) < limit {
+        Ok(value)
+    } else {
+        Err(DecodeError::InvalidField {
+            report_id,
+            field,
+            value,
+        })
+    }
+}
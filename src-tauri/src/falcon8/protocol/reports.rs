@@ -0,0 +1,593 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    check_range, DecodeError, FeatureReport, KeyBinding, BINDING_SIZE, KEY_COUNT, PAGE_SIZE,
+    PROFILE_COUNT,
+};
+
+/// Firmware version, read-only.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirmwareInfoReport {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+    pub build: u16,
+    pub protocol_version: u8,
+}
+
+impl FeatureReport for FirmwareInfoReport {
+    const ID: u8 = 0x01;
+    const LEN: usize = 8;
+
+    fn write_payload(&self, payload: &mut [u8]) {
+        payload[0] = self.major;
+        payload[1] = self.minor;
+        payload[2] = self.patch;
+        payload[3..5].copy_from_slice(&self.build.to_le_bytes());
+        payload[5] = self.protocol_version;
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            major: payload[0],
+            minor: payload[1],
+            patch: payload[2],
+            build: u16::from_le_bytes([payload[3], payload[4]]),
+            protocol_version: payload[5],
+        })
+    }
+}
+
+/// Which profile the keypad is running and which one `KeyMapReport`, `LightingReport` and
+/// `LedColorsReport` reads return.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileStateReport {
+    pub active_slot: u8,
+    pub edit_slot: u8,
+    pub slot_count: u8,
+}
+
+impl FeatureReport for ProfileStateReport {
+    const ID: u8 = 0x02;
+    const LEN: usize = 6;
+
+    fn write_payload(&self, payload: &mut [u8]) {
+        payload[0] = self.active_slot;
+        payload[1] = self.edit_slot;
+        payload[2] = self.slot_count;
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            active_slot: check_range(Self::ID, "active slot", payload[0], PROFILE_COUNT)?,
+            edit_slot: check_range(Self::ID, "edit slot", payload[1], PROFILE_COUNT)?,
+            slot_count: check_range(Self::ID, "slot count", payload[2], PROFILE_COUNT + 1)?,
+        })
+    }
+}
+
+/// A key's colour as red, green, blue.
+pub type Rgb = [u8; 3];
+
+/// Built-in effects the keypad animates by itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LightingMode {
+    Off = 0x00,
+    /// Each key shows its own colour from `LedColorsReport`.
+    #[default]
+    Static = 0x01,
+    /// The static colours fade in and out.
+    Breathing = 0x02,
+    /// A rainbow sweeping across the keys.
+    Wave = 0x03,
+    /// Keys light up when pressed and fade out.
+    Reactive = 0x04,
+}
+
+impl TryFrom<u8> for LightingMode {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            0x00 => Ok(Self::Off),
+            0x01 => Ok(Self::Static),
+            0x02 => Ok(Self::Breathing),
+            0x03 => Ok(Self::Wave),
+            0x04 => Ok(Self::Reactive),
+            value => Err(value),
+        }
+    }
+}
+
+impl fmt::Display for LightingMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Off => "off",
+            Self::Static => "static",
+            Self::Breathing => "breathing",
+            Self::Wave => "wave",
+            Self::Reactive => "reactive",
+        })
+    }
+}
+
+impl FromStr for LightingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "off" => Ok(Self::Off),
+            "static" => Ok(Self::Static),
+            "breathing" => Ok(Self::Breathing),
+            "wave" => Ok(Self::Wave),
+            "reactive" => Ok(Self::Reactive),
+            _ => Err(format!(
+                "unknown lighting mode {s:?}, expected off, static, breathing, wave or reactive"
+            )),
+        }
+    }
+}
+
+/// Which way `Wave` and `Reactive` travel across the keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    #[default]
+    LeftToRight = 0x00,
+    RightToLeft = 0x01,
+}
+
+impl TryFrom<u8> for Direction {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, u8> {
+        match value {
+            0x00 => Ok(Self::LeftToRight),
+            0x01 => Ok(Self::RightToLeft),
+            value => Err(value),
+        }
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::LeftToRight => "left_to_right",
+            Self::RightToLeft => "right_to_left",
+        })
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "left_to_right" => Ok(Self::LeftToRight),
+            "right_to_left" => Ok(Self::RightToLeft),
+            _ => Err(format!(
+                "unknown direction {s:?}, expected left_to_right or right_to_left"
+            )),
+        }
+    }
+}
+
+/// Built-in lighting effect of a profile. `speed` runs from 0 (slowest) to 255, `brightness`
+/// scales every colour from 0 (dark) to 255.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightingReport {
+    pub slot: u8,
+    pub mode: LightingMode,
+    pub speed: u8,
+    pub direction: Direction,
+    pub brightness: u8,
+}
+
+impl FeatureReport for LightingReport {
+    const ID: u8 = 0x03;
+    const LEN: usize = 8;
+
+    fn write_payload(&self, payload: &mut [u8]) {
+        payload[0] = self.slot;
+        payload[1] = self.mode as u8;
+        payload[2] = self.speed;
+        payload[3] = self.direction as u8;
+        payload[4] = self.brightness;
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            slot: check_range(Self::ID, "slot", payload[0], PROFILE_COUNT)?,
+            mode: payload[1]
+                .try_into()
+                .map_err(|value| DecodeError::InvalidField {
+                    report_id: Self::ID,
+                    field: "lighting mode",
+                    value,
+                })?,
+            speed: payload[2],
+            direction: payload[3]
+                .try_into()
+                .map_err(|value| DecodeError::InvalidField {
+                    report_id: Self::ID,
+                    field: "direction",
+                    value,
+                })?,
+            brightness: payload[4],
+        })
+    }
+}
+
+/// `LedColorsReport::flags` bit: show the colours right away without saving them to flash. The
+/// saved colours come back on the next profile switch or power cycle. For streaming animations,
+/// which would wear out the flash otherwise.
+pub const LED_FLAG_VOLATILE: u8 = 0x01;
+
+/// Static per-key colours of a profile. Only keys whose bit is set in `mask` are applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedColorsReport {
+    pub slot: u8,
+    pub flags: u8,
+    pub mask: u8,
+    pub colors: [Rgb; KEY_COUNT],
+}
+
+impl FeatureReport for LedColorsReport {
+    const ID: u8 = 0x05;
+    const LEN: usize = 29;
+
+    fn write_payload(&self, payload: &mut [u8]) {
+        payload[0] = self.slot;
+        payload[1] = self.flags;
+        payload[2] = self.mask;
+        for (chunk, color) in payload[3..].chunks_exact_mut(3).zip(&self.colors) {
+            chunk.copy_from_slice(color);
+        }
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError> {
+        let mut colors = [[0; 3]; KEY_COUNT];
+        for (color, chunk) in colors.iter_mut().zip(payload[3..].chunks_exact(3)) {
+            color.copy_from_slice(chunk);
+        }
+
+        Ok(Self {
+            slot: check_range(Self::ID, "slot", payload[0], PROFILE_COUNT)?,
+            flags: payload[1],
+            mask: payload[2],
+            colors,
+        })
+    }
+}
+
+/// Which keys are held down. An input report rather than a feature report: the keypad sends it
+/// on the interrupt endpoint of `CONFIG_INTERFACE` whenever a key goes down or up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyStateReport {
+    /// Bit `n` is set while key `n` is held.
+    pub pressed: u8,
+}
+
+impl FeatureReport for KeyStateReport {
+    const ID: u8 = 0x04;
+    const LEN: usize = 3;
+
+    fn write_payload(&self, payload: &mut [u8]) {
+        payload[0] = self.pressed;
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError> {
+        Ok(Self {
+            pressed: payload[0],
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MemoryOp {
+    /// Point the next GET of `MemoryAccessReport` at `address`.
+    Read = 0x01,
+    Write = 0x02,
+}
+
+/// Paged access to the configuration memory described in `protocol::memory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryAccessReport {
+    pub op: MemoryOp,
+    pub address: u16,
+    pub len: u8,
+    pub data: [u8; PAGE_SIZE],
+}
+
+impl MemoryAccessReport {
+    pub fn read(address: u16, len: u8) -> Self {
+        Self {
+            op: MemoryOp::Read,
+            address,
+            len,
+            data: [0; PAGE_SIZE],
+        }
+    }
+
+    /// Panics if `data` is longer than `PAGE_SIZE`.
+    pub fn write(address: u16, data: &[u8]) -> Self {
+        let mut page = [0; PAGE_SIZE];
+        page[..data.len()].copy_from_slice(data);
+        Self {
+            op: MemoryOp::Write,
+            address,
+            len: data.len() as u8,
+            data: page,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data[..usize::from(self.len)]
+    }
+}
+
+impl FeatureReport for MemoryAccessReport {
+    const ID: u8 = 0x06;
+    const LEN: usize = 38;
+
+    fn write_payload(&self, payload: &mut [u8]) {
+        payload[0] = self.op as u8;
+        payload[1..3].copy_from_slice(&self.address.to_le_bytes());
+        payload[3] = self.len;
+        payload[4..].copy_from_slice(&self.data);
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError> {
+        let op = match payload[0] {
+            0x01 => MemoryOp::Read,
+            0x02 => MemoryOp::Write,
+            value => {
+                return Err(DecodeError::InvalidField {
+                    report_id: Self::ID,
+                    field: "memory op",
+                    value,
+                })
+            }
+        };
+        let mut data = [0; PAGE_SIZE];
+        data.copy_from_slice(&payload[4..]);
+
+        Ok(Self {
+            op,
+            address: u16::from_le_bytes([payload[1], payload[2]]),
+            len: check_range(Self::ID, "length", payload[3], PAGE_SIZE + 1)?,
+            data,
+        })
+    }
+}
+
+/// What each of the eight keys sends in a profile, as raw bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyMapReport {
+    pub slot: u8,
+    pub bindings: [[u8; BINDING_SIZE]; KEY_COUNT],
+}
+
+impl KeyMapReport {
+    pub fn from_keys(slot: u8, keys: &[KeyBinding; KEY_COUNT]) -> Self {
+        Self {
+            slot,
+            bindings: keys.map(|key| key.encode()),
+        }
+    }
+
+    pub fn keys(&self) -> Result<[KeyBinding; KEY_COUNT], DecodeError> {
+        let mut keys = [KeyBinding::Disabled; KEY_COUNT];
+        for (key, &binding) in keys.iter_mut().zip(&self.bindings) {
+            *key = KeyBinding::decode(binding)?;
+        }
+        Ok(keys)
+    }
+}
+
+impl FeatureReport for KeyMapReport {
+    const ID: u8 = 0x07;
+    const LEN: usize = 36;
+
+    fn write_payload(&self, payload: &mut [u8]) {
+        payload[0] = self.slot;
+        for (chunk, binding) in payload[2..]
+            .chunks_exact_mut(BINDING_SIZE)
+            .zip(&self.bindings)
+        {
+            chunk.copy_from_slice(binding);
+        }
+    }
+
+    fn read_payload(payload: &[u8]) -> Result<Self, DecodeError> {
+        let mut bindings = [[0; BINDING_SIZE]; KEY_COUNT];
+        for (binding, chunk) in bindings
+            .iter_mut()
+            .zip(payload[2..].chunks_exact(BINDING_SIZE))
+        {
+            binding.copy_from_slice(chunk);
+        }
+
+        Ok(Self {
+            slot: check_range(Self::ID, "slot", payload[0], PROFILE_COUNT)?,
+            bindings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::falcon8::protocol::checksum;
+
+    fn slot() -> impl Strategy<Value = u8> {
+        0..PROFILE_COUNT as u8
+    }
+
+    fn round_trip<R: FeatureReport + PartialEq + std::fmt::Debug>(report: R) {
+        let encoded = report.encode();
+        assert_eq!(encoded.len(), R::LEN);
+        assert_eq!(encoded[0], R::ID);
+        assert_eq!(R::decode(&encoded).unwrap(), report);
+    }
+
+    proptest! {
+        #[test]
+        fn firmware_info_round_trip(
+            major: u8, minor: u8, patch: u8, build: u16, protocol_version: u8,
+        ) {
+            round_trip(FirmwareInfoReport { major, minor, patch, build, protocol_version });
+        }
+
+        #[test]
+        fn profile_state_round_trip(
+            active_slot in slot(), edit_slot in slot(), slot_count in 0..=PROFILE_COUNT as u8,
+        ) {
+            round_trip(ProfileStateReport { active_slot, edit_slot, slot_count });
+        }
+
+        #[test]
+        fn lighting_round_trip(
+            slot in slot(), mode in 0..=4u8, speed: u8, direction in 0..=1u8, brightness: u8,
+        ) {
+            let mode = mode.try_into().unwrap();
+            let direction = direction.try_into().unwrap();
+            round_trip(LightingReport { slot, mode, speed, direction, brightness });
+        }
+
+        #[test]
+        fn led_colors_round_trip(
+            slot in slot(), flags: u8, mask: u8, colors: [[u8; 3]; KEY_COUNT],
+        ) {
+            round_trip(LedColorsReport { slot, flags, mask, colors });
+        }
+
+        #[test]
+        fn key_state_round_trip(pressed: u8) {
+            round_trip(KeyStateReport { pressed });
+        }
+
+        #[test]
+        fn memory_access_round_trip(
+            write: bool, address: u16, data in proptest::collection::vec(any::<u8>(), 0..=PAGE_SIZE),
+        ) {
+            if write {
+                round_trip(MemoryAccessReport::write(address, &data));
+            } else {
+                round_trip(MemoryAccessReport::read(address, data.len() as u8));
+            }
+        }
+
+        #[test]
+        fn key_map_round_trip(slot in slot(), bindings: [[u8; BINDING_SIZE]; KEY_COUNT]) {
+            round_trip(KeyMapReport { slot, bindings });
+        }
+
+        #[test]
+        fn corrupted_byte_is_rejected(
+            bindings: [[u8; BINDING_SIZE]; KEY_COUNT],
+            index in 1..KeyMapReport::LEN - 1,
+            flip in 1..=u8::MAX,
+        ) {
+            let mut encoded = KeyMapReport { slot: 0, bindings }.encode();
+            encoded[index] ^= flip;
+            prop_assert!(KeyMapReport::decode(&encoded).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_wrong_length() {
+        let encoded = ProfileStateReport {
+            active_slot: 0,
+            edit_slot: 0,
+            slot_count: 4,
+        }
+        .encode();
+        assert_eq!(
+            ProfileStateReport::decode(&encoded[..5]),
+            Err(DecodeError::Length {
+                report_id: 0x02,
+                expected: 6,
+                actual: 5
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_lighting_mode() {
+        let mut encoded = LightingReport {
+            slot: 0,
+            mode: LightingMode::Wave,
+            speed: 0,
+            direction: Direction::RightToLeft,
+            brightness: 0,
+        }
+        .encode();
+        encoded[2] = 0x09;
+        encoded[7] = checksum(&encoded[..7]);
+        assert_eq!(
+            LightingReport::decode(&encoded),
+            Err(DecodeError::InvalidField {
+                report_id: 0x03,
+                field: "lighting mode",
+                value: 0x09
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_wrong_report_id() {
+        let encoded = LightingReport {
+            slot: 0,
+            mode: LightingMode::Off,
+            speed: 0,
+            direction: Direction::LeftToRight,
+            brightness: 0,
+        }
+        .encode();
+        assert_eq!(
+            FirmwareInfoReport::decode(&encoded),
+            Err(DecodeError::ReportId {
+                expected: 0x01,
+                actual: 0x03
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_bad_checksum() {
+        let mut encoded = MemoryAccessReport::read(0x0100, 32).encode();
+        encoded[MemoryAccessReport::LEN - 1] ^= 0xFF;
+        assert!(matches!(
+            MemoryAccessReport::decode(&encoded),
+            Err(DecodeError::Checksum {
+                report_id: 0x06,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_out_of_range_slot() {
+        let mut encoded = KeyMapReport {
+            slot: 0,
+            bindings: [[0; BINDING_SIZE]; KEY_COUNT],
+        }
+        .encode();
+        encoded[1] = PROFILE_COUNT as u8;
+        encoded[KeyMapReport::LEN - 1] = checksum(&encoded[..KeyMapReport::LEN - 1]);
+        assert_eq!(
+            KeyMapReport::decode(&encoded),
+            Err(DecodeError::InvalidField {
+                report_id: 0x07,
+                field: "slot",
+                value: PROFILE_COUNT as u8
+            })
+        );
+    }
+}
@@ -0,0 +1,408 @@
+//! Picking one keypad out of several, by something that survives restarts and replugging.
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{transport::Transport, Falcon8, Falcon8Error, Result, TIMEOUT};
+
+/// Identifies a keypad. Parsed from, and displayed as:
+///
+/// - `2`: the third keypad found (`#2` works too),
+/// - `serial:F8A01234`: the keypad with that serial number,
+/// - `port:3-1.4`: whatever keypad is plugged into that port,
+/// - `name:left`: the keypad nicknamed `left`,
+/// - anything else: a nickname or, failing that, a serial number, or a port path if it looks like
+///   one.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceSelector {
+    Index(usize),
+    Serial(String),
+    PortPath(String),
+    Nickname(String),
+    /// A nickname or serial number.
+    Name(String),
+}
+
+/// What a keypad can be selected by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceIdentity {
+    pub serial_number: Option<String>,
+    pub port_path: String,
+    pub nickname: Option<String>,
+}
+
+/// Whether `s` is in sysfs port path notation, e.g. `3-1.4`.
+fn is_port_path(s: &str) -> bool {
+    let Some((bus, ports)) = s.split_once('-') else {
+        return false;
+    };
+    let numeric = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    numeric(bus) && ports.split('.').all(numeric)
+}
+
+impl DeviceSelector {
+    fn matches(&self, index: usize, identity: &DeviceIdentity) -> bool {
+        match self {
+            Self::Index(i) => *i == index,
+            Self::Serial(serial) => identity.serial_number.as_ref() == Some(serial),
+            Self::PortPath(path) => identity.port_path == *path,
+            Self::Nickname(name) => identity.nickname.as_ref() == Some(name),
+            Self::Name(name) => {
+                identity.nickname.as_ref() == Some(name)
+                    || identity.serial_number.as_ref() == Some(name)
+            }
+        }
+    }
+
+    /// Index of the one keypad in `identities` this selects.
+    pub fn find(&self, identities: &[DeviceIdentity]) -> Result<usize> {
+        let mut found = identities
+            .iter()
+            .enumerate()
+            .filter(|(index, identity)| self.matches(*index, identity))
+            .map(|(index, _)| index);
+
+        match (found.next(), found.count()) {
+            (Some(index), 0) => Ok(index),
+            (Some(_), others) => Err(Falcon8Error::AmbiguousDevice {
+                selector: self.to_string(),
+                count: others + 1,
+            }),
+            (None, _) => Err(Falcon8Error::NoMatchingDevice(self.to_string())),
+        }
+    }
+}
+
+impl FromStr for DeviceSelector {
+    type Err = Falcon8Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || Falcon8Error::InvalidSelector(s.to_string());
+        let non_empty = |value: &str| {
+            (!value.is_empty())
+                .then(|| value.to_string())
+                .ok_or_else(invalid)
+        };
+
+        if let Some(serial) = s.strip_prefix("serial:") {
+            return non_empty(serial).map(Self::Serial);
+        }
+        if let Some(path) = s.strip_prefix("port:") {
+            return is_port_path(path)
+                .then(|| Self::PortPath(path.to_string()))
+                .ok_or_else(invalid);
+        }
+        if let Some(name) = s.strip_prefix("name:") {
+            return non_empty(name).map(Self::Nickname);
+        }
+        if let Ok(index) = s.strip_prefix('#').unwrap_or(s).parse() {
+            return Ok(Self::Index(index));
+        }
+        if is_port_path(s) {
+            return Ok(Self::PortPath(s.to_string()));
+        }
+        non_empty(s).map(Self::Name)
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "{index}"),
+            Self::Serial(serial) => write!(f, "serial:{serial}"),
+            Self::PortPath(path) => write!(f, "port:{path}"),
+            Self::Nickname(name) => write!(f, "name:{name}"),
+            Self::Name(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+impl Serialize for DeviceSelector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Accepts a selector string, or a bare number as an index.
+impl<'de> Deserialize<'de> for DeviceSelector {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct Visitor;
+
+        impl de::Visitor<'_> for Visitor {
+            type Value = DeviceSelector;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a device index, serial number, port path or nickname")
+            }
+
+            fn visit_u64<E: de::Error>(self, index: u64) -> std::result::Result<Self::Value, E> {
+                usize::try_from(index)
+                    .map(DeviceSelector::Index)
+                    .map_err(E::custom)
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> std::result::Result<Self::Value, E> {
+                s.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Nicknames for keypads, keyed by [`Falcon8::persistent_key`] and optionally backed by a JSON
+/// file that is rewritten on every change.
+#[derive(Debug, Default)]
+pub struct Nicknames {
+    path: Option<PathBuf>,
+    names: BTreeMap<String, String>,
+}
+
+impl Nicknames {
+    /// Loads the nicknames saved at `path`, or none if it doesn't exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let names = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|e| Falcon8Error::file(&path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(Falcon8Error::file(&path, e)),
+        };
+
+        Ok(Self {
+            path: Some(path),
+            names,
+        })
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.names.get(key).map(String::as_str)
+    }
+
+    /// Names the keypad with persistent key `key`, or forgets its name if `name` is `None`.
+    pub fn set(&mut self, key: &str, name: Option<&str>) -> Result<()> {
+        match name.map(str::trim) {
+            Some(name) => {
+                Self::validate(name)?;
+                if self.names.iter().any(|(k, n)| k != key && n == name) {
+                    return Err(Falcon8Error::InvalidNickname {
+                        name: name.to_string(),
+                        reason: "another keypad already has it",
+                    });
+                }
+                self.names.insert(key.to_string(), name.to_string());
+            }
+            None => {
+                self.names.remove(key);
+            }
+        }
+        self.save()
+    }
+
+    /// Rejects names that would be parsed as some other kind of [`DeviceSelector`].
+    fn validate(name: &str) -> Result<()> {
+        let reason = if name.is_empty() {
+            "it is empty"
+        } else if !matches!(name.parse(), Ok(DeviceSelector::Name(_))) {
+            "it would be read as an index, port path or prefixed selector"
+        } else {
+            return Ok(());
+        };
+
+        Err(Falcon8Error::InvalidNickname {
+            name: name.to_string(),
+            reason,
+        })
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| Falcon8Error::file(path, e))?;
+        }
+        let json = serde_json::to_string_pretty(&self.names).expect("string map serializes");
+        fs::write(path, json).map_err(|e| Falcon8Error::file(path, e))
+    }
+}
+
+impl<T: Transport> Falcon8<T> {
+    /// A key for remembering things about this keypad between runs: its serial number, or its
+    /// port path if it doesn't report one.
+    pub fn persistent_key(&self) -> Result<String> {
+        match self.transport.read_strings(TIMEOUT)?.serial_number {
+            Some(serial_number) => Ok(serial_number),
+            None => Ok(self.transport.usb_details()?.port_path()),
+        }
+    }
+
+    pub fn identity(&self, nicknames: &Nicknames) -> Result<DeviceIdentity> {
+        let serial_number = self.transport.read_strings(TIMEOUT)?.serial_number;
+        let port_path = self.transport.usb_details()?.port_path();
+        let key = serial_number.as_ref().unwrap_or(&port_path);
+        let nickname = nicknames.get(key).map(str::to_string);
+
+        Ok(DeviceIdentity {
+            serial_number,
+            port_path,
+            nickname,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::transport::SimulatedFalcon8;
+
+    fn identity(serial_number: &str, port_path: &str, nickname: Option<&str>) -> DeviceIdentity {
+        DeviceIdentity {
+            serial_number: Some(serial_number.to_string()),
+            port_path: port_path.to_string(),
+            nickname: nickname.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for (input, selector) in [
+            ("2", DeviceSelector::Index(2)),
+            ("#2", DeviceSelector::Index(2)),
+            ("serial:F8A01234", DeviceSelector::Serial("F8A01234".into())),
+            ("port:3-1.4", DeviceSelector::PortPath("3-1.4".into())),
+            ("3-1.4", DeviceSelector::PortPath("3-1.4".into())),
+            ("name:left", DeviceSelector::Nickname("left".into())),
+            (" left ", DeviceSelector::Name("left".into())),
+        ] {
+            let parsed: DeviceSelector = input.parse().unwrap();
+            assert_eq!(parsed, selector, "{input}");
+            assert_eq!(
+                parsed.to_string().parse::<DeviceSelector>().unwrap(),
+                parsed
+            );
+        }
+
+        for input in ["", "serial:", "port:3", "port:a-1", "name:"] {
+            assert!(input.parse::<DeviceSelector>().is_err(), "{input}");
+        }
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let selectors: Vec<DeviceSelector> =
+            serde_json::from_str(r#"[1, "1", "serial:X", "desk"]"#).unwrap();
+        assert_eq!(
+            selectors,
+            [
+                DeviceSelector::Index(1),
+                DeviceSelector::Index(1),
+                DeviceSelector::Serial("X".into()),
+                DeviceSelector::Name("desk".into()),
+            ]
+        );
+        assert!(serde_json::from_str::<DeviceSelector>(r#""port:x""#).is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let identities = [
+            identity("A", "1-1", Some("left")),
+            identity("B", "1-2", None),
+            identity("left", "1-3", None),
+        ];
+
+        let find = |s: &str| s.parse::<DeviceSelector>().unwrap().find(&identities);
+        assert_eq!(find("1"), Ok(1));
+        assert_eq!(find("serial:B"), Ok(1));
+        assert_eq!(find("1-2"), Ok(1));
+        assert_eq!(find("name:left"), Ok(0));
+        assert_eq!(find("serial:left"), Ok(2));
+        assert_eq!(find("B"), Ok(1));
+        assert_eq!(
+            find("left"),
+            Err(Falcon8Error::AmbiguousDevice {
+                selector: "left".into(),
+                count: 2,
+            })
+        );
+        assert_eq!(find("3"), Err(Falcon8Error::NoMatchingDevice("3".into())));
+    }
+
+    #[test]
+    fn test_identity() {
+        let sim = SimulatedFalcon8::new();
+        sim.set_serial_number("F8A01234");
+        sim.set_location(3, &[1, 4]);
+        let falcon = Falcon8::with_transport(sim);
+
+        let mut nicknames = Nicknames::default();
+        nicknames.set("F8A01234", Some("left")).unwrap();
+
+        assert_eq!(falcon.persistent_key().unwrap(), "F8A01234");
+        assert_eq!(
+            falcon.identity(&nicknames).unwrap(),
+            identity("F8A01234", "3-1.4", Some("left"))
+        );
+    }
+
+    #[test]
+    fn test_nickname_validation() {
+        let mut nicknames = Nicknames::default();
+        nicknames.set("A", Some("left")).unwrap();
+        nicknames.set("A", Some("left")).unwrap();
+
+        for name in ["", "3", "1-2", "serial:x"] {
+            assert!(
+                matches!(
+                    nicknames.set("B", Some(name)),
+                    Err(Falcon8Error::InvalidNickname { .. })
+                ),
+                "{name}"
+            );
+        }
+        assert_eq!(
+            nicknames.set("B", Some("left")),
+            Err(Falcon8Error::InvalidNickname {
+                name: "left".into(),
+                reason: "another keypad already has it",
+            })
+        );
+
+        nicknames.set("A", None).unwrap();
+        assert_eq!(nicknames.get("A"), None);
+        nicknames.set("B", Some("left")).unwrap();
+    }
+
+    #[test]
+    fn test_nicknames_persist() {
+        let dir = std::env::temp_dir().join(format!("falcon8-nicknames-{}", std::process::id()));
+        let path = dir.join("nicknames.json");
+
+        let mut nicknames = Nicknames::open(&path).unwrap();
+        assert_eq!(nicknames.get("A"), None);
+        nicknames.set("A", Some("left")).unwrap();
+
+        let reopened = Nicknames::open(&path).unwrap();
+        assert_eq!(reopened.get("A"), Some("left"));
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(
+            Nicknames::open(&path),
+            Err(Falcon8Error::File { .. })
+        ));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use rusb::Result;
+use serde::{Deserialize, Serialize};
+
+use super::Endpoint;
+
+mod sim;
+mod usb;
+
+pub use sim::SimulatedFalcon8;
+pub use usb::UsbTransport;
+
+/// USB string descriptors read from the device, in its first supported language.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceStrings {
+    pub language: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial_number: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsbSpeed {
+    Unknown,
+    Low,
+    Full,
+    High,
+    Super,
+    SuperPlus,
+}
+
+impl From<rusb::Speed> for UsbSpeed {
+    fn from(speed: rusb::Speed) -> Self {
+        match speed {
+            rusb::Speed::Low => Self::Low,
+            rusb::Speed::Full => Self::Full,
+            rusb::Speed::High => Self::High,
+            rusb::Speed::Super => Self::Super,
+            rusb::Speed::SuperPlus => Self::SuperPlus,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Where the device sits on the bus and how it enumerated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UsbDetails {
+    pub bus_number: u8,
+    pub address: u8,
+    pub port_numbers: Vec<u8>,
+    pub speed: UsbSpeed,
+    /// bcdDevice as (major, minor, sub-minor).
+    pub device_version: (u8, u8, u8),
+}
+
+impl UsbDetails {
+    /// The bus and port chain in sysfs notation, e.g. `3-1.4`. Stable across replugs into the
+    /// same physical port, unlike `address`.
+    pub fn port_path(&self) -> String {
+        port_path(self.bus_number, &self.port_numbers)
+    }
+}
+
+pub(crate) fn port_path(bus_number: u8, port_numbers: &[u8]) -> String {
+    let ports: Vec<String> = port_numbers.iter().map(u8::to_string).collect();
+    format!("{bus_number}-{}", ports.join("."))
+}
+
+/// Everything the driver needs from the USB stack.
+///
+/// `UsbTransport` talks to real hardware through `rusb`; `SimulatedFalcon8` is an in-memory
+/// keypad so the driver can be exercised on machines without one plugged in.
+///
+/// Methods take `&self` and implementations must be `Send + Sync`, so one device can serve
+/// commands from several threads at once.
+pub trait Transport: Send + Sync {
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize>;
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize>;
+
+    fn endpoints(&self) -> Result<Vec<Endpoint>>;
+
+    fn claim_interface(&self, iface: u8) -> Result<()>;
+
+    fn release_interface(&self, iface: u8) -> Result<()>;
+
+    fn kernel_driver_active(&self, iface: u8) -> Result<bool>;
+
+    fn detach_kernel_driver(&self, iface: u8) -> Result<()>;
+
+    fn attach_kernel_driver(&self, iface: u8) -> Result<()>;
+
+    fn active_configuration(&self) -> Result<u8>;
+
+    fn read_strings(&self, timeout: Duration) -> Result<DeviceStrings>;
+
+    fn usb_details(&self) -> Result<UsbDetails>;
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        (**self).read_control(request_type, request, value, index, buf, timeout)
+    }
+
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        (**self).write_control(request_type, request, value, index, buf, timeout)
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        (**self).read_interrupt(endpoint, buf, timeout)
+    }
+
+    fn endpoints(&self) -> Result<Vec<Endpoint>> {
+        (**self).endpoints()
+    }
+
+    fn claim_interface(&self, iface: u8) -> Result<()> {
+        (**self).claim_interface(iface)
+    }
+
+    fn release_interface(&self, iface: u8) -> Result<()> {
+        (**self).release_interface(iface)
+    }
+
+    fn kernel_driver_active(&self, iface: u8) -> Result<bool> {
+        (**self).kernel_driver_active(iface)
+    }
+
+    fn detach_kernel_driver(&self, iface: u8) -> Result<()> {
+        (**self).detach_kernel_driver(iface)
+    }
+
+    fn attach_kernel_driver(&self, iface: u8) -> Result<()> {
+        (**self).attach_kernel_driver(iface)
+    }
+
+    fn active_configuration(&self) -> Result<u8> {
+        (**self).active_configuration()
+    }
+
+    fn read_strings(&self, timeout: Duration) -> Result<DeviceStrings> {
+        (**self).read_strings(timeout)
+    }
+
+    fn usb_details(&self) -> Result<UsbDetails> {
+        (**self).usb_details()
+    }
+}
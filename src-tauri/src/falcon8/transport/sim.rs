@@ -0,0 +1,564 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use rusb::{Direction, Recipient, RequestType, Result};
+
+use super::{DeviceStrings, Transport, UsbDetails, UsbSpeed};
+use crate::falcon8::{protocol::*, Endpoint};
+
+/// (interface, interrupt IN endpoint) pairs, mirroring the real keypad's descriptors.
+const INTERFACES: [(u8, u8); 3] = [(0, 0x81), (1, 0x82), (2, 0x83)];
+
+#[derive(Debug)]
+struct SimState {
+    firmware: FirmwareInfoReport,
+    memory: Vec<u8>,
+    active_slot: u8,
+    edit_slot: u8,
+    read_pointer: (u16, u8),
+    /// The most bytes a memory read returns, to simulate short reads.
+    max_read_len: u8,
+    /// Colours from volatile writes, shown over the active profile's saved ones.
+    live_colors: Option<[Rgb; KEY_COUNT]>,
+    volatile_masks: Vec<u8>,
+    interrupts: HashMap<u8, VecDeque<Vec<u8>>>,
+    /// Claimed interfaces and the handle holding each.
+    claimed: HashMap<u8, usize>,
+    /// Handles opened so far, used to number the next one.
+    handles: usize,
+    kernel_drivers: HashSet<u8>,
+    strings: DeviceStrings,
+    usb: UsbDetails,
+}
+
+fn default_memory() -> Vec<u8> {
+    let mut memory = vec![0; MEMORY_SIZE];
+    for slot in 0..PROFILE_COUNT {
+        let base = profile_address(slot);
+        for key in 0..KEY_COUNT {
+            // Keyboard usage F13 + key, no modifiers.
+            let offset = base + KEY_MAP_OFFSET + key * BINDING_SIZE;
+            memory[offset..offset + BINDING_SIZE].copy_from_slice(&[
+                0x01,
+                0x00,
+                0x68 + key as u8,
+                0x00,
+            ]);
+        }
+        memory[base + LIGHTING_OFFSET..base + LIGHTING_OFFSET + 4]
+            .copy_from_slice(&[0x01, 0x80, 0x00, 0xFF]);
+        memory[base + LED_COLORS_OFFSET..base + LED_COLORS_OFFSET + LED_COLORS_SIZE].fill(0xFF);
+    }
+    memory
+}
+
+fn decode<R: FeatureReport>(data: &[u8]) -> Result<R> {
+    R::decode(data).map_err(|_| rusb::Error::Pipe)
+}
+
+impl SimState {
+    fn profile_bytes(&self, slot: u8, offset: usize, len: usize) -> &[u8] {
+        let base = profile_address(usize::from(slot)) + offset;
+        &self.memory[base..base + len]
+    }
+
+    fn profile_bytes_mut(&mut self, slot: u8, offset: usize, len: usize) -> &mut [u8] {
+        let base = profile_address(usize::from(slot)) + offset;
+        &mut self.memory[base..base + len]
+    }
+
+    fn saved_colors(&self, slot: u8) -> [Rgb; KEY_COUNT] {
+        let bytes = self.profile_bytes(slot, LED_COLORS_OFFSET, LED_COLORS_SIZE);
+        let mut colors = [[0; 3]; KEY_COUNT];
+        for (color, chunk) in colors.iter_mut().zip(bytes.chunks_exact(3)) {
+            color.copy_from_slice(chunk);
+        }
+        colors
+    }
+
+    fn get_report(&self, report_id: u8) -> Result<Vec<u8>> {
+        let slot = self.edit_slot;
+        let report = match report_id {
+            FirmwareInfoReport::ID => self.firmware.encode(),
+            ProfileStateReport::ID => ProfileStateReport {
+                active_slot: self.active_slot,
+                edit_slot: self.edit_slot,
+                slot_count: PROFILE_COUNT as u8,
+            }
+            .encode(),
+            LightingReport::ID => {
+                let bytes = self.profile_bytes(slot, LIGHTING_OFFSET, LIGHTING_SIZE);
+                LightingReport {
+                    slot,
+                    mode: bytes[0].try_into().map_err(|_| rusb::Error::Pipe)?,
+                    speed: bytes[1],
+                    direction: bytes[2].try_into().map_err(|_| rusb::Error::Pipe)?,
+                    brightness: bytes[3],
+                }
+                .encode()
+            }
+            LedColorsReport::ID => LedColorsReport {
+                slot,
+                flags: 0,
+                mask: 0xFF,
+                colors: self.saved_colors(slot),
+            }
+            .encode(),
+            MemoryAccessReport::ID => {
+                let (address, len) = self.read_pointer;
+                let len = len.min(self.max_read_len);
+                let start = usize::from(address);
+                let end = start + usize::from(len);
+                let mut data = [0; PAGE_SIZE];
+                data[..usize::from(len)].copy_from_slice(&self.memory[start..end]);
+                MemoryAccessReport {
+                    op: MemoryOp::Read,
+                    address,
+                    len,
+                    data,
+                }
+                .encode()
+            }
+            KeyMapReport::ID => {
+                let bytes = self.profile_bytes(slot, KEY_MAP_OFFSET, KEY_MAP_SIZE);
+                let mut bindings = [[0; BINDING_SIZE]; KEY_COUNT];
+                for (binding, chunk) in bindings.iter_mut().zip(bytes.chunks_exact(BINDING_SIZE)) {
+                    binding.copy_from_slice(chunk);
+                }
+                KeyMapReport { slot, bindings }.encode()
+            }
+            _ => return Err(rusb::Error::Pipe),
+        };
+        Ok(report)
+    }
+
+    fn set_report(&mut self, report_id: u8, data: &[u8]) -> Result<()> {
+        match report_id {
+            ProfileStateReport::ID => {
+                let report: ProfileStateReport = decode(data)?;
+                if report.active_slot != self.active_slot {
+                    self.live_colors = None;
+                }
+                self.active_slot = report.active_slot;
+                self.edit_slot = report.edit_slot;
+            }
+            LightingReport::ID => {
+                let report: LightingReport = decode(data)?;
+                self.profile_bytes_mut(report.slot, LIGHTING_OFFSET, 4)
+                    .copy_from_slice(&[
+                        report.mode as u8,
+                        report.speed,
+                        report.direction as u8,
+                        report.brightness,
+                    ]);
+            }
+            LedColorsReport::ID => {
+                let report: LedColorsReport = decode(data)?;
+                if report.flags & LED_FLAG_VOLATILE != 0 {
+                    let saved = self.saved_colors(self.active_slot);
+                    let live = self.live_colors.get_or_insert(saved);
+                    for (key, (live, color)) in live.iter_mut().zip(&report.colors).enumerate() {
+                        if report.mask & (1 << key) != 0 {
+                            *live = *color;
+                        }
+                    }
+                    self.volatile_masks.push(report.mask);
+                    return Ok(());
+                }
+                let bytes = self.profile_bytes_mut(report.slot, LED_COLORS_OFFSET, LED_COLORS_SIZE);
+                for (key, (chunk, color)) in
+                    bytes.chunks_exact_mut(3).zip(&report.colors).enumerate()
+                {
+                    if report.mask & (1 << key) != 0 {
+                        chunk.copy_from_slice(color);
+                    }
+                }
+            }
+            MemoryAccessReport::ID => {
+                let report: MemoryAccessReport = decode(data)?;
+                let start = usize::from(report.address);
+                let end = start + usize::from(report.len);
+                if end > MEMORY_SIZE {
+                    return Err(rusb::Error::Pipe);
+                }
+                match report.op {
+                    MemoryOp::Read => self.read_pointer = (report.address, report.len),
+                    MemoryOp::Write => self.memory[start..end].copy_from_slice(report.data()),
+                }
+            }
+            KeyMapReport::ID => {
+                let report: KeyMapReport = decode(data)?;
+                let bytes = self.profile_bytes_mut(report.slot, KEY_MAP_OFFSET, KEY_MAP_SIZE);
+                for (chunk, binding) in bytes.chunks_exact_mut(BINDING_SIZE).zip(&report.bindings) {
+                    chunk.copy_from_slice(binding);
+                }
+            }
+            _ => return Err(rusb::Error::Pipe),
+        }
+        Ok(())
+    }
+}
+
+/// An in-memory Falcon-8 implementing `Transport`.
+///
+/// It keeps the keypad's configuration memory and profile state and answers the feature reports
+/// in `protocol` from them, queues interrupt packets pushed by the test, and tracks interface
+/// claims and kernel drivers the same way libusb would refuse them. Each value is one open handle;
+/// `reopen` gives another handle to the same keypad, and dropping a handle releases its claims.
+#[derive(Debug)]
+pub struct SimulatedFalcon8 {
+    state: Arc<Mutex<SimState>>,
+    interrupt_ready: Arc<Condvar>,
+    handle: usize,
+}
+
+impl Default for SimulatedFalcon8 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimulatedFalcon8 {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SimState {
+                firmware: FirmwareInfoReport {
+                    major: 1,
+                    minor: 2,
+                    patch: 0,
+                    build: 42,
+                    protocol_version: 1,
+                },
+                memory: default_memory(),
+                active_slot: 0,
+                edit_slot: 0,
+                read_pointer: (0, 0),
+                max_read_len: PAGE_SIZE as u8,
+                live_colors: None,
+                volatile_masks: Vec::new(),
+                interrupts: HashMap::new(),
+                claimed: HashMap::new(),
+                handles: 1,
+                kernel_drivers: INTERFACES.iter().map(|&(iface, _)| iface).collect(),
+                strings: DeviceStrings {
+                    language: Some(0x0409),
+                    manufacturer: Some("Simulated".to_string()),
+                    product: Some("Falcon-8".to_string()),
+                    serial_number: Some("SIM00001".to_string()),
+                },
+                usb: UsbDetails {
+                    bus_number: 1,
+                    address: 5,
+                    port_numbers: vec![2],
+                    speed: UsbSpeed::Full,
+                    device_version: (1, 2, 0),
+                },
+            })),
+            interrupt_ready: Arc::new(Condvar::new()),
+            handle: 0,
+        }
+    }
+
+    /// Opens another handle to the same keypad, as a rescan would.
+    pub fn reopen(&self) -> Self {
+        let mut state = self.state();
+        let handle = state.handles;
+        state.handles += 1;
+        Self {
+            state: Arc::clone(&self.state),
+            interrupt_ready: Arc::clone(&self.interrupt_ready),
+            handle,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, SimState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_serial_number(&self, serial: &str) {
+        self.state().strings.serial_number = Some(serial.to_string());
+    }
+
+    /// Moves the device to another bus and port chain.
+    pub fn set_location(&self, bus_number: u8, port_numbers: &[u8]) {
+        let mut state = self.state();
+        state.usb.bus_number = bus_number;
+        state.usb.port_numbers = port_numbers.to_vec();
+    }
+
+    /// Gives the device another bus address, as re-enumerating it would.
+    pub fn set_address(&self, address: u8) {
+        self.state().usb.address = address;
+    }
+
+    pub fn set_firmware(&self, firmware: FirmwareInfoReport) {
+        self.state().firmware = firmware;
+    }
+
+    pub fn memory(&self) -> Vec<u8> {
+        self.state().memory.clone()
+    }
+
+    /// Overwrites configuration memory behind the protocol's back, e.g. to corrupt it.
+    pub fn poke_memory(&self, address: usize, data: &[u8]) {
+        self.state().memory[address..address + data.len()].copy_from_slice(data);
+    }
+
+    /// Makes memory reads return at most `len` bytes, however many were asked for.
+    pub fn limit_memory_reads(&self, len: u8) {
+        self.state().max_read_len = len;
+    }
+
+    pub fn active_slot(&self) -> u8 {
+        self.state().active_slot
+    }
+
+    /// What pressing the profile button on the keypad does: cycle to the next slot.
+    pub fn press_profile_button(&self) {
+        let mut state = self.state();
+        state.active_slot = (state.active_slot + 1) % PROFILE_COUNT as u8;
+        state.live_colors = None;
+    }
+
+    pub fn key_map(&self, slot: usize) -> [[u8; BINDING_SIZE]; KEY_COUNT] {
+        let state = self.state();
+        let base = profile_address(slot) + KEY_MAP_OFFSET;
+        let mut bindings = [[0; BINDING_SIZE]; KEY_COUNT];
+        for (binding, chunk) in bindings
+            .iter_mut()
+            .zip(state.memory[base..base + KEY_MAP_SIZE].chunks_exact(BINDING_SIZE))
+        {
+            binding.copy_from_slice(chunk);
+        }
+        bindings
+    }
+
+    /// The colours saved in profile `slot`.
+    pub fn led_colors(&self, slot: usize) -> [Rgb; KEY_COUNT] {
+        self.state().saved_colors(slot as u8)
+    }
+
+    /// What the LEDs are showing right now, including volatile writes.
+    pub fn displayed_colors(&self) -> [Rgb; KEY_COUNT] {
+        let state = self.state();
+        state
+            .live_colors
+            .unwrap_or_else(|| state.saved_colors(state.active_slot))
+    }
+
+    /// Masks of the volatile colour writes since the last call.
+    pub fn take_volatile_masks(&self) -> Vec<u8> {
+        std::mem::take(&mut self.state().volatile_masks)
+    }
+
+    /// Queues a packet to be returned by the next interrupt read on `endpoint`.
+    pub fn push_interrupt(&self, endpoint: u8, data: &[u8]) {
+        self.state()
+            .interrupts
+            .entry(endpoint)
+            .or_default()
+            .push_back(data.to_vec());
+        self.interrupt_ready.notify_all();
+    }
+
+    /// Whether any handle has claimed `iface`.
+    pub fn is_claimed(&self, iface: u8) -> bool {
+        self.state().claimed.contains_key(&iface)
+    }
+
+    pub fn kernel_driver_attached(&self, iface: u8) -> bool {
+        self.state().kernel_drivers.contains(&iface)
+    }
+
+    fn check_interface(iface: u8) -> Result<()> {
+        if INTERFACES.iter().any(|&(i, _)| i == iface) {
+            Ok(())
+        } else {
+            Err(rusb::Error::NotFound)
+        }
+    }
+
+    /// Interface-directed control requests go through usbfs, which refuses them while another
+    /// driver owns the interface.
+    fn check_control(state: &SimState, request_type: u8, index: u16) -> Result<u8> {
+        let class_iface_in =
+            rusb::request_type(Direction::In, RequestType::Class, Recipient::Interface);
+        let class_iface_out =
+            rusb::request_type(Direction::Out, RequestType::Class, Recipient::Interface);
+        if request_type != class_iface_in && request_type != class_iface_out {
+            return Err(rusb::Error::Pipe);
+        }
+
+        let iface = u8::try_from(index).map_err(|_| rusb::Error::InvalidParam)?;
+        Self::check_interface(iface)?;
+        if state.kernel_drivers.contains(&iface) {
+            return Err(rusb::Error::Busy);
+        }
+        Ok(iface)
+    }
+}
+
+impl Transport for SimulatedFalcon8 {
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        let state = self.state();
+        Self::check_control(&state, request_type, index)?;
+
+        let [report_type, report_id] = value.to_be_bytes();
+        if request != HID_GET_REPORT || report_type != HID_REPORT_TYPE_FEATURE {
+            return Err(rusb::Error::Pipe);
+        }
+
+        let report = state.get_report(report_id)?;
+        let len = report.len().min(buf.len());
+        buf[..len].copy_from_slice(&report[..len]);
+        Ok(len)
+    }
+
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        _timeout: Duration,
+    ) -> Result<usize> {
+        let mut state = self.state();
+        Self::check_control(&state, request_type, index)?;
+
+        let [report_type, report_id] = value.to_be_bytes();
+        if request != HID_SET_REPORT || report_type != HID_REPORT_TYPE_FEATURE {
+            return Err(rusb::Error::Pipe);
+        }
+
+        if buf.first() != Some(&report_id) {
+            return Err(rusb::Error::Pipe);
+        }
+        state.set_report(report_id, buf)?;
+        Ok(buf.len())
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let iface = INTERFACES
+            .iter()
+            .find(|&&(_, address)| address == endpoint)
+            .map(|&(iface, _)| iface)
+            .ok_or(rusb::Error::NotFound)?;
+
+        let state = self.state();
+        if state.claimed.get(&iface) != Some(&self.handle) {
+            return Err(rusb::Error::Busy);
+        }
+
+        let (mut state, _) = self
+            .interrupt_ready
+            .wait_timeout_while(state, timeout, |state| {
+                state
+                    .interrupts
+                    .get(&endpoint)
+                    .is_none_or(|queue| queue.is_empty())
+            })
+            .unwrap_or_else(|e| e.into_inner());
+
+        let packet = state
+            .interrupts
+            .get_mut(&endpoint)
+            .and_then(|queue| queue.pop_front())
+            .ok_or(rusb::Error::Timeout)?;
+        let len = packet.len().min(buf.len());
+        buf[..len].copy_from_slice(&packet[..len]);
+        Ok(len)
+    }
+
+    fn endpoints(&self) -> Result<Vec<Endpoint>> {
+        Ok(INTERFACES
+            .iter()
+            .map(|&(iface, address)| Endpoint {
+                config: 1,
+                iface,
+                setting: 0,
+                address,
+            })
+            .collect())
+    }
+
+    fn claim_interface(&self, iface: u8) -> Result<()> {
+        Self::check_interface(iface)?;
+        let mut state = self.state();
+        if state.kernel_drivers.contains(&iface) {
+            return Err(rusb::Error::Busy);
+        }
+        match state.claimed.get(&iface) {
+            Some(&owner) if owner != self.handle => Err(rusb::Error::Busy),
+            _ => {
+                state.claimed.insert(iface, self.handle);
+                Ok(())
+            }
+        }
+    }
+
+    fn release_interface(&self, iface: u8) -> Result<()> {
+        Self::check_interface(iface)?;
+        let mut state = self.state();
+        if state.claimed.get(&iface) == Some(&self.handle) {
+            state.claimed.remove(&iface);
+            Ok(())
+        } else {
+            Err(rusb::Error::NotFound)
+        }
+    }
+
+    fn kernel_driver_active(&self, iface: u8) -> Result<bool> {
+        Self::check_interface(iface)?;
+        Ok(self.state().kernel_drivers.contains(&iface))
+    }
+
+    fn detach_kernel_driver(&self, iface: u8) -> Result<()> {
+        Self::check_interface(iface)?;
+        if self.state().kernel_drivers.remove(&iface) {
+            Ok(())
+        } else {
+            Err(rusb::Error::NotFound)
+        }
+    }
+
+    fn attach_kernel_driver(&self, iface: u8) -> Result<()> {
+        Self::check_interface(iface)?;
+        let mut state = self.state();
+        if state.claimed.contains_key(&iface) || !state.kernel_drivers.insert(iface) {
+            return Err(rusb::Error::Busy);
+        }
+        Ok(())
+    }
+
+    fn active_configuration(&self) -> Result<u8> {
+        Ok(1)
+    }
+
+    fn read_strings(&self, _timeout: Duration) -> Result<DeviceStrings> {
+        Ok(self.state().strings.clone())
+    }
+
+    fn usb_details(&self) -> Result<UsbDetails> {
+        Ok(self.state().usb.clone())
+    }
+}
+
+impl Drop for SimulatedFalcon8 {
+    /// Closing a handle gives up its claims, as libusb_close does.
+    fn drop(&mut self) {
+        let handle = self.handle;
+        self.state().claimed.retain(|_, owner| *owner != handle);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+    time::Duration,
+};
+
+use rusb::{Device, DeviceHandle, Result, UsbContext};
+
+use super::{DeviceStrings, Transport, UsbDetails};
+use crate::falcon8::Endpoint;
+
+/// A `Transport` backed by a real USB device handle.
+///
+/// Transfers only need a shared borrow of the handle, while claiming interfaces and swapping
+/// kernel drivers need an exclusive one, so the handle sits behind a `RwLock`.
+#[derive(Debug)]
+pub struct UsbTransport<C: UsbContext> {
+    pub device: Device<C>,
+    handle: RwLock<DeviceHandle<C>>,
+}
+
+impl<C: UsbContext> UsbTransport<C> {
+    pub fn open(device: Device<C>) -> Result<Self> {
+        let handle = device.open()?;
+        Ok(Self {
+            device,
+            handle: RwLock::new(handle),
+        })
+    }
+
+    /// Opens every device matching `vid`/`pid`. Devices that fail to open are skipped, unless
+    /// none could be opened, in which case the first error is returned so a missing permission
+    /// isn't mistaken for a missing device.
+    pub fn open_all(context: &C, vid: u16, pid: u16) -> Result<Vec<Self>> {
+        let devices = context.devices()?;
+        let mut result = Vec::new();
+        let mut first_error = None;
+
+        for device in devices.iter() {
+            let Ok(device_desc) = device.device_descriptor() else {
+                continue;
+            };
+
+            if device_desc.vendor_id() == vid && device_desc.product_id() == pid {
+                match Self::open(device) {
+                    Ok(transport) => result.push(transport),
+                    Err(e) => {
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+        }
+
+        match first_error {
+            Some(e) if result.is_empty() => Err(e),
+            _ => Ok(result),
+        }
+    }
+
+    pub fn context(&self) -> &C {
+        self.device.context()
+    }
+
+    fn handle(&self) -> RwLockReadGuard<'_, DeviceHandle<C>> {
+        self.handle.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn handle_mut(&self) -> RwLockWriteGuard<'_, DeviceHandle<C>> {
+        self.handle.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<C: UsbContext> Transport for UsbTransport<C> {
+    fn read_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.handle()
+            .read_control(request_type, request, value, index, buf, timeout)
+    }
+
+    fn write_control(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        buf: &[u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        self.handle()
+            .write_control(request_type, request, value, index, buf, timeout)
+    }
+
+    fn read_interrupt(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.handle().read_interrupt(endpoint, buf, timeout)
+    }
+
+    fn endpoints(&self) -> Result<Vec<Endpoint>> {
+        let config_desc = self.device.config_descriptor(0)?;
+        let mut endpoints = vec![];
+
+        for interface in config_desc.interfaces() {
+            for interface_desc in interface.descriptors() {
+                for endpoint_desc in interface_desc.endpoint_descriptors() {
+                    endpoints.push(Endpoint {
+                        config: config_desc.number(),
+                        iface: interface_desc.interface_number(),
+                        setting: interface_desc.setting_number(),
+                        address: endpoint_desc.address(),
+                    });
+                }
+            }
+        }
+
+        Ok(endpoints)
+    }
+
+    fn claim_interface(&self, iface: u8) -> Result<()> {
+        self.handle_mut().claim_interface(iface)
+    }
+
+    fn release_interface(&self, iface: u8) -> Result<()> {
+        self.handle_mut().release_interface(iface)
+    }
+
+    fn kernel_driver_active(&self, iface: u8) -> Result<bool> {
+        self.handle().kernel_driver_active(iface)
+    }
+
+    fn detach_kernel_driver(&self, iface: u8) -> Result<()> {
+        self.handle_mut().detach_kernel_driver(iface)
+    }
+
+    fn attach_kernel_driver(&self, iface: u8) -> Result<()> {
+        self.handle_mut().attach_kernel_driver(iface)
+    }
+
+    fn active_configuration(&self) -> Result<u8> {
+        self.handle().active_configuration()
+    }
+
+    fn read_strings(&self, timeout: Duration) -> Result<DeviceStrings> {
+        let device_desc = self.device.device_descriptor()?;
+        let handle = self.handle();
+        let languages = handle.read_languages(timeout)?;
+
+        let Some(&language) = languages.first() else {
+            return Ok(DeviceStrings::default());
+        };
+
+        Ok(DeviceStrings {
+            language: Some(language.lang_id()),
+            manufacturer: handle
+                .read_manufacturer_string(language, &device_desc, timeout)
+                .ok(),
+            product: handle
+                .read_product_string(language, &device_desc, timeout)
+                .ok(),
+            serial_number: handle
+                .read_serial_number_string(language, &device_desc, timeout)
+                .ok(),
+        })
+    }
+
+    fn usb_details(&self) -> Result<UsbDetails> {
+        let version = self.device.device_descriptor()?.device_version();
+        Ok(UsbDetails {
+            bus_number: self.device.bus_number(),
+            address: self.device.address(),
+            port_numbers: self.device.port_numbers()?,
+            speed: self.device.speed().into(),
+            device_version: (version.major(), version.minor(), version.sub_minor()),
+        })
+    }
+}
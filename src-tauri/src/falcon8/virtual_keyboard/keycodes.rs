@@ -0,0 +1,105 @@
+//! HID usages to Linux input event codes (`KEY_*` in `linux/input-event-codes.h`), following
+//! the kernel's own HID driver.
+
+use super::Key;
+
+/// Keyboard page usages 0x00 to 0x94, 0 where Linux has no key.
+#[rustfmt::skip]
+const KEYBOARD: [u16; 0x95] = [
+      0,   0,   0,   0,  30,  48,  46,  32,  18,  33,  34,  35,  23,  36,  37,  38,
+     50,  49,  24,  25,  16,  19,  31,  20,  22,  47,  17,  45,  21,  44,   2,   3,
+      4,   5,   6,   7,   8,   9,  10,  11,  28,   1,  14,  15,  57,  12,  13,  26,
+     27,  43,  43,  39,  40,  41,  51,  52,  53,  58,  59,  60,  61,  62,  63,  64,
+     65,  66,  67,  68,  87,  88,  99,  70, 119, 110, 102, 104, 111, 107, 109, 106,
+    105, 108, 103,  69,  98,  55,  74,  78,  96,  79,  80,  81,  75,  76,  77,  71,
+     72,  73,  82,  83,  86, 127, 116, 117, 183, 184, 185, 186, 187, 188, 189, 190,
+    191, 192, 193, 194, 134, 138, 130, 132, 128, 129, 131, 137, 133, 135, 136, 113,
+    115, 114,   0,   0,   0, 121,   0,  89,  93, 124,  92,  94,  95,   0,   0,   0,
+    122, 123,  90,  91,  85,
+];
+
+/// Left Ctrl (0xE0) to Right GUI (0xE7).
+const MODIFIERS: [u16; 8] = [29, 42, 56, 125, 97, 54, 100, 126];
+
+/// Consumer page usages with a Linux key.
+const CONSUMER: &[(u16, u16)] = &[
+    (0x006F, 225), // Brightness up
+    (0x0070, 224), // Brightness down
+    (0x00B0, 207), // Play
+    (0x00B1, 201), // Pause
+    (0x00B3, 208), // Fast forward
+    (0x00B4, 168), // Rewind
+    (0x00B5, 163), // Next track
+    (0x00B6, 165), // Previous track
+    (0x00B7, 166), // Stop
+    (0x00B8, 161), // Eject
+    (0x00CD, 164), // Play/pause
+    (0x00E2, 113), // Mute
+    (0x00E9, 115), // Volume up
+    (0x00EA, 114), // Volume down
+    (0x0183, 171), // Media player
+    (0x018A, 155), // Mail
+    (0x0192, 140), // Calculator
+    (0x0194, 144), // File browser
+    (0x0221, 217), // Search
+    (0x0223, 172), // Browser home
+    (0x0224, 158), // Back
+    (0x0225, 159), // Forward
+    (0x0227, 173), // Refresh
+    (0x022A, 156), // Bookmarks
+];
+
+/// Generic Desktop page system controls.
+const SYSTEM: &[(u8, u16)] = &[
+    (0x81, 116), // Power down
+    (0x82, 142), // Sleep
+    (0x83, 143), // Wake up
+];
+
+/// The event code `key` sends, if Linux has one for it.
+pub fn code(key: Key) -> Option<u16> {
+    let code = match key {
+        Key::Keyboard(usage @ 0xE0..=0xE7) => MODIFIERS[usize::from(usage - 0xE0)],
+        Key::Keyboard(usage) => KEYBOARD.get(usize::from(usage)).copied().unwrap_or(0),
+        Key::Consumer(usage) => lookup(CONSUMER, usage),
+        Key::System(usage) => lookup(SYSTEM, usage),
+    };
+    (code != 0).then_some(code)
+}
+
+fn lookup<T: PartialEq>(table: &[(T, u16)], usage: T) -> u16 {
+    table
+        .iter()
+        .find(|(u, _)| *u == usage)
+        .map_or(0, |&(_, code)| code)
+}
+
+/// Every event code the virtual keyboard can send, to declare when creating it.
+pub fn all() -> impl Iterator<Item = u16> {
+    KEYBOARD
+        .into_iter()
+        .chain(MODIFIERS)
+        .chain(CONSUMER.iter().map(|&(_, code)| code))
+        .chain(SYSTEM.iter().map(|&(_, code)| code))
+        .filter(|&code| code != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::protocol::usage;
+
+    #[test]
+    fn test_code() {
+        assert_eq!(code(Key::Keyboard(usage::keyboard::A)), Some(30));
+        assert_eq!(code(Key::Keyboard(usage::keyboard::N0)), Some(11));
+        assert_eq!(code(Key::Keyboard(usage::keyboard::F13)), Some(183));
+        assert_eq!(code(Key::Keyboard(usage::keyboard::RIGHT_GUI)), Some(126));
+        assert_eq!(code(Key::Keyboard(0x00)), None);
+        assert_eq!(code(Key::Keyboard(0xA5)), None);
+        assert_eq!(code(Key::Consumer(usage::consumer::VOLUME_UP)), Some(115));
+        assert_eq!(code(Key::Consumer(0x01B8)), None);
+        assert_eq!(code(Key::System(usage::system::SLEEP)), Some(142));
+        assert!(all().all(|code| code != 0 && code <= 0x2FF));
+    }
+}
@@ -0,0 +1,226 @@
+//! A keyboard on the computer that the driver types on, for host-side actions that type text or
+//! send key combos.
+//!
+//! `UinputKeyboard` creates one with Linux's uinput; `RecordingKeyboard` notes down what would
+//! have been typed, so this can be tested on machines without access to `/dev/uinput`.
+
+use std::fmt;
+
+use super::{
+    protocol::{KeyBinding, Keystroke, Layout},
+    Falcon8Error, Result,
+};
+
+#[cfg(target_os = "linux")]
+mod keycodes;
+mod recording;
+#[cfg(target_os = "linux")]
+mod uinput;
+
+pub use recording::RecordingKeyboard;
+#[cfg(target_os = "linux")]
+pub use uinput::UinputKeyboard;
+
+/// A key on the virtual keyboard, by HID usage like the keypad's own bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Key {
+    /// Keyboard/Keypad page, modifiers included.
+    Keyboard(u8),
+    /// Consumer page.
+    Consumer(u16),
+    /// Generic Desktop page system controls.
+    System(u8),
+}
+
+/// Written like the single-key binding it is, e.g. `F13` or `Media.VolumeUp`.
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let binding = match *self {
+            Self::Keyboard(usage) => KeyBinding::key(usage),
+            Self::Consumer(usage) => KeyBinding::Consumer { usage },
+            Self::System(usage) => KeyBinding::System { usage },
+        };
+        binding.fmt(f)
+    }
+}
+
+/// The keys `binding` holds down, modifiers first.
+fn binding_keys(binding: KeyBinding) -> Result<Vec<Key>> {
+    Ok(match binding {
+        KeyBinding::Disabled => Vec::new(),
+        KeyBinding::Keyboard { modifiers, usage } => modifiers
+            .usages()
+            .chain((usage != 0).then_some(usage))
+            .map(Key::Keyboard)
+            .collect(),
+        KeyBinding::Consumer { usage } => vec![Key::Consumer(usage)],
+        KeyBinding::System { usage } => vec![Key::System(usage)],
+        KeyBinding::Mouse { .. } | KeyBinding::Macro { .. } => {
+            return Err(Falcon8Error::NotAKeyCombo(binding))
+        }
+    })
+}
+
+/// The keys `binding` holds down, if `keyboard` has every one of them.
+fn sendable_keys<K: VirtualKeyboard + ?Sized>(
+    keyboard: &K,
+    binding: KeyBinding,
+) -> Result<Vec<Key>> {
+    let keys = binding_keys(binding)?;
+    match keys.iter().find(|&&key| !keyboard.can_send(key)) {
+        Some(&key) => Err(Falcon8Error::UnmappedKey(key)),
+        None => Ok(keys),
+    }
+}
+
+/// A keyboard the driver can press keys on.
+///
+/// Implementations only need to press single keys; combos and text are built on top.
+pub trait VirtualKeyboard: Send {
+    /// Presses or releases `key`. Takes effect at the next `sync`.
+    fn set_key(&mut self, key: Key, pressed: bool) -> Result<()>;
+
+    /// Whether this keyboard has `key`, i.e. `set_key` won't refuse it.
+    fn can_send(&self, _key: Key) -> bool {
+        true
+    }
+
+    /// Sends the key changes since the last call, as one report.
+    fn sync(&mut self) -> Result<()>;
+
+    /// Presses and releases `binding`, one key per report, so modifiers are down before the
+    /// key they modify and come up after it. Nothing is pressed if any of the keys can't be.
+    fn tap(&mut self, binding: KeyBinding) -> Result<()> {
+        let keys = sendable_keys(self, binding)?;
+        let presses = keys.iter().map(|&key| (key, true));
+        let releases = keys.iter().rev().map(|&key| (key, false));
+        for (key, pressed) in presses.chain(releases) {
+            self.set_key(key, pressed)?;
+            self.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Taps each of `keystrokes` in turn, after checking this keyboard can send all of them.
+    fn type_keystrokes(&mut self, keystrokes: &[Keystroke]) -> Result<()> {
+        keystrokes
+            .iter()
+            .try_for_each(|&keystroke| sendable_keys(self, keystroke.into()).map(drop))?;
+        keystrokes
+            .iter()
+            .try_for_each(|&keystroke| self.tap(keystroke.into()))
+    }
+
+    /// Types `text` on a computer set to `layout`. Nothing is typed if any of it can't be.
+    fn type_text(&mut self, text: &str, layout: Layout) -> Result<()> {
+        self.type_keystrokes(&layout.keystrokes(text)?)
+    }
+}
+
+/// The system's virtual keyboard.
+pub fn open() -> Result<Box<dyn VirtualKeyboard>> {
+    #[cfg(target_os = "linux")]
+    return Ok(Box::new(UinputKeyboard::open()?));
+    #[cfg(not(target_os = "linux"))]
+    Err(Falcon8Error::ActionUnsupported(
+        "typing on a virtual keyboard",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::falcon8::protocol::{usage, Modifiers};
+
+    const CTRL: Key = Key::Keyboard(usage::keyboard::LEFT_CTRL);
+    const SHIFT: Key = Key::Keyboard(usage::keyboard::LEFT_SHIFT);
+    const M: Key = Key::Keyboard(0x10);
+
+    #[test]
+    fn test_tap_combo() {
+        let mut keyboard = RecordingKeyboard::default();
+        let combo = KeyBinding::combo(Modifiers::LEFT_CTRL | Modifiers::LEFT_SHIFT, 0x10);
+        keyboard.tap(combo).unwrap();
+        assert_eq!(
+            keyboard.reports,
+            [
+                vec![(CTRL, true)],
+                vec![(SHIFT, true)],
+                vec![(M, true)],
+                vec![(M, false)],
+                vec![(SHIFT, false)],
+                vec![(CTRL, false)],
+            ]
+        );
+        assert!(keyboard.held().is_empty());
+
+        keyboard.reports.clear();
+        let volume = Key::Consumer(usage::consumer::VOLUME_UP);
+        keyboard
+            .tap(KeyBinding::Consumer {
+                usage: usage::consumer::VOLUME_UP,
+            })
+            .unwrap();
+        keyboard.tap(KeyBinding::Disabled).unwrap();
+        assert_eq!(
+            keyboard.reports,
+            [vec![(volume, true)], vec![(volume, false)]]
+        );
+    }
+
+    #[test]
+    fn test_unsendable() {
+        let mut keyboard = RecordingKeyboard::default();
+        let binding = KeyBinding::Macro { slot: 1 };
+        assert_eq!(
+            keyboard.tap(binding),
+            Err(Falcon8Error::NotAKeyCombo(binding))
+        );
+        assert!(keyboard.reports.is_empty());
+    }
+
+    #[test]
+    fn test_unmapped_key() {
+        let mut keyboard = RecordingKeyboard::default();
+        keyboard.rejected.insert(M);
+        let combo = KeyBinding::combo(Modifiers::LEFT_CTRL | Modifiers::LEFT_SHIFT, 0x10);
+        assert_eq!(keyboard.tap(combo), Err(Falcon8Error::UnmappedKey(M)));
+        assert!(keyboard.held().is_empty());
+
+        // The "a" isn't typed either.
+        assert_eq!(
+            keyboard.type_text("am", Layout::Us),
+            Err(Falcon8Error::UnmappedKey(M))
+        );
+        assert!(keyboard.reports.is_empty());
+    }
+
+    #[test]
+    fn test_type_text() {
+        let mut keyboard = RecordingKeyboard::default();
+        keyboard.type_text("Hi!\n", Layout::Us).unwrap();
+        assert_eq!(
+            keyboard.typed(),
+            [
+                KeyBinding::combo(Modifiers::LEFT_SHIFT, 0x0B),
+                KeyBinding::key(0x0C),
+                KeyBinding::combo(Modifiers::LEFT_SHIFT, usage::keyboard::N1),
+                KeyBinding::key(usage::keyboard::ENTER),
+            ]
+        );
+
+        keyboard.reports.clear();
+        keyboard.type_text("@", Layout::De).unwrap();
+        assert_eq!(
+            keyboard.typed(),
+            [KeyBinding::combo(Modifiers::RIGHT_ALT, 0x14)]
+        );
+
+        keyboard.reports.clear();
+        assert!(matches!(
+            keyboard.type_text("ok ✓", Layout::Us),
+            Err(Falcon8Error::Untypeable(_))
+        ));
+        assert!(keyboard.reports.is_empty());
+    }
+}
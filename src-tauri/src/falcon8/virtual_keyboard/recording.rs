@@ -0,0 +1,71 @@
+use std::collections::BTreeSet;
+
+use super::{Key, VirtualKeyboard};
+use crate::falcon8::{
+    protocol::{usage, KeyBinding, Modifiers},
+    Falcon8Error, Result,
+};
+
+/// A virtual keyboard that only notes down what it's asked to press.
+#[derive(Debug, Default)]
+pub struct RecordingKeyboard {
+    /// Key changes, grouped by the `sync` that sent them.
+    pub reports: Vec<Vec<(Key, bool)>>,
+    /// Keys this keyboard doesn't have, like ones without a Linux event code.
+    pub rejected: BTreeSet<Key>,
+    pending: Vec<(Key, bool)>,
+}
+
+impl VirtualKeyboard for RecordingKeyboard {
+    fn set_key(&mut self, key: Key, pressed: bool) -> Result<()> {
+        if !self.can_send(key) {
+            return Err(Falcon8Error::UnmappedKey(key));
+        }
+        self.pending.push((key, pressed));
+        Ok(())
+    }
+
+    fn can_send(&self, key: Key) -> bool {
+        !self.rejected.contains(&key)
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            self.reports.push(std::mem::take(&mut self.pending));
+        }
+        Ok(())
+    }
+}
+
+impl RecordingKeyboard {
+    /// Keys still down after the last report.
+    pub fn held(&self) -> BTreeSet<Key> {
+        let mut held = BTreeSet::new();
+        for &(key, pressed) in self.reports.iter().flatten() {
+            if pressed {
+                held.insert(key);
+            } else {
+                held.remove(&key);
+            }
+        }
+        held
+    }
+
+    /// Each keyboard key pressed, with the modifiers that were down at the time.
+    pub fn typed(&self) -> Vec<KeyBinding> {
+        let mut modifiers = Modifiers::NONE;
+        let mut typed = Vec::new();
+        for &(key, pressed) in self.reports.iter().flatten() {
+            let Key::Keyboard(usage) = key else {
+                continue;
+            };
+            match usage.checked_sub(usage::keyboard::LEFT_CTRL) {
+                Some(bit @ 0..=7) if pressed => modifiers |= Modifiers(1 << bit),
+                Some(bit @ 0..=7) => modifiers = Modifiers(modifiers.0 & !(1 << bit)),
+                _ if pressed => typed.push(KeyBinding::combo(modifiers, usage)),
+                _ => {}
+            }
+        }
+        typed
+    }
+}
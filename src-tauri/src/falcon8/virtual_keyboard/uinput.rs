@@ -0,0 +1,136 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    mem,
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    thread,
+    time::Duration,
+};
+
+use super::{keycodes, Key, VirtualKeyboard};
+use crate::falcon8::{Falcon8Error, Result};
+
+const UINPUT_PATH: &str = "/dev/uinput";
+/// Named after the driver rather than the keypad, so the two aren't mistaken for each other.
+const DEVICE_NAME: &[u8] = b"falcon8touch virtual keyboard";
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const SYN_REPORT: u16 = 0;
+const BUS_VIRTUAL: u16 = 0x06;
+
+/// Keys sent right after the keyboard appears are lost, until the desktop has picked it up.
+const SETTLE_TIME: Duration = Duration::from_millis(200);
+
+/// `_IO` and `_IOW` from `asm-generic/ioctl.h`, for uinput's ioctls (type `'U'`).
+const fn io(nr: u32) -> u32 {
+    (b'U' as u32) << 8 | nr
+}
+
+const fn iow<T>(nr: u32) -> u32 {
+    1 << 30 | (mem::size_of::<T>() as u32) << 16 | io(nr)
+}
+
+const UI_DEV_CREATE: u32 = io(1);
+const UI_DEV_DESTROY: u32 = io(2);
+const UI_DEV_SETUP: u32 = iow::<libc::uinput_setup>(3);
+const UI_SET_EVBIT: u32 = iow::<libc::c_int>(100);
+const UI_SET_KEYBIT: u32 = iow::<libc::c_int>(101);
+
+fn uinput_error(error: io::Error) -> Falcon8Error {
+    match error.kind() {
+        io::ErrorKind::PermissionDenied => Falcon8Error::UinputPermissionDenied,
+        io::ErrorKind::NotFound => Falcon8Error::VirtualKeyboard(format!(
+            "{UINPUT_PATH} does not exist, load the uinput kernel module"
+        )),
+        _ => Falcon8Error::VirtualKeyboard(error.to_string()),
+    }
+}
+
+/// A virtual keyboard made with Linux's uinput. It goes away when dropped.
+#[derive(Debug)]
+pub struct UinputKeyboard {
+    file: File,
+}
+
+impl UinputKeyboard {
+    pub fn open() -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(UINPUT_PATH)
+            .map_err(uinput_error)?;
+        let keyboard = Self { file };
+
+        keyboard.ioctl(UI_SET_EVBIT, libc::c_ulong::from(EV_KEY))?;
+        for code in keycodes::all() {
+            keyboard.ioctl(UI_SET_KEYBIT, libc::c_ulong::from(code))?;
+        }
+
+        // SAFETY: `uinput_setup` is plain old data, for which all zeroes is valid.
+        let mut setup: libc::uinput_setup = unsafe { mem::zeroed() };
+        // Vendor and product stay 0, so nothing matching the keypad's IDs picks this up.
+        setup.id.bustype = BUS_VIRTUAL;
+        for (dst, &src) in setup.name.iter_mut().zip(DEVICE_NAME) {
+            *dst = src as libc::c_char;
+        }
+        keyboard.ioctl(UI_DEV_SETUP, &setup as *const _ as libc::c_ulong)?;
+        keyboard.ioctl(UI_DEV_CREATE, 0)?;
+        thread::sleep(SETTLE_TIME);
+        Ok(keyboard)
+    }
+
+    fn ioctl(&self, request: u32, arg: libc::c_ulong) -> Result<()> {
+        // SAFETY: the requests are uinput's, and each gets the argument type it expects: an
+        // integer, or a pointer to a `uinput_setup` that outlives the call.
+        let ret = unsafe { libc::ioctl(self.file.as_raw_fd(), request as _, arg) };
+        if ret < 0 {
+            return Err(uinput_error(io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    fn write_event(&mut self, type_: u16, code: u16, value: i32) -> Result<()> {
+        let event = libc::input_event {
+            // The kernel fills in the time.
+            time: libc::timeval {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            type_,
+            code,
+            value,
+        };
+        // SAFETY: `input_event` is plain old data with no padding on Linux targets.
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const _ as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.file.write_all(bytes).map_err(uinput_error)
+    }
+}
+
+impl VirtualKeyboard for UinputKeyboard {
+    fn set_key(&mut self, key: Key, pressed: bool) -> Result<()> {
+        let code = keycodes::code(key).ok_or(Falcon8Error::UnmappedKey(key))?;
+        self.write_event(EV_KEY, code, i32::from(pressed))
+    }
+
+    fn can_send(&self, key: Key) -> bool {
+        keycodes::code(key).is_some()
+    }
+
+    fn sync(&mut self) -> Result<()> {
+        self.write_event(EV_SYN, SYN_REPORT, 0)
+    }
+}
+
+impl Drop for UinputKeyboard {
+    fn drop(&mut self) {
+        if let Err(e) = self.ioctl(UI_DEV_DESTROY, 0) {
+            eprintln!("Failed to remove the virtual keyboard: {e}");
+        }
+    }
+}
@@ -0,0 +1,2 @@
+pub mod falcon8;
+pub mod state;
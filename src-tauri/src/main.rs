@@ -1,17 +1,134 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-mod falcon8;
+use std::{thread, time::Duration};
 
-// Learn more about Tauri commands at https://tauri.app/v1/guides/features/command
-#[tauri::command]
-fn greet(name: &str) -> String {
-    format!("Hello, {}! You've been greeted from Rust!", name)
+use falcon8touch::{
+    falcon8::{
+        hotplug::{HotplugEvent, HotplugWatcher},
+        DeviceSelector, Falcon8Error,
+    },
+    state::AppState,
+};
+use tauri::{AppHandle, Manager};
+
+mod commands;
+
+/// How often keypads are asked for their active profile while a window is watching, to notice
+/// hardware button presses.
+const PROFILE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Restores the configuration of, and reads key presses from, keypads as they're plugged in, and
+/// tells the frontend.
+fn on_hotplug(handle: &AppHandle, event: &HotplugEvent) {
+    if let Err(e) = handle.state::<AppState>().handle_hotplug(event) {
+        eprintln!("Failed to handle {}: {e}", event.name());
+    }
+    if let HotplugEvent::DeviceAdded(location) = event {
+        let selector = DeviceSelector::PortPath(location.port_path.clone());
+        if let Err(e) = commands::watch_device_keys(handle.clone(), &selector) {
+            eprintln!("Failed to read key presses: {e}");
+        }
+    }
+    if let Err(e) = handle.emit_all(event.name(), event) {
+        eprintln!("Failed to emit {}: {e}", event.name());
+    }
 }
 
 fn main() {
     tauri::Builder::default()
-        .invoke_handler(tauri::generate_handler![greet])
+        .manage(AppState::from_env())
+        .setup(|app| {
+            if let Some(dir) = app.path_resolver().app_config_dir() {
+                let state = app.state::<AppState>();
+                if let Err(e) = state.load_nicknames(dir.join("nicknames.json")) {
+                    eprintln!("Failed to load nicknames: {e}");
+                }
+                if let Err(e) = state.load_host_actions(dir.join("actions.json")) {
+                    eprintln!("Failed to load host-side actions: {e}");
+                }
+            }
+
+            // Key presses are always read, so host-side actions work without the window open.
+            match app.state::<AppState>().list_devices() {
+                Ok(devices) => {
+                    for device in devices {
+                        let selector = DeviceSelector::Index(device.index);
+                        if let Err(e) = commands::watch_device_keys(app.handle(), &selector) {
+                            eprintln!("Failed to read key presses: {e}");
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Failed to list keypads: {e}"),
+            }
+
+            // Simulated keypads never come or go, and shouldn't need libusb.
+            if !AppState::simulating() {
+                let handle = app.handle();
+                let watcher =
+                    rusb::Context::new()
+                        .map_err(Falcon8Error::from)
+                        .and_then(|context| {
+                            HotplugWatcher::start(context, move |event| on_hotplug(&handle, &event))
+                        });
+                match watcher {
+                    Ok(watcher) => {
+                        app.manage(watcher);
+                    }
+                    Err(e) => eprintln!("Hotplug monitoring unavailable: {e}"),
+                }
+            }
+
+            let handle = app.handle();
+            thread::spawn(move || loop {
+                thread::sleep(PROFILE_POLL_INTERVAL);
+                let state = handle.state::<AppState>();
+                if !state.watching_profiles() {
+                    continue;
+                }
+                for change in state.poll_profiles() {
+                    if let Err(e) = handle.emit_all("profile-changed", &change) {
+                        eprintln!("Failed to emit profile-changed: {e}");
+                    }
+                }
+            });
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            commands::list_devices,
+            commands::get_device_info,
+            commands::read_config,
+            commands::write_config,
+            commands::set_key_binding,
+            commands::set_key_map,
+            commands::parse_binding,
+            commands::format_binding,
+            commands::list_profiles,
+            commands::read_profile,
+            commands::write_profile,
+            commands::import_profile,
+            commands::upgrade_profile,
+            commands::export_profile,
+            commands::diff_profile,
+            commands::dump_device,
+            commands::restore_device,
+            commands::set_active_profile,
+            commands::watch_profiles,
+            commands::unwatch_profiles,
+            commands::upload_macro,
+            commands::download_macro,
+            commands::set_lighting,
+            commands::get_lighting,
+            commands::set_effect,
+            commands::set_brightness,
+            commands::set_key_color,
+            commands::set_key_colors,
+            commands::start_animation,
+            commands::stop_animation,
+            commands::watch_keys,
+            commands::unwatch_keys,
+            commands::set_nickname,
+        ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
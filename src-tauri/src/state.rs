@@ -0,0 +1,1265 @@
+//! What the Tauri commands operate on: the set of connected keypads.
+//!
+//! Nothing in here depends on Tauri, so the commands' behaviour can be tested against
+//! simulated devices.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError},
+        Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard,
+    },
+    time::Duration,
+};
+
+use rusb::Context;
+use serde::Serialize;
+
+use crate::falcon8::{
+    actions::{Action, ActionEngine, ActionHost, HostActions, KeyActions},
+    animation::{Animation, EffectConfig},
+    hotplug::HotplugEvent,
+    key_events::{now_millis, KeyEvent, KeyEventReader},
+    migrate_profile,
+    protocol::{Direction, KeyBinding, LightingMode, Macro, Rgb, KEY_COUNT},
+    transport::{SimulatedFalcon8, Transport, UsbTransport},
+    Config, DeviceIdentity, DeviceInfo, DeviceSelector, Dump, Falcon8, Falcon8Error,
+    LightingSettings, LightingState, MigrationReport, Nicknames, Profile, ProfileDiff, ProfileSlot,
+    Result, PID, TIMEOUT, VID,
+};
+
+pub type DynFalcon8 = Falcon8<Box<dyn Transport>>;
+
+type Scanner = dyn Fn() -> Result<Vec<DynFalcon8>> + Send + Sync;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedProfile {
+    pub profile: Profile,
+    /// How the file was upgraded while reading it, if it was written for an older schema. The
+    /// file itself is left as it is.
+    pub migration: Option<MigrationReport>,
+}
+
+/// A keypad switched profiles, most likely from its hardware button.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProfileChanged {
+    /// The keypad's serial number, or its port path if it has none.
+    pub device: String,
+    pub slot: u8,
+}
+
+/// A key press or release on one of the keypads.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceKeyEvent {
+    /// The keypad's serial number, or its port path if it has none.
+    pub device: String,
+    #[serde(flatten)]
+    pub event: KeyEvent,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeviceSummary {
+    pub index: usize,
+    pub product: Option<String>,
+    #[serde(flatten)]
+    pub identity: DeviceIdentity,
+}
+
+/// The bundle identifier from tauri.conf.json, which names the GUI's app config directory.
+pub fn app_identifier() -> String {
+    let config: serde_json::Value = serde_json::from_str(include_str!("../tauri.conf.json"))
+        .expect("tauri.conf.json is valid JSON");
+    config["tauri"]["bundle"]["identifier"]
+        .as_str()
+        .expect("tauri.conf.json has a bundle identifier")
+        .to_string()
+}
+
+/// The app config directory Tauri's path resolver gives the GUI, for use without a Tauri app.
+pub fn app_config_dir() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let config_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home().map(|home| home.join(".config")))
+    };
+    Some(config_dir?.join(app_identifier()))
+}
+
+pub struct AppState {
+    /// `None` for a fixed set of devices that rescanning leaves alone.
+    scanner: Option<Box<Scanner>>,
+    devices: RwLock<Vec<Arc<DynFalcon8>>>,
+    /// The last configuration written to each keypad, keyed by serial number (or port path for
+    /// keypads without one), so it can be put back after the keypad resets or is replugged.
+    last_applied: Mutex<HashMap<String, Config>>,
+    nicknames: Mutex<Nicknames>,
+    /// Running animations, keyed like `last_applied`.
+    animations: Mutex<HashMap<String, Animation>>,
+    /// The active profile of each keypad when `poll_profiles` last looked.
+    active_profiles: Mutex<HashMap<String, u8>>,
+    /// How many frontends are listening for profile switches, which are only worth polling for
+    /// while there are any.
+    profile_watchers: Mutex<usize>,
+    /// Keypads whose key presses are being read, keyed like `last_applied`.
+    key_readers: Mutex<HashMap<String, KeyEventReader>>,
+    /// The host-side actions of each profile written to a keypad, keyed like `last_applied`.
+    host_actions: Mutex<HostActions>,
+}
+
+fn boxed<T: Transport + 'static>(transport: T) -> DynFalcon8 {
+    Falcon8::with_transport(Box::new(transport))
+}
+
+/// Whether two handles reach the same keypad without it having been replugged in between, which
+/// would give it a new bus address.
+fn same_keypad(a: &DynFalcon8, b: &DynFalcon8) -> bool {
+    let usb_a = a.transport.usb_details();
+    usb_a.is_ok() && usb_a == b.transport.usb_details() && {
+        let key_a = a.persistent_key();
+        key_a.is_ok() && key_a == b.persistent_key()
+    }
+}
+
+impl AppState {
+    pub fn new(scanner: impl Fn() -> Result<Vec<DynFalcon8>> + Send + Sync + 'static) -> Self {
+        Self {
+            scanner: Some(Box::new(scanner)),
+            devices: RwLock::new(Vec::new()),
+            last_applied: Mutex::default(),
+            nicknames: Mutex::default(),
+            animations: Mutex::default(),
+            active_profiles: Mutex::default(),
+            profile_watchers: Mutex::default(),
+            key_readers: Mutex::default(),
+            host_actions: Mutex::default(),
+        }
+    }
+
+    pub fn with_devices(devices: Vec<DynFalcon8>) -> Self {
+        Self {
+            scanner: None,
+            devices: RwLock::new(devices.into_iter().map(Arc::new).collect()),
+            last_applied: Mutex::default(),
+            nicknames: Mutex::default(),
+            animations: Mutex::default(),
+            active_profiles: Mutex::default(),
+            profile_watchers: Mutex::default(),
+            key_readers: Mutex::default(),
+            host_actions: Mutex::default(),
+        }
+    }
+
+    /// Keypads plugged into this machine.
+    pub fn usb() -> Self {
+        Self::new(|| {
+            let context = Context::new()?;
+            Ok(UsbTransport::open_all(&context, VID, PID)?
+                .into_iter()
+                .map(boxed)
+                .collect())
+        })
+    }
+
+    /// `count` simulated keypads, for working on the UI without hardware.
+    pub fn simulated(count: usize) -> Self {
+        Self::with_devices(
+            (1..=count)
+                .map(|i| {
+                    let sim = SimulatedFalcon8::new();
+                    sim.set_serial_number(&format!("SIM{i:05}"));
+                    sim.set_location(1, &[i as u8]);
+                    boxed(sim)
+                })
+                .collect(),
+        )
+    }
+
+    /// Simulated keypads when `FALCON8_SIMULATE` is set (to a device count, or anything else for
+    /// one), real ones otherwise.
+    pub fn from_env() -> Self {
+        match env::var("FALCON8_SIMULATE") {
+            Ok(count) => Self::simulated(count.parse().unwrap_or(1)),
+            Err(_) => Self::usb(),
+        }
+    }
+
+    /// Whether `from_env` makes simulated keypads.
+    pub fn simulating() -> bool {
+        env::var("FALCON8_SIMULATE").is_ok()
+    }
+
+    /// Loads nicknames from, and saves changes to, the JSON file at `path`.
+    pub fn load_nicknames(&self, path: impl Into<PathBuf>) -> Result<()> {
+        *self.nicknames() = Nicknames::open(path)?;
+        Ok(())
+    }
+
+    /// Loads host-side actions from, and saves changes to, the JSON file at `path`.
+    pub fn load_host_actions(&self, path: impl Into<PathBuf>) -> Result<()> {
+        *self.host_actions() = HostActions::open(path)?;
+        Ok(())
+    }
+
+    fn nicknames(&self) -> MutexGuard<'_, Nicknames> {
+        self.nicknames.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Rescans for keypads. Ones that are still plugged in where they were keep their existing
+    /// handle, so key readers and animations running on them hold on to their claims.
+    pub fn refresh(&self) -> Result<()> {
+        let Some(scanner) = &self.scanner else {
+            return Ok(());
+        };
+
+        let found = scanner()?;
+        let mut devices = self.devices.write().unwrap_or_else(|e| e.into_inner());
+        let refreshed = found
+            .into_iter()
+            .map(|device| {
+                devices
+                    .iter()
+                    .find(|known| same_keypad(known, &device))
+                    .map_or_else(|| Arc::new(device), Arc::clone)
+            })
+            .collect();
+        *devices = refreshed;
+        Ok(())
+    }
+
+    fn devices(&self) -> RwLockReadGuard<'_, Vec<Arc<DynFalcon8>>> {
+        self.devices.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn identities(&self, devices: &[Arc<DynFalcon8>]) -> Result<Vec<DeviceIdentity>> {
+        let nicknames = self.nicknames();
+        devices
+            .iter()
+            .map(|device| device.identity(&nicknames))
+            .collect()
+    }
+
+    pub fn device(&self, selector: &DeviceSelector) -> Result<Arc<DynFalcon8>> {
+        let devices = self.devices();
+        let index = match selector {
+            DeviceSelector::Index(index) if *index < devices.len() => *index,
+            DeviceSelector::Index(_) => {
+                return Err(Falcon8Error::NoMatchingDevice(selector.to_string()))
+            }
+            selector => selector.find(&self.identities(&devices)?)?,
+        };
+        Ok(Arc::clone(&devices[index]))
+    }
+
+    pub fn list_devices(&self) -> Result<Vec<DeviceSummary>> {
+        self.refresh()?;
+
+        let devices = self.devices();
+        let identities = self.identities(&devices)?;
+        devices
+            .iter()
+            .zip(identities)
+            .enumerate()
+            .map(|(index, (device, identity))| {
+                Ok(DeviceSummary {
+                    index,
+                    product: device.transport.read_strings(TIMEOUT)?.product,
+                    identity,
+                })
+            })
+            .collect()
+    }
+
+    /// Names the selected keypad, or removes its name if `nickname` is `None`.
+    pub fn set_nickname(&self, selector: &DeviceSelector, nickname: Option<&str>) -> Result<()> {
+        let key = self.device(selector)?.persistent_key()?;
+        self.nicknames().set(&key, nickname)
+    }
+
+    /// Records `device`'s current configuration as the one to restore on reconnect.
+    fn remember(&self, device: &DynFalcon8) -> Result<()> {
+        let key = device.persistent_key()?;
+        let config = device.read_config()?;
+        self.last_applied
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, config);
+        Ok(())
+    }
+
+    /// Rescans after a keypad comes or goes, and writes the last applied configuration back to
+    /// a keypad that has just (re)appeared.
+    pub fn handle_hotplug(&self, event: &HotplugEvent) -> Result<()> {
+        self.refresh()?;
+
+        let HotplugEvent::DeviceAdded(location) = event else {
+            return Ok(());
+        };
+        let device = self.devices().iter().find_map(|device| {
+            device
+                .transport
+                .usb_details()
+                .is_ok_and(|usb| usb.port_path() == location.port_path)
+                .then(|| Arc::clone(device))
+        });
+        let Some(device) = device else {
+            return Ok(());
+        };
+
+        let saved = self
+            .last_applied
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(&device.persistent_key()?)
+            .cloned();
+        match saved {
+            Some(config) => device.write_config(&config),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_device_info(&self, selector: &DeviceSelector) -> Result<DeviceInfo> {
+        self.device(selector)?.device_info()
+    }
+
+    pub fn read_config(&self, selector: &DeviceSelector) -> Result<Config> {
+        self.device(selector)?.read_config()
+    }
+
+    pub fn write_config(&self, selector: &DeviceSelector, config: &Config) -> Result<()> {
+        let device = self.device(selector)?;
+        device.write_config(config)?;
+        self.remember(&device)
+    }
+
+    pub fn set_key_binding(
+        &self,
+        selector: &DeviceSelector,
+        key: usize,
+        binding: KeyBinding,
+    ) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_key_binding(key, binding)?;
+        self.remember(&device)
+    }
+
+    pub fn set_key_map(
+        &self,
+        selector: &DeviceSelector,
+        keys: &[KeyBinding; KEY_COUNT],
+    ) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_key_map(keys)?;
+        self.remember(&device)
+    }
+
+    /// The keypad's profile slots, and which of them is active.
+    pub fn profiles(&self, selector: &DeviceSelector) -> Result<Vec<ProfileSlot>> {
+        self.device(selector)?.profiles()
+    }
+
+    /// Reads profile `slot`, along with the host-side actions last written with it.
+    pub fn read_profile(&self, selector: &DeviceSelector, slot: u8) -> Result<Profile> {
+        let device = self.device(selector)?;
+        let mut profile = device.read_profile(slot)?;
+        if let Some(actions) = self.host_actions().get(&device.persistent_key()?, slot) {
+            profile.actions = actions.clone();
+        }
+        Ok(profile)
+    }
+
+    pub fn write_profile(
+        &self,
+        selector: &DeviceSelector,
+        slot: u8,
+        profile: &Profile,
+    ) -> Result<()> {
+        let device = self.device(selector)?;
+        device.write_profile(slot, profile)?;
+        self.host_actions()
+            .set(&device.persistent_key()?, slot, &profile.actions)?;
+        self.remember(&device)
+    }
+
+    /// Writes the profile file at `path` to profile `slot`. Files written for an older schema
+    /// are upgraded in memory; `upgrade_profile_file` is what upgrades them on disk.
+    pub fn import_profile(
+        &self,
+        selector: &DeviceSelector,
+        slot: u8,
+        path: &Path,
+    ) -> Result<ImportedProfile> {
+        let text = fs::read_to_string(path).map_err(|e| Falcon8Error::file(path, e))?;
+        let (migrated, report) = migrate_profile(&text)?;
+        let profile = Profile::from_toml(&migrated)?;
+        self.write_profile(selector, slot, &profile)?;
+        let migration = (report.from_version != report.to_version).then_some(report);
+        Ok(ImportedProfile { profile, migration })
+    }
+
+    /// Saves profile `slot` as a profile file at `path`.
+    pub fn export_profile(&self, selector: &DeviceSelector, slot: u8, path: &Path) -> Result<()> {
+        self.read_profile(selector, slot)?.save(path)
+    }
+
+    /// How profile `slot`, or the active profile if `None`, differs from the profile file at
+    /// `path`.
+    pub fn diff_profile(
+        &self,
+        selector: &DeviceSelector,
+        slot: Option<u8>,
+        path: &Path,
+    ) -> Result<ProfileDiff> {
+        let expected = Profile::load(path)?;
+        let device = self.device(selector)?;
+        let slot = match slot {
+            Some(slot) => slot,
+            None => device.active_profile()?,
+        };
+        Ok(expected.diff(&device.read_profile(slot)?))
+    }
+
+    /// Saves the keypad's whole configuration memory to a dump file at `path`.
+    pub fn dump_device(&self, selector: &DeviceSelector, path: &Path) -> Result<()> {
+        self.device(selector)?.dump()?.save(path)
+    }
+
+    /// Writes a dump file back to the keypad, if it is intact and was taken from a compatible
+    /// keypad.
+    pub fn restore_device(&self, selector: &DeviceSelector, path: &Path) -> Result<()> {
+        let dump = Dump::load(path)?;
+        let device = self.device(selector)?;
+        device.restore(&dump)?;
+        self.remember(&device)
+    }
+
+    pub fn set_active_profile(&self, selector: &DeviceSelector, slot: u8) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_active_profile(slot)?;
+        self.active_profiles
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(device.persistent_key()?, slot);
+        Ok(())
+    }
+
+    /// Notes that a frontend wants to hear about profile switches, until `unwatch_profiles`.
+    /// Switches made while nobody was watching aren't reported.
+    pub fn watch_profiles(&self) {
+        let mut watchers = self.profile_watchers();
+        if *watchers == 0 {
+            self.active_profiles
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clear();
+        }
+        *watchers += 1;
+    }
+
+    pub fn unwatch_profiles(&self) {
+        let mut watchers = self.profile_watchers();
+        *watchers = watchers.saturating_sub(1);
+    }
+
+    /// Whether any frontend is watching for profile switches, i.e. `poll_profiles` is worth
+    /// calling.
+    pub fn watching_profiles(&self) -> bool {
+        *self.profile_watchers() > 0
+    }
+
+    fn profile_watchers(&self) -> MutexGuard<'_, usize> {
+        self.profile_watchers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Checks every keypad's active profile, reporting the ones that changed since the last
+    /// call. Keypads seen for the first time aren't reported.
+    pub fn poll_profiles(&self) -> Vec<ProfileChanged> {
+        let devices = self.devices().clone();
+        let mut known = self
+            .active_profiles
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        devices
+            .iter()
+            .filter_map(|device| {
+                let key = device.persistent_key().ok()?;
+                let slot = device.active_profile().ok()?;
+                match known.insert(key.clone(), slot) {
+                    Some(previous) if previous != slot => {
+                        Some(ProfileChanged { device: key, slot })
+                    }
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    pub fn upload_macro(&self, selector: &DeviceSelector, slot: usize, m: &Macro) -> Result<()> {
+        self.device(selector)?.upload_macro(slot, m)
+    }
+
+    pub fn download_macro(&self, selector: &DeviceSelector, slot: usize) -> Result<Option<Macro>> {
+        self.device(selector)?.download_macro(slot)
+    }
+
+    pub fn set_lighting(
+        &self,
+        selector: &DeviceSelector,
+        lighting: LightingSettings,
+    ) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_lighting(lighting)?;
+        self.remember(&device)
+    }
+
+    pub fn lighting(&self, selector: &DeviceSelector) -> Result<LightingState> {
+        self.device(selector)?.lighting()
+    }
+
+    pub fn set_effect(
+        &self,
+        selector: &DeviceSelector,
+        mode: LightingMode,
+        speed: u8,
+        direction: Direction,
+    ) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_effect(mode, speed, direction)?;
+        self.remember(&device)
+    }
+
+    pub fn set_brightness(&self, selector: &DeviceSelector, brightness: u8) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_brightness(brightness)?;
+        self.remember(&device)
+    }
+
+    pub fn set_key_color(&self, selector: &DeviceSelector, key: usize, color: Rgb) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_key_color(key, color)?;
+        self.remember(&device)
+    }
+
+    pub fn set_key_colors(
+        &self,
+        selector: &DeviceSelector,
+        colors: &[Rgb; KEY_COUNT],
+    ) -> Result<()> {
+        let device = self.device(selector)?;
+        device.set_key_colors(colors)?;
+        self.remember(&device)
+    }
+
+    /// Streams `effect` to the selected keypad, replacing any animation already running there.
+    pub fn start_animation(
+        &self,
+        selector: &DeviceSelector,
+        effect: EffectConfig,
+        fps: u32,
+    ) -> Result<()> {
+        let device = self.device(selector)?;
+        let key = device.persistent_key()?;
+        let running = self.animations().remove(&key);
+        // Stopping waits for the current frame, so it's done without holding the lock. The old
+        // animation failing is no reason not to start the new one.
+        if let Some(Err(e)) = running.map(Animation::stop) {
+            eprintln!("Animation on {key} had failed: {e}");
+        }
+
+        let animation = Animation::start(device, effect.into_effect(), fps)?;
+        // One started by another call in the meantime is stopped when dropped, after the lock
+        // is released.
+        let _replaced = self.animations().insert(key, animation);
+        Ok(())
+    }
+
+    fn animations(&self) -> MutexGuard<'_, HashMap<String, Animation>> {
+        self.animations.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn stop_animation(&self, selector: &DeviceSelector) -> Result<()> {
+        let key = self.device(selector)?.persistent_key()?;
+        let running = self.animations().remove(&key);
+        match running {
+            Some(animation) => animation.stop(),
+            None => Ok(()),
+        }
+    }
+
+    fn host_actions(&self) -> MutexGuard<'_, HostActions> {
+        self.host_actions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The keypad with persistent key `key`, if it's still connected.
+    fn device_by_key(&self, key: &str) -> Option<Arc<DynFalcon8>> {
+        self.devices()
+            .iter()
+            .find(|device| device.persistent_key().is_ok_and(|k| k == key))
+            .cloned()
+    }
+
+    /// The host-side actions of key `key_index` in the active profile of the keypad with
+    /// persistent key `device`.
+    fn key_actions(&self, device: &str, key_index: usize) -> KeyActions {
+        let Some(slot) = self
+            .device_by_key(device)
+            .and_then(|falcon| falcon.active_profile().ok())
+        else {
+            return KeyActions::default();
+        };
+        self.host_actions()
+            .get(device, slot)
+            .and_then(|actions| actions.get(key_index))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn run_action(&self, device: &str, action: &Action, host: &mut impl ActionHost) -> Result<()> {
+        match action {
+            Action::Shell { command } => host.run_command(command),
+            Action::Url { url } => host.open_url(url),
+            Action::Text {
+                text,
+                layout,
+                unicode_input: true,
+            } => host.type_keystrokes(&layout.keystrokes_or_unicode(text)),
+            Action::Text { text, layout, .. } => host.type_keystrokes(&layout.keystrokes(text)?),
+            Action::Combo { combo } => host.send_combo(*combo),
+            // Left for `poll_profiles` to notice, so the switch is reported like one made
+            // with the hardware button.
+            Action::Profile { slot } => self
+                .device_by_key(device)
+                .ok_or(Falcon8Error::Disconnected)?
+                .set_active_profile(*slot),
+        }
+    }
+
+    /// Runs the host-side actions that key events from `watch_keys` trigger, until `events`
+    /// ends. `on_event` sees every event first, e.g. to show it in a key tester.
+    pub fn run_key_events(
+        &self,
+        events: Receiver<DeviceKeyEvent>,
+        host: &mut impl ActionHost,
+        mut on_event: impl FnMut(&DeviceKeyEvent),
+    ) {
+        let mut engine = ActionEngine::default();
+        let mut device = String::new();
+
+        loop {
+            let received = match engine.next_deadline() {
+                Some(deadline) => {
+                    let wait = deadline.saturating_sub(now_millis());
+                    events.recv_timeout(Duration::from_millis(wait))
+                }
+                None => events.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let actions = match received {
+                Ok(event) => {
+                    on_event(&event);
+                    device = event.device;
+                    let key_index = event.event.key_index;
+                    let lookup = || self.key_actions(&device, key_index);
+                    engine.key_event(&event.event, lookup).into_iter().collect()
+                }
+                Err(RecvTimeoutError::Timeout) => engine.expire(now_millis()),
+                Err(RecvTimeoutError::Disconnected) => return,
+            };
+
+            for action in actions {
+                if let Err(e) = self.run_action(&device, &action, host) {
+                    eprintln!("Failed to run {action:?}: {e}");
+                }
+            }
+        }
+    }
+
+    /// Starts reading key presses from the selected keypad, replacing any earlier reader for
+    /// it. Events come out of the returned channel until `unwatch_keys` is called or the keypad
+    /// goes away.
+    pub fn watch_keys(&self, selector: &DeviceSelector) -> Result<Receiver<DeviceKeyEvent>> {
+        let device = self.device(selector)?;
+        let key = device.persistent_key()?;
+        let mut readers = self.key_readers.lock().unwrap_or_else(|e| e.into_inner());
+        readers.remove(&key);
+
+        let (sender, events) = mpsc::channel();
+        let name = key.clone();
+        let reader = KeyEventReader::start(device, move |event| {
+            let _ = sender.send(DeviceKeyEvent {
+                device: name.clone(),
+                event,
+            });
+        })?;
+        readers.insert(key, reader);
+        Ok(events)
+    }
+
+    pub fn unwatch_keys(&self, selector: &DeviceSelector) -> Result<()> {
+        let key = self.device(selector)?.persistent_key()?;
+        let reader = self
+            .key_readers
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&key);
+        match reader {
+            Some(reader) => reader.stop(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    use super::*;
+    use crate::falcon8::{
+        actions::SystemHost,
+        hotplug::DeviceLocation,
+        protocol::{
+            usage, Direction, FeatureReport, KeyStateReport, Keystroke, Layout, LightingMode,
+            Modifiers, ProfileStateReport,
+        },
+        virtual_keyboard::{Key, RecordingKeyboard, VirtualKeyboard},
+    };
+
+    const FIRST: DeviceSelector = DeviceSelector::Index(0);
+
+    fn summary(index: usize, serial_number: &str, port_path: &str) -> DeviceSummary {
+        DeviceSummary {
+            index,
+            product: Some("Falcon-8".to_string()),
+            identity: DeviceIdentity {
+                serial_number: Some(serial_number.to_string()),
+                port_path: port_path.to_string(),
+                nickname: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_list_devices() {
+        let state = AppState::simulated(2);
+        let devices = state.list_devices().unwrap();
+        assert_eq!(
+            devices,
+            [summary(0, "SIM00001", "1-1"), summary(1, "SIM00002", "1-2"),]
+        );
+    }
+
+    #[test]
+    fn test_refresh_replaces_devices() {
+        let state = AppState::new(|| Ok(vec![boxed(SimulatedFalcon8::new())]));
+        assert_eq!(
+            state.device(&FIRST).err(),
+            Some(Falcon8Error::NoMatchingDevice("0".to_string()))
+        );
+
+        assert_eq!(state.list_devices().unwrap().len(), 1);
+        assert!(state.device(&FIRST).is_ok());
+    }
+
+    #[test]
+    fn test_refresh_keeps_watched_devices() {
+        let sim = SimulatedFalcon8::new();
+        let state = AppState::new({
+            let sim = sim.reopen();
+            move || Ok(vec![boxed(sim.reopen())])
+        });
+        state.refresh().unwrap();
+        let events = state.watch_keys(&FIRST).unwrap();
+
+        assert_eq!(state.list_devices().unwrap().len(), 1);
+        state
+            .set_key_binding(&FIRST, 0, KeyBinding::Disabled)
+            .unwrap();
+        sim.push_interrupt(0x83, &KeyStateReport { pressed: 0b1 }.encode());
+        let event = events.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!((event.event.key_index, event.event.pressed), (0, true));
+    }
+
+    #[test]
+    fn test_get_device_info() {
+        let state = AppState::simulated(1);
+        let info = state.get_device_info(&FIRST).unwrap();
+        assert_eq!(info.firmware.protocol_version, 1);
+        assert_eq!(info.manufacturer.as_deref(), Some("Simulated"));
+        assert_eq!(
+            state.get_device_info(&DeviceSelector::Index(1)),
+            Err(Falcon8Error::NoMatchingDevice("1".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_config_commands() {
+        let state = AppState::simulated(1);
+
+        let mut config = state.read_config(&FIRST).unwrap();
+        config.colors[0] = [1, 2, 3];
+        state.write_config(&FIRST, &config).unwrap();
+
+        let binding = KeyBinding::combo(Modifiers::LEFT_SHIFT, usage::keyboard::A + 1);
+
+        state.set_key_binding(&FIRST, 1, binding).unwrap();
+        let lighting = LightingSettings {
+            mode: LightingMode::Wave,
+            speed: 1,
+            direction: Direction::LeftToRight,
+            brightness: 200,
+        };
+        state.set_lighting(&FIRST, lighting).unwrap();
+
+        let config = state.read_config(&FIRST).unwrap();
+        assert_eq!(config.colors[0], [1, 2, 3]);
+        assert_eq!(config.keys[1], binding);
+        assert_eq!(config.lighting, lighting);
+    }
+
+    #[test]
+    fn test_animation_commands() {
+        let state = AppState::simulated(1);
+        let effect = EffectConfig::Rainbow { period_ms: 1000 };
+
+        assert_eq!(
+            state.start_animation(&FIRST, effect, 0),
+            Err(Falcon8Error::InvalidFrameRate(0))
+        );
+        state.start_animation(&FIRST, effect, 30).unwrap();
+        state.start_animation(&FIRST, effect, 60).unwrap();
+        state.stop_animation(&FIRST).unwrap();
+        state.stop_animation(&FIRST).unwrap();
+    }
+
+    #[test]
+    fn test_poll_profiles() {
+        let state = AppState::simulated(1);
+        let device = state.device(&FIRST).unwrap();
+        // What the hardware button does, from the host's point of view.
+        let press_button = |active_slot| {
+            device
+                .write_report(&ProfileStateReport {
+                    active_slot,
+                    edit_slot: 0,
+                    slot_count: 4,
+                })
+                .unwrap()
+        };
+
+        assert!(state.poll_profiles().is_empty());
+        press_button(1);
+        assert_eq!(
+            state.poll_profiles(),
+            [ProfileChanged {
+                device: "SIM00001".to_string(),
+                slot: 1,
+            }]
+        );
+        assert!(state.poll_profiles().is_empty());
+
+        // Switching from the app isn't reported back.
+        state.set_active_profile(&FIRST, 3).unwrap();
+        assert!(state.poll_profiles().is_empty());
+        assert_eq!(device.active_profile().unwrap(), 3);
+    }
+
+    #[test]
+    fn test_watch_profiles() {
+        let state = AppState::simulated(1);
+        let device = state.device(&FIRST).unwrap();
+        assert!(!state.watching_profiles());
+
+        state.watch_profiles();
+        state.watch_profiles();
+        state.unwatch_profiles();
+        assert!(state.watching_profiles());
+        state.poll_profiles();
+        state.unwatch_profiles();
+        assert!(!state.watching_profiles());
+        state.unwatch_profiles();
+        assert!(!state.watching_profiles());
+
+        // A switch nobody was watching for isn't reported once someone is.
+        device.set_active_profile(2).unwrap();
+        state.watch_profiles();
+        assert!(state.poll_profiles().is_empty());
+        device.set_active_profile(1).unwrap();
+        assert_eq!(state.poll_profiles().len(), 1);
+    }
+
+    #[test]
+    fn test_profile_commands() {
+        let state = AppState::simulated(1);
+        assert_eq!(state.profiles(&FIRST).unwrap().len(), 4);
+
+        let mut profile = state.read_profile(&FIRST, 1).unwrap();
+        profile.colors = [[9; 3]; KEY_COUNT];
+        state.write_profile(&FIRST, 1, &profile).unwrap();
+        assert_eq!(state.read_profile(&FIRST, 1).unwrap(), profile);
+    }
+
+    #[test]
+    fn test_import_and_export_profile() {
+        let state = AppState::simulated(1);
+        let path = std::env::temp_dir().join(format!("falcon8-export-{}.toml", std::process::id()));
+
+        let mut profile = state.read_profile(&FIRST, 1).unwrap();
+        profile.colors = [[9; 3]; KEY_COUNT];
+        state.write_profile(&FIRST, 1, &profile).unwrap();
+        state.export_profile(&FIRST, 1, &path).unwrap();
+
+        let imported = state.import_profile(&FIRST, 3, &path).unwrap();
+        assert_eq!(imported.profile, profile);
+        assert_eq!(imported.migration, None);
+        assert_eq!(state.read_profile(&FIRST, 3).unwrap(), profile);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_import_leaves_file_alone() {
+        let state = AppState::simulated(1);
+        let dir = std::env::temp_dir().join(format!("falcon8-import-{}", std::process::id()));
+        let path = dir.join("golden.toml");
+        let v1 = include_str!("falcon8/profile_file/testdata/v1.toml");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, v1).unwrap();
+
+        let imported = state.import_profile(&FIRST, 2, &path).unwrap();
+        assert_eq!(imported.migration, None);
+        assert_eq!(state.read_profile(&FIRST, 2).unwrap(), imported.profile);
+
+        // Reading the file left it alone.
+        assert_eq!(fs::read_to_string(&path).unwrap(), v1);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_diff_profile() {
+        let state = AppState::simulated(1);
+        let path = std::env::temp_dir().join(format!("falcon8-diff-{}.toml", std::process::id()));
+        state.export_profile(&FIRST, 0, &path).unwrap();
+        assert!(state.diff_profile(&FIRST, None, &path).unwrap().is_empty());
+
+        state
+            .set_key_binding(&FIRST, 2, KeyBinding::Disabled)
+            .unwrap();
+        let diff = state.diff_profile(&FIRST, Some(0), &path).unwrap();
+        assert_eq!(diff.to_string(), "key3: expected F15, found None\n");
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_dump_and_restore() {
+        let state = AppState::simulated(1);
+        let path = std::env::temp_dir().join(format!("falcon8-state-dump-{}", std::process::id()));
+        let config = state.read_config(&FIRST).unwrap();
+        state.dump_device(&FIRST, &path).unwrap();
+
+        state
+            .set_key_binding(&FIRST, 0, KeyBinding::Disabled)
+            .unwrap();
+        state.restore_device(&FIRST, &path).unwrap();
+        assert_eq!(state.read_config(&FIRST).unwrap(), config);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_watch_keys() {
+        let state = AppState::simulated(1);
+        let events = state.watch_keys(&FIRST).unwrap();
+        assert_eq!(events.try_recv(), Err(mpsc::TryRecvError::Empty));
+        // Configuration keeps working while the reader holds the interface.
+        state
+            .set_key_binding(&FIRST, 0, KeyBinding::Disabled)
+            .unwrap();
+
+        let replaced = events;
+        let events = state.watch_keys(&FIRST).unwrap();
+        assert_eq!(replaced.recv(), Err(mpsc::RecvError));
+
+        state.unwatch_keys(&FIRST).unwrap();
+        assert_eq!(events.recv(), Err(mpsc::RecvError));
+        state.unwatch_keys(&FIRST).unwrap();
+    }
+
+    /// Notes down actions instead of running them.
+    #[derive(Default)]
+    struct RecordingHost(Vec<String>);
+
+    impl ActionHost for RecordingHost {
+        fn run_command(&mut self, command: &str) -> Result<()> {
+            self.0.push(format!("run {command}"));
+            Ok(())
+        }
+
+        fn open_url(&mut self, url: &str) -> Result<()> {
+            self.0.push(format!("open {url}"));
+            Ok(())
+        }
+
+        fn type_keystrokes(&mut self, keystrokes: &[Keystroke]) -> Result<()> {
+            let keystrokes: Vec<String> = keystrokes.iter().map(Keystroke::to_string).collect();
+            self.0.push(format!("type {}", keystrokes.join(" ")));
+            Ok(())
+        }
+
+        fn send_combo(&mut self, combo: KeyBinding) -> Result<()> {
+            self.0.push(format!("send {combo}"));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_key_events() {
+        let state = AppState::simulated(1);
+        let mut profile = state.read_profile(&FIRST, 1).unwrap();
+        profile.actions[0] = KeyActions {
+            press: Some(Action::Shell {
+                command: "echo hi".to_string(),
+            }),
+            release: Some(Action::Url {
+                url: "https://example.com".to_string(),
+            }),
+            ..KeyActions::default()
+        };
+        profile.actions[1].long_press = Some(Action::Text {
+            text: "zé".to_string(),
+            layout: Layout::De,
+            unicode_input: false,
+        });
+        profile.actions[2].press = Some(Action::Profile { slot: 2 });
+        state.write_profile(&FIRST, 1, &profile).unwrap();
+        state.set_active_profile(&FIRST, 1).unwrap();
+        assert_eq!(state.read_profile(&FIRST, 1).unwrap(), profile);
+        assert!(state.read_profile(&FIRST, 2).unwrap().actions[0].is_empty());
+
+        let (sender, events) = mpsc::channel();
+        let now = now_millis();
+        for (key_index, pressed, timestamp) in [
+            (0, true, now),
+            (0, false, now),
+            // Held since long before now.
+            (1, true, 0),
+            (1, false, now),
+            (2, true, now),
+            // Profile 2 has no actions.
+            (0, true, now),
+        ] {
+            let event = KeyEvent {
+                key_index,
+                pressed,
+                timestamp,
+            };
+            let device = "SIM00001".to_string();
+            sender.send(DeviceKeyEvent { device, event }).unwrap();
+        }
+        drop(sender);
+
+        let mut host = RecordingHost::default();
+        let mut seen = 0;
+        state.run_key_events(events, &mut host, |_| seen += 1);
+        assert_eq!(seen, 6);
+        assert_eq!(
+            host.0,
+            ["run echo hi", "open https://example.com", "type Y Equal E"]
+        );
+        assert_eq!(state.device(&FIRST).unwrap().active_profile(), Ok(2));
+    }
+
+    /// A `RecordingKeyboard` that can be read after handing it to a `SystemHost`.
+    #[derive(Clone, Default)]
+    struct SharedKeyboard(Arc<Mutex<RecordingKeyboard>>);
+
+    impl VirtualKeyboard for SharedKeyboard {
+        fn set_key(&mut self, key: Key, pressed: bool) -> Result<()> {
+            self.0.lock().unwrap().set_key(key, pressed)
+        }
+
+        fn sync(&mut self) -> Result<()> {
+            self.0.lock().unwrap().sync()
+        }
+    }
+
+    #[test]
+    fn test_run_key_events_on_keyboard() {
+        let state = AppState::simulated(1);
+        let mut profile = state.read_profile(&FIRST, 1).unwrap();
+        profile.actions[0].press = Some(Action::Combo {
+            combo: KeyBinding::combo(Modifiers::LEFT_CTRL, 0x06),
+        });
+        profile.actions[1].press = Some(Action::Text {
+            text: "Hi".to_string(),
+            layout: Layout::Us,
+            unicode_input: false,
+        });
+        state.write_profile(&FIRST, 1, &profile).unwrap();
+        state.set_active_profile(&FIRST, 1).unwrap();
+
+        let (sender, events) = mpsc::channel();
+        for key_index in [0, 1] {
+            for pressed in [true, false] {
+                let event = KeyEvent {
+                    key_index,
+                    pressed,
+                    timestamp: now_millis(),
+                };
+                let device = "SIM00001".to_string();
+                sender.send(DeviceKeyEvent { device, event }).unwrap();
+            }
+        }
+        drop(sender);
+
+        let keyboard = SharedKeyboard::default();
+        let mut host = SystemHost::with_keyboard(Box::new(keyboard.clone()));
+        state.run_key_events(events, &mut host, |_| {});
+        let keyboard = keyboard.0.lock().unwrap();
+        assert_eq!(
+            keyboard.typed(),
+            [
+                KeyBinding::combo(Modifiers::LEFT_CTRL, 0x06),
+                KeyBinding::combo(Modifiers::LEFT_SHIFT, 0x0B),
+                KeyBinding::key(0x0C),
+            ]
+        );
+        assert!(keyboard.held().is_empty());
+    }
+
+    #[test]
+    fn test_host_actions_persist() {
+        let dir = std::env::temp_dir().join(format!("falcon8-actions-{}", std::process::id()));
+        let path = dir.join("actions.json");
+
+        let state = AppState::simulated(1);
+        state.load_host_actions(&path).unwrap();
+        let mut profile = state.read_profile(&FIRST, 2).unwrap();
+        profile.actions[3].press = Some(Action::Url {
+            url: "https://example.com".to_string(),
+        });
+        state.write_profile(&FIRST, 2, &profile).unwrap();
+
+        let restarted = AppState::simulated(1);
+        restarted.load_host_actions(&path).unwrap();
+        assert_eq!(
+            restarted.read_profile(&FIRST, 2).unwrap().actions,
+            profile.actions
+        );
+
+        // Clearing them all forgets the keypad.
+        restarted
+            .write_profile(&FIRST, 2, &state.read_profile(&FIRST, 1).unwrap())
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "{}");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_hotplug_restores_last_applied_config() {
+        // Every scan finds a freshly reset keypad with the same serial number, enumerated anew.
+        let scans = AtomicU8::new(5);
+        let state = AppState::new(move || {
+            let sim = SimulatedFalcon8::new();
+            sim.set_serial_number("SIM00001");
+            sim.set_address(scans.fetch_add(1, Ordering::Relaxed));
+            Ok(vec![boxed(sim)])
+        });
+        state.refresh().unwrap();
+
+        let lighting = LightingSettings {
+            mode: LightingMode::Breathing,
+            speed: 4,
+            direction: Direction::RightToLeft,
+            brightness: 50,
+        };
+        state.set_lighting(&FIRST, lighting).unwrap();
+        let applied = state.read_config(&FIRST).unwrap();
+
+        let location = DeviceLocation {
+            bus_number: 1,
+            address: 5,
+            port_path: "1-2".to_string(),
+        };
+        state
+            .handle_hotplug(&HotplugEvent::DeviceRemoved(location.clone()))
+            .unwrap();
+        assert_ne!(state.read_config(&FIRST).unwrap(), applied);
+
+        state
+            .handle_hotplug(&HotplugEvent::DeviceAdded(location))
+            .unwrap();
+        assert_eq!(state.read_config(&FIRST).unwrap(), applied);
+    }
+
+    #[test]
+    fn test_select_devices() {
+        let state = AppState::simulated(3);
+        let serial = |selector: &str| {
+            let device = state.device(&selector.parse().unwrap())?;
+            device.persistent_key()
+        };
+
+        assert_eq!(serial("2").unwrap(), "SIM00003");
+        assert_eq!(serial("serial:SIM00002").unwrap(), "SIM00002");
+        assert_eq!(serial("1-3").unwrap(), "SIM00003");
+        assert_eq!(
+            serial("3"),
+            Err(Falcon8Error::NoMatchingDevice("3".to_string()))
+        );
+        assert_eq!(
+            serial("desk"),
+            Err(Falcon8Error::NoMatchingDevice("desk".to_string()))
+        );
+
+        state
+            .set_nickname(&"SIM00002".parse().unwrap(), Some("desk"))
+            .unwrap();
+        assert_eq!(serial("desk").unwrap(), "SIM00002");
+        assert_eq!(
+            state.list_devices().unwrap()[1]
+                .identity
+                .nickname
+                .as_deref(),
+            Some("desk")
+        );
+
+        state
+            .set_nickname(&"name:desk".parse().unwrap(), None)
+            .unwrap();
+        assert!(serial("desk").is_err());
+    }
+
+    #[test]
+    fn test_app_config_dir() {
+        let identifier = app_identifier();
+        assert!(!identifier.is_empty());
+        if let Some(dir) = app_config_dir() {
+            assert!(dir.ends_with(&identifier), "{}", dir.display());
+        }
+    }
+
+    #[test]
+    fn test_errors_serialize_to_messages() {
+        let state = AppState::simulated(1);
+        let err = state
+            .set_key_binding(&FIRST, 9, KeyBinding::Disabled)
+            .unwrap_err();
+        assert_eq!(
+            serde_json::to_value(err).unwrap(),
+            "key index 9 does not exist, indexes run from 0 (key1) to 7 (key8)"
+        );
+    }
+}
@@ -0,0 +1,398 @@
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/tauri";
+
+// Mirrors the serde DTOs returned by the commands in src-tauri/src/commands.rs.
+
+// An index, "serial:…", "port:…", "name:…", or a bare nickname / serial number / port path.
+export type DeviceSelector = number | string;
+
+export interface DeviceSummary {
+	index: number;
+	product: string | null;
+	serial_number: string | null;
+	port_path: string;
+	nickname: string | null;
+}
+
+// A selector that keeps pointing at the same keypad when others come and go.
+export const selectorFor = (device: DeviceSummary): DeviceSelector =>
+	device.serial_number !== null
+		? `serial:${device.serial_number}`
+		: `port:${device.port_path}`;
+
+export interface FirmwareInfo {
+	major: number;
+	minor: number;
+	patch: number;
+	build: number;
+	protocol_version: number;
+}
+
+export type UsbSpeed =
+	| "unknown"
+	| "low"
+	| "full"
+	| "high"
+	| "super"
+	| "super_plus";
+
+export interface DeviceInfo {
+	manufacturer: string | null;
+	product: string | null;
+	serial_number: string | null;
+	language: number | null;
+	active_configuration: number;
+	bus_number: number;
+	address: number;
+	port_path: string;
+	speed: UsbSpeed;
+	device_version: string;
+	firmware: FirmwareInfo;
+}
+
+export type Rgb = [number, number, number];
+
+export type LightingMode = "off" | "static" | "breathing" | "wave" | "reactive";
+
+export type Direction = "left_to_right" | "right_to_left";
+
+export interface LightingSettings {
+	mode: LightingMode;
+	speed: number;
+	direction: Direction;
+	brightness: number;
+}
+
+export interface LightingState {
+	slot: number;
+	settings: LightingSettings;
+	colors: Rgb[];
+}
+
+// Modifier and mouse button fields are bitmasks, usages are HID usage IDs.
+export type KeyBinding =
+	| { type: "disabled" }
+	| { type: "keyboard"; modifiers: number; usage: number }
+	| { type: "consumer"; usage: number }
+	| { type: "system"; usage: number }
+	| { type: "mouse"; buttons: number }
+	| { type: "macro"; slot: number };
+
+export type MacroEvent =
+	| { type: "key_down"; usage: number }
+	| { type: "key_up"; usage: number }
+	| { type: "delay"; ms: number };
+
+export interface Macro {
+	repeat: number;
+	events: MacroEvent[];
+}
+
+export interface Config {
+	slot: number;
+	keys: KeyBinding[];
+	lighting: LightingSettings;
+	colors: Rgb[];
+}
+
+export interface DeviceLocation {
+	bus_number: number;
+	address: number;
+	port_path: string;
+}
+
+export type HotplugEvent =
+	| { kind: "device_added"; device: DeviceLocation }
+	| { kind: "device_removed"; device: DeviceLocation };
+
+// The keyboard layout the computer is set to, for typing text.
+export type Layout = "us" | "uk" | "de" | "fr" | "dvorak";
+
+// Run on the computer, not the keypad.
+export type Action =
+	| { type: "shell"; command: string }
+	| { type: "url"; url: string }
+	| { type: "text"; text: string; layout: Layout; unicode_input: boolean }
+	| { type: "combo"; combo: KeyBinding }
+	| { type: "profile"; slot: number };
+
+export interface KeyActions {
+	press: Action | null;
+	release: Action | null;
+	long_press: Action | null;
+	hold_ms: number;
+}
+
+export interface Profile {
+	keys: KeyBinding[];
+	lighting: LightingSettings;
+	colors: Rgb[];
+	// Macros the keys play, by macro slot.
+	macros: Record<number, Macro>;
+	actions: KeyActions[];
+}
+
+export interface MigrationReport {
+	from_version: number;
+	to_version: number;
+	changes: string[];
+	// Where the original file was copied to.
+	backup: string | null;
+}
+
+export interface ImportedProfile {
+	profile: Profile;
+	// Set when the file was written for an older schema and upgraded while reading it. The
+	// file itself is left alone; see upgradeProfile.
+	migration: MigrationReport | null;
+}
+
+// One way a keypad's profile differs from a profile file. `key` is 0-based, and `name` is
+// how profile files refer to the key, "key1" to "key8".
+export type Difference =
+	| {
+			kind: "key";
+			key: number;
+			name: string;
+			expected: KeyBinding;
+			actual: KeyBinding;
+	  }
+	| {
+			kind: "lighting";
+			expected: LightingSettings;
+			actual: LightingSettings;
+	  }
+	| { kind: "color"; key: number; name: string; expected: Rgb; actual: Rgb }
+	| {
+			kind: "macro";
+			slot: number;
+			expected: Macro | null;
+			actual: Macro | null;
+	  };
+
+export interface ProfileDiff {
+	differences: Difference[];
+}
+
+export interface ProfileSlot {
+	slot: number;
+	active: boolean;
+}
+
+export interface ProfileChanged {
+	// Serial number, or port path for keypads without one.
+	device: string;
+	slot: number;
+}
+
+export interface DeviceKeyEvent {
+	// Serial number, or port path for keypads without one.
+	device: string;
+	key_index: number;
+	pressed: boolean;
+	// Milliseconds since the Unix epoch.
+	timestamp: number;
+}
+
+export type EffectConfig =
+	| { effect: "rainbow"; period_ms: number }
+	| { effect: "chase"; color: Rgb; background: Rgb; step_ms: number };
+
+export const listDevices = () => invoke<DeviceSummary[]>("list_devices");
+
+export const getDeviceInfo = (device: DeviceSelector) =>
+	invoke<DeviceInfo>("get_device_info", { device });
+
+export const readConfig = (device: DeviceSelector) =>
+	invoke<Config>("read_config", { device });
+
+export const writeConfig = (device: DeviceSelector, config: Config) =>
+	invoke<void>("write_config", { device, config });
+
+export const setKeyBinding = (
+	device: DeviceSelector,
+	key: number,
+	binding: KeyBinding,
+) => invoke<void>("set_key_binding", { device, key, binding });
+
+export const setKeyMap = (device: DeviceSelector, keys: KeyBinding[]) =>
+	invoke<void>("set_key_map", { device, keys });
+
+// Converts between bindings and combos like "Ctrl+Shift+F13", "Media.VolumeUp" or
+// "Mouse.Left". Parsing ignores case, accepts aliases like "Cmd" and "Esc", and rejects
+// unknown keys with an error suggesting close matches.
+export const parseBinding = (text: string) =>
+	invoke<KeyBinding>("parse_binding", { text });
+
+export const formatBinding = (binding: KeyBinding) =>
+	invoke<string>("format_binding", { binding });
+
+export const listProfiles = (device: DeviceSelector) =>
+	invoke<ProfileSlot[]>("list_profiles", { device });
+
+export const readProfile = (device: DeviceSelector, slot: number) =>
+	invoke<Profile>("read_profile", { device, slot });
+
+export const writeProfile = (
+	device: DeviceSelector,
+	slot: number,
+	profile: Profile,
+) => invoke<void>("write_profile", { device, slot, profile });
+
+// Reads a profile file and writes it to profile `slot`, upgrading old files first.
+export const importProfile = (
+	device: DeviceSelector,
+	slot: number,
+	path: string,
+) => invoke<ImportedProfile>("import_profile", { device, slot, path });
+
+// Upgrades a profile file from an older version in place, keeping a backup of the original.
+// Resolves to null if the file was already current.
+export const upgradeProfile = (path: string) =>
+	invoke<MigrationReport | null>("upgrade_profile", { path });
+
+export const exportProfile = (
+	device: DeviceSelector,
+	slot: number,
+	path: string,
+) => invoke<void>("export_profile", { device, slot, path });
+
+// How profile `slot` (the active one if null) differs from a profile file.
+export const diffProfile = (
+	device: DeviceSelector,
+	slot: number | null,
+	path: string,
+) => invoke<ProfileDiff>("diff_profile", { device, slot, path });
+
+// Saves the keypad's whole configuration memory to a binary dump file.
+export const dumpDevice = (device: DeviceSelector, path: string) =>
+	invoke<void>("dump_device", { device, path });
+
+// Writes a dump back, after checking its CRC and that the firmware is compatible.
+export const restoreDevice = (device: DeviceSelector, path: string) =>
+	invoke<void>("restore_device", { device, path });
+
+export const setActiveProfile = (device: DeviceSelector, slot: number) =>
+	invoke<void>("set_active_profile", { device, slot });
+
+export const uploadMacro = (
+	device: DeviceSelector,
+	slot: number,
+	definition: Macro,
+) => invoke<void>("upload_macro", { device, slot, definition });
+
+export const downloadMacro = (device: DeviceSelector, slot: number) =>
+	invoke<Macro | null>("download_macro", { device, slot });
+
+export const setNickname = (
+	device: DeviceSelector,
+	nickname: string | null,
+) =>
+	invoke<void>("set_nickname", { device, nickname });
+
+export const setLighting = (
+	device: DeviceSelector,
+	lighting: LightingSettings,
+) =>
+	invoke<void>("set_lighting", { device, lighting });
+
+export const getLighting = (device: DeviceSelector) =>
+	invoke<LightingState>("get_lighting", { device });
+
+export const setEffect = (
+	device: DeviceSelector,
+	mode: LightingMode,
+	speed: number,
+	direction: Direction,
+) => invoke<void>("set_effect", { device, mode, speed, direction });
+
+export const setBrightness = (device: DeviceSelector, brightness: number) =>
+	invoke<void>("set_brightness", { device, brightness });
+
+export const setKeyColor = (device: DeviceSelector, key: number, color: Rgb) =>
+	invoke<void>("set_key_color", { device, key, color });
+
+export const setKeyColors = (device: DeviceSelector, colors: Rgb[]) =>
+	invoke<void>("set_key_colors", { device, colors });
+
+// Streams a host-side animation to the LEDs at `fps` frames per second (1 to 60).
+export const startAnimation = (
+	device: DeviceSelector,
+	effect: EffectConfig,
+	fps: number,
+) => invoke<void>("start_animation", { device, effect, fps });
+
+export const stopAnimation = (device: DeviceSelector) =>
+	invoke<void>("stop_animation", { device });
+
+// Starts and stops sending the keypad's key presses as "key-event" events; see onKeyEvent.
+export const watchKeys = (device: DeviceSelector) =>
+	invoke<void>("watch_keys", { device });
+
+export const unwatchKeys = (device: DeviceSelector) =>
+	invoke<void>("unwatch_keys", { device });
+
+// Calls `handler` whenever a keypad is plugged in or unplugged.
+export async function onHotplug(
+	handler: (event: HotplugEvent) => void,
+): Promise<UnlistenFn> {
+	const unlisten = await Promise.all(
+		["device-added", "device-removed"].map((name) =>
+			listen<HotplugEvent>(name, (event) => handler(event.payload)),
+		),
+	);
+	return () => unlisten.forEach((f) => f());
+}
+
+// Calls `handler` when a keypad's hardware button switches profiles. Keypads are only polled
+// for this while someone is listening.
+export async function onProfileChanged(
+	handler: (event: ProfileChanged) => void,
+): Promise<UnlistenFn> {
+	const unlisten = await listen<ProfileChanged>("profile-changed", (event) =>
+		handler(event.payload),
+	);
+	await invoke<void>("watch_profiles");
+	return () => {
+		unlisten();
+		invoke<void>("unwatch_profiles");
+	};
+}
+
+// Calls `handler` for each key press and release on keypads passed to watchKeys.
+export const onKeyEvent = (handler: (event: DeviceKeyEvent) => void) =>
+	listen<DeviceKeyEvent>("key-event", (event) => handler(event.payload));
+
+const MODIFIERS = [
+	"LCtrl",
+	"LShift",
+	"LAlt",
+	"LGui",
+	"RCtrl",
+	"RShift",
+	"RAlt",
+	"RGui",
+];
+const hex = (n: number) => "0x" + n.toString(16).padStart(2, "0");
+
+// A short human-readable summary of a binding.
+export function describeBinding(binding: KeyBinding): string {
+	switch (binding.type) {
+		case "disabled":
+			return "Disabled";
+		case "keyboard": {
+			const mods = MODIFIERS.filter(
+				(_, bit) => binding.modifiers & (1 << bit),
+			);
+			return [...mods, `Key ${hex(binding.usage)}`].join("+");
+		}
+		case "consumer":
+			return `Media ${hex(binding.usage)}`;
+		case "system":
+			return `System ${hex(binding.usage)}`;
+		case "mouse":
+			return `Mouse buttons ${hex(binding.buttons)}`;
+		case "macro":
+			return `Macro ${binding.slot + 1}`;
+	}
+}